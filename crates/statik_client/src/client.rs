@@ -0,0 +1,487 @@
+use std::{
+    io::{self, ErrorKind},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use statik_core::prelude::*;
+use statik_proto::{
+    c2s::{
+        handshake::C2SHandshake,
+        login::{C2SLoginPluginResponse, C2SLoginStart},
+        status::{C2SPing, C2SStatusRequest},
+    },
+    s2c::{login::GameProfile, status::response::StatusResponse, S2CLoginPacket, S2CStatusPacket},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+    net::TcpStream,
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// How the server responded to a login attempt.
+#[derive(Debug)]
+pub enum LoginResult {
+    /// The player joined, and the connection is now in the play state.
+    Success(GameProfile),
+    /// The server disconnected the player before they could join.
+    Disconnected(Chat),
+}
+
+/// A connection to a minecraft server.
+///
+/// The connection starts in the handshake state - call
+/// [`handshake`](Client::handshake) before anything else.
+#[derive(Debug)]
+pub struct Client {
+    stream: BufWriter<TcpStream>,
+
+    /// The buffer for reading frames.
+    buffer: BytesMut,
+
+    /// Buffer packets are encoded into before being sent.
+    write_buffer: BytesMut,
+
+    /// Splits the bytes read into frames, and writes packets.
+    codec: PacketCodec,
+
+    /// The hostname sent in the handshake.
+    host: String,
+
+    /// The port sent in the handshake.
+    port: u16,
+
+    /// The protocol version sent in the handshake. Defaults to statik's own.
+    protocol_version: i32,
+
+    state: State,
+}
+
+impl Client {
+    /// Connects to the server at `host:port`.
+    pub async fn connect(host: &str, port: u16) -> Result<Self> {
+        let stream = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("failed to connect to {host}:{port}"))?;
+
+        Ok(Self {
+            stream: BufWriter::new(stream),
+            buffer: BytesMut::with_capacity(4096),
+            write_buffer: BytesMut::with_capacity(4096),
+            codec: PacketCodec::default(),
+            host: host.to_string(),
+            port,
+            protocol_version: PROTOCOL_VERSION as i32,
+            state: State::Handshake,
+        })
+    }
+
+    /// Sets the protocol version sent in the handshake.
+    pub fn with_protocol_version(mut self, protocol_version: i32) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
+    /// The state the connection is currently in.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Sends the handshake, moving the connection into the state for `intent`.
+    pub async fn handshake(&mut self, intent: HandshakeIntent) -> Result<()> {
+        ensure!(
+            self.state == State::Handshake,
+            "can't handshake in the {:?} state",
+            self.state
+        );
+
+        self.write_packet(C2SHandshake {
+            protocol_version: VarInt(self.protocol_version),
+            server_address: self.host.clone(),
+            server_port: self.port,
+            next_state: intent,
+        })
+        .await?;
+
+        self.state = intent.next_state();
+
+        Ok(())
+    }
+
+    /// Requests the server's status. The connection must be in the status
+    /// state.
+    pub async fn status(&mut self) -> Result<StatusResponse> {
+        ensure!(
+            self.state == State::Status,
+            "can't request the status in the {:?} state",
+            self.state
+        );
+
+        self.write_packet(C2SStatusRequest {}).await?;
+
+        match S2CStatusPacket::decode(self.read_frame().await?.reader())? {
+            S2CStatusPacket::StatusResponse(response) => Ok(response.json_response),
+            packet => bail!("expected a status response, got {packet:?}"),
+        }
+    }
+
+    /// Pings the server, returning how long it took to respond. The
+    /// connection must be in the status state.
+    ///
+    /// Note: the notchian server closes the connection after responding to a
+    /// ping, so this should be the last thing done with this client.
+    pub async fn ping(&mut self) -> Result<Duration> {
+        ensure!(
+            self.state == State::Status,
+            "can't ping in the {:?} state",
+            self.state
+        );
+
+        // Like the notchian client, use the current time as the payload.
+        let payload = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+
+        let start = Instant::now();
+
+        self.write_packet(C2SPing { payload }).await?;
+
+        match S2CStatusPacket::decode(self.read_frame().await?.reader())? {
+            S2CStatusPacket::Pong(pong) => {
+                let latency = start.elapsed();
+
+                ensure!(
+                    pong.payload == payload,
+                    "server responded with the wrong ping payload ({} instead of {payload})",
+                    pong.payload
+                );
+
+                Ok(latency)
+            }
+            packet => bail!("expected a pong, got {packet:?}"),
+        }
+    }
+
+    /// Logs in as `username`, without authenticating with Mojang - so the
+    /// server must be in offline mode. The connection must be in the login
+    /// state.
+    ///
+    /// Any login plugin requests (e.g. from a proxy) are answered as not
+    /// understood.
+    pub async fn login_offline(&mut self, username: &str) -> Result<LoginResult> {
+        ensure!(
+            self.state == State::Login,
+            "can't log in in the {:?} state",
+            self.state
+        );
+
+        self.write_packet(C2SLoginStart {
+            username: username.to_string(),
+            uuid: None,
+        })
+        .await?;
+
+        loop {
+            match S2CLoginPacket::decode(self.read_frame().await?.reader())? {
+                S2CLoginPacket::Disconnect(disconnect) => {
+                    return Ok(LoginResult::Disconnected(disconnect.reason))
+                }
+                S2CLoginPacket::EncryptionRequest(_) => {
+                    bail!("the server is in online mode, so can't be joined without an account")
+                }
+                S2CLoginPacket::LoginSuccess(success) => {
+                    self.state = State::Play;
+
+                    return Ok(LoginResult::Success(GameProfile {
+                        id: success.uuid,
+                        name: success.username,
+                        properties: success.properties,
+                    }));
+                }
+                S2CLoginPacket::SetCompression(set_compression) => {
+                    let threshold = set_compression.threshold.0;
+
+                    self.codec
+                        .set_compression((threshold >= 0).then_some(threshold as usize));
+                }
+                S2CLoginPacket::LoginPluginRequest(request) => {
+                    self.write_packet(C2SLoginPluginResponse {
+                        message_id: request.message_id,
+                        data: None,
+                    })
+                    .await?;
+                }
+            }
+        }
+    }
+
+    /// Reads a single frame (a packet id followed by its data) from the
+    /// server, waiting until all of it has arrived.
+    pub async fn read_frame(&mut self) -> Result<Bytes> {
+        loop {
+            if let Some(frame) = self.codec.decode(&mut self.buffer)? {
+                return Ok(frame.freeze());
+            }
+
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    /// Writes a single packet to the server.
+    pub async fn write_packet(&mut self, packet: impl Packet) -> Result<()> {
+        self.codec.encode(packet, &mut self.write_buffer)?;
+
+        self.stream.write_all(&self.write_buffer).await?;
+        self.stream.flush().await?;
+
+        self.write_buffer.clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use statik_proto::{
+        c2s::{C2SHandshakePacket, C2SLoginPacket, C2SStatusPacket},
+        s2c::{
+            login::{S2CDisconnect, S2CLoginPluginRequest, S2CLoginSuccess, S2CSetCompression},
+            status::{
+                response::{Players, Version},
+                S2CPong, S2CStatusResponse,
+            },
+        },
+    };
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// The server's side of a connection from a [`Client`].
+    struct FakeServer {
+        stream: TcpStream,
+        buffer: BytesMut,
+        codec: PacketCodec,
+    }
+
+    impl FakeServer {
+        /// Listens on a free port, returning it and a future which accepts a
+        /// single connection, checking its handshake has `intent`.
+        async fn listen(intent: HandshakeIntent) -> (u16, impl Future<Output = Self>) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            let accept = async move {
+                let (stream, _) = listener.accept().await.unwrap();
+
+                let mut server = Self {
+                    stream,
+                    buffer: BytesMut::new(),
+                    codec: PacketCodec::default(),
+                };
+
+                let C2SHandshakePacket::Handshake(handshake) = server.read().await;
+
+                assert_eq!(handshake.protocol_version.0, PROTOCOL_VERSION as i32);
+                assert_eq!(handshake.server_address, "127.0.0.1");
+                assert_eq!(handshake.server_port, port);
+                assert_eq!(handshake.next_state, intent);
+
+                server
+            };
+
+            (port, accept)
+        }
+
+        async fn read<P: Decode>(&mut self) -> P {
+            loop {
+                if let Some(frame) = self.codec.decode(&mut self.buffer).unwrap() {
+                    return P::decode(frame.reader()).unwrap();
+                }
+
+                assert_ne!(self.stream.read_buf(&mut self.buffer).await.unwrap(), 0);
+            }
+        }
+
+        async fn write(&mut self, packet: impl Packet) {
+            let mut buffer = BytesMut::new();
+            self.codec.encode(packet, &mut buffer).unwrap();
+
+            self.stream.write_all(&buffer).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn status_and_ping() {
+        let (port, accept) = FakeServer::listen(HandshakeIntent::Status).await;
+
+        let server = tokio::spawn(async move {
+            let mut server = accept.await;
+
+            let C2SStatusPacket::StatusRequest(_) = server.read().await else {
+                panic!("expected a status request");
+            };
+
+            // Proxies which aren't ready yet report a protocol of -1.
+            let json_response = StatusResponse::new(
+                Players::new(20, 3, Vec::new()),
+                Chat::new("A Minecraft Server"),
+                None,
+                false,
+            )
+            .with_version(Version::new("Starting", -1));
+
+            server.write(S2CStatusResponse { json_response }).await;
+
+            let C2SStatusPacket::Ping(ping) = server.read().await else {
+                panic!("expected a ping");
+            };
+
+            server
+                .write(S2CPong {
+                    payload: ping.payload,
+                })
+                .await;
+        });
+
+        let (status, _) = crate::status("127.0.0.1", port).await.unwrap();
+
+        assert_eq!(status.version().name(), "Starting");
+        assert_eq!(status.version().protocol(), -1);
+        assert_eq!(status.players().online(), 3);
+        assert_eq!(status.players().max(), 20);
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn wrong_pong_payload() {
+        let (port, accept) = FakeServer::listen(HandshakeIntent::Status).await;
+
+        let server = tokio::spawn(async move {
+            let mut server = accept.await;
+
+            let C2SStatusPacket::Ping(ping) = server.read().await else {
+                panic!("expected a ping");
+            };
+
+            server
+                .write(S2CPong {
+                    payload: ping.payload + 1,
+                })
+                .await;
+        });
+
+        let mut client = Client::connect("127.0.0.1", port).await.unwrap();
+        client.handshake(HandshakeIntent::Status).await.unwrap();
+
+        assert!(client.ping().await.is_err());
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn logs_in_offline() {
+        let (port, accept) = FakeServer::listen(HandshakeIntent::Login).await;
+
+        let server = tokio::spawn(async move {
+            let mut server = accept.await;
+
+            let C2SLoginPacket::LoginStart(login_start) = server.read().await else {
+                panic!("expected a login start");
+            };
+            assert_eq!(login_start.username, "statik");
+
+            server
+                .write(S2CLoginPluginRequest {
+                    message_id: VarInt(7),
+                    channel: "velocity:player_info".to_string(),
+                    data: RawBytes::new(vec![1]),
+                })
+                .await;
+
+            let C2SLoginPacket::LoginPluginResponse(response) = server.read().await else {
+                panic!("expected a login plugin response");
+            };
+            assert_eq!(response.message_id.0, 7);
+            assert!(response.data.is_none());
+
+            server
+                .write(S2CSetCompression {
+                    threshold: VarInt(0),
+                })
+                .await;
+            server.codec.set_compression(Some(0));
+
+            server
+                .write(S2CLoginSuccess {
+                    uuid: Uuid::nil(),
+                    username: "statik".to_string(),
+                    properties: Vec::new(),
+                })
+                .await;
+        });
+
+        let mut client = Client::connect("127.0.0.1", port).await.unwrap();
+        client.handshake(HandshakeIntent::Login).await.unwrap();
+
+        let LoginResult::Success(profile) = client.login_offline("statik").await.unwrap() else {
+            panic!("expected to log in");
+        };
+
+        assert_eq!(profile.id, Uuid::nil());
+        assert_eq!(profile.name, "statik");
+        assert_eq!(client.state(), State::Play);
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn disconnected_while_logging_in() {
+        let (port, accept) = FakeServer::listen(HandshakeIntent::Login).await;
+
+        let server = tokio::spawn(async move {
+            let mut server = accept.await;
+
+            let C2SLoginPacket::LoginStart(_) = server.read().await else {
+                panic!("expected a login start");
+            };
+
+            server
+                .write(S2CDisconnect {
+                    reason: Chat::new("The server is starting"),
+                })
+                .await;
+        });
+
+        let mut client = Client::connect("127.0.0.1", port).await.unwrap();
+        client.handshake(HandshakeIntent::Login).await.unwrap();
+
+        assert!(matches!(
+            client.login_offline("statik").await.unwrap(),
+            LoginResult::Disconnected(_)
+        ));
+        assert_eq!(client.state(), State::Login);
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn checks_state() {
+        let (port, _accept) = FakeServer::listen(HandshakeIntent::Status).await;
+
+        let mut client = Client::connect("127.0.0.1", port).await.unwrap();
+
+        assert!(client.status().await.is_err());
+        assert!(client.login_offline("statik").await.is_err());
+
+        client.handshake(HandshakeIntent::Status).await.unwrap();
+
+        assert!(client.handshake(HandshakeIntent::Status).await.is_err());
+        assert!(client.login_offline("statik").await.is_err());
+    }
+}
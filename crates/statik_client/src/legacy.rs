@@ -0,0 +1,241 @@
+//! The server list ping used by clients before 1.7, which most servers still
+//! respond to.
+//!
+//! See [Server List Ping#1.6](https://wiki.vg/Server_List_Ping#1.6).
+
+use std::time::{Duration, Instant};
+
+use statik_core::prelude::*;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// The protocol version of 1.6.4, the last version to use the legacy ping.
+const LEGACY_PROTOCOL_VERSION: u8 = 78;
+
+/// The status of a server, as returned by a legacy ping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyStatus {
+    /// Not sent by servers older than 1.4.
+    pub protocol_version: Option<i32>,
+    /// Not sent by servers older than 1.4.
+    pub version: Option<String>,
+    pub motd: String,
+    pub online: i32,
+    pub max: i32,
+    /// How long the server took to respond.
+    pub latency: Duration,
+}
+
+/// Pings the server at `host:port` the way 1.6 clients do.
+pub async fn legacy_ping(host: &str, port: u16) -> Result<LegacyStatus> {
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("failed to connect to {host}:{port}"))?;
+
+    let start = Instant::now();
+
+    stream.write_all(&ping_request(host, port)).await?;
+
+    let id = stream.read_u8().await?;
+    ensure!(
+        id == 0xff,
+        "expected a kick packet (0xff) in response to a legacy ping, got {id:#04x}"
+    );
+
+    let length = stream.read_u16().await?;
+    let mut chars = Vec::with_capacity(length as usize);
+
+    for _ in 0..length {
+        chars.push(stream.read_u16().await?);
+    }
+
+    let latency = start.elapsed();
+
+    parse_response(&String::from_utf16(&chars)?, latency)
+}
+
+/// Builds the request a 1.6 client sends to ping `host:port`: a server list
+/// ping (0xfe 0x01), followed by an "MC|PingHost" plugin message (0xfa).
+fn ping_request(host: &str, port: u16) -> Vec<u8> {
+    let channel = utf16_be("MC|PingHost");
+    let hostname = utf16_be(host);
+
+    let mut request = vec![0xfe, 0x01, 0xfa];
+    request.extend_from_slice(&(channel.len() as u16 / 2).to_be_bytes());
+    request.extend_from_slice(&channel);
+    request.extend_from_slice(&(7 + hostname.len() as u16).to_be_bytes());
+    request.push(LEGACY_PROTOCOL_VERSION);
+    request.extend_from_slice(&(hostname.len() as u16 / 2).to_be_bytes());
+    request.extend_from_slice(&hostname);
+    request.extend_from_slice(&(port as i32).to_be_bytes());
+
+    request
+}
+
+fn parse_response(response: &str, latency: Duration) -> Result<LegacyStatus> {
+    let parse_count = |count: &str| {
+        count
+            .parse()
+            .with_context(|| format!("invalid player count in legacy ping response: {count:?}"))
+    };
+
+    // 1.4 onwards: §1\0protocol\0version\0motd\0online\0max
+    if let Some(fields) = response.strip_prefix("§1\0") {
+        let fields = fields.split('\0').collect::<Vec<_>>();
+
+        let [protocol_version, version, motd, online, max] = fields[..] else {
+            bail!(
+                "legacy ping response has {} fields instead of 5",
+                fields.len()
+            );
+        };
+
+        return Ok(LegacyStatus {
+            protocol_version: Some(protocol_version.parse().with_context(|| {
+                format!("invalid protocol version in legacy ping response: {protocol_version:?}")
+            })?),
+            version: Some(version.to_string()),
+            motd: motd.to_string(),
+            online: parse_count(online)?,
+            max: parse_count(max)?,
+            latency,
+        });
+    }
+
+    // Beta 1.8 to 1.3: motd§online§max
+    let mut fields = response.rsplitn(3, '§');
+
+    let (Some(max), Some(online), Some(motd)) = (fields.next(), fields.next(), fields.next())
+    else {
+        bail!("invalid legacy ping response: {response:?}");
+    };
+
+    Ok(LegacyStatus {
+        protocol_version: None,
+        version: None,
+        motd: motd.to_string(),
+        online: parse_count(online)?,
+        max: parse_count(max)?,
+        latency,
+    })
+}
+
+fn utf16_be(string: &str) -> Vec<u8> {
+    string.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const LATENCY: Duration = Duration::from_millis(5);
+
+    #[test]
+    fn builds_ping_request() {
+        // The example from wiki.vg, with 1.6.4's protocol version instead.
+        #[rustfmt::skip]
+        let expected = [
+            0xfe, 0x01, 0xfa,
+            0x00, 0x0b,
+            0x00, 0x4d, 0x00, 0x43, 0x00, 0x7c, 0x00, 0x50, 0x00, 0x69, 0x00, 0x6e, 0x00, 0x67,
+            0x00, 0x48, 0x00, 0x6f, 0x00, 0x73, 0x00, 0x74,
+            0x00, 0x19,
+            0x4e,
+            0x00, 0x09,
+            0x00, 0x6c, 0x00, 0x6f, 0x00, 0x63, 0x00, 0x61, 0x00, 0x6c, 0x00, 0x68, 0x00, 0x6f,
+            0x00, 0x73, 0x00, 0x74,
+            0x00, 0x00, 0x63, 0xdd,
+        ];
+
+        assert_eq!(ping_request("localhost", 25565), expected);
+    }
+
+    #[test]
+    fn parses_response() {
+        let status = parse_response(
+            "§1\x00127\x001.6.4\x00A Minecraft Server\x003\x0020",
+            LATENCY,
+        )
+        .unwrap();
+
+        assert_eq!(
+            status,
+            LegacyStatus {
+                protocol_version: Some(127),
+                version: Some("1.6.4".to_string()),
+                motd: "A Minecraft Server".to_string(),
+                online: 3,
+                max: 20,
+                latency: LATENCY,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_pre_1_4_response() {
+        // The motd can itself contain section signs (for colours).
+        let status = parse_response("§aA Minecraft Server§3§20", LATENCY).unwrap();
+
+        assert_eq!(
+            status,
+            LegacyStatus {
+                protocol_version: None,
+                version: None,
+                motd: "§aA Minecraft Server".to_string(),
+                online: 3,
+                max: 20,
+                latency: LATENCY,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_responses() {
+        for response in [
+            "",
+            "A Minecraft Server",
+            "A Minecraft Server§3",
+            "A Minecraft Server§three§20",
+            "§1\x00127\x001.6.4\x00A Minecraft Server\x003",
+            "§1\x00127\x001.6.4\x00A Minecraft Server\x003\x0020\x00extra",
+            "§1\x00latest\x001.6.4\x00A Minecraft Server\x003\x0020",
+            "§1\x00127\x001.6.4\x00A Minecraft Server\x003\x00",
+        ] {
+            assert!(parse_response(response, LATENCY).is_err(), "{response:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn pings_a_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let expected = ping_request("127.0.0.1", port);
+            let mut request = vec![0; expected.len()];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, expected);
+
+            let response = utf16_be("§1\x00127\x001.6.4\x00A Minecraft Server\x003\x0020");
+
+            let mut kick = vec![0xff];
+            kick.extend_from_slice(&(response.len() as u16 / 2).to_be_bytes());
+            kick.extend_from_slice(&response);
+            stream.write_all(&kick).await.unwrap();
+        });
+
+        let status = legacy_ping("127.0.0.1", port).await.unwrap();
+
+        assert_eq!(status.protocol_version, Some(127));
+        assert_eq!(status.motd, "A Minecraft Server");
+        assert_eq!((status.online, status.max), (3, 20));
+
+        server.await.unwrap();
+    }
+}
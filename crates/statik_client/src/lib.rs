@@ -0,0 +1,41 @@
+//! An async minecraft client, for checking the status of (and logging into)
+//! minecraft servers.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let (status, latency) = statik_client::status("localhost", 25565).await?;
+//!
+//! println!(
+//!     "{}/{} players online ({}ms)",
+//!     status.players().online(),
+//!     status.players().max(),
+//!     latency.as_millis()
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod legacy;
+
+use std::time::Duration;
+
+pub use client::{Client, LoginResult};
+pub use legacy::{legacy_ping, LegacyStatus};
+use statik_core::prelude::*;
+use statik_proto::s2c::status::response::StatusResponse;
+
+/// Requests the status of the server at `host:port`, then pings it to measure
+/// the latency to it.
+pub async fn status(host: &str, port: u16) -> Result<(StatusResponse, Duration)> {
+    let mut client = Client::connect(host, port).await?;
+
+    client.handshake(HandshakeIntent::Status).await?;
+
+    let status = client.status().await?;
+    let latency = client.ping().await?;
+
+    Ok((status, latency))
+}
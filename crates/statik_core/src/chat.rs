@@ -2,9 +2,8 @@ use std::borrow::Cow;
 
 use serde::{Deserialize, Serialize};
 
-use crate::prelude::*;
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ChatRepr")]
 pub struct Chat {
     text: Cow<'static, str>,
 }
@@ -13,4 +12,28 @@ impl Chat {
     pub fn new<S: Into<Cow<'static, str>>>(text: S) -> Self {
         Self { text: text.into() }
     }
-}
\ No newline at end of file
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Chat components may also be sent as a plain string, e.g. by older servers
+/// in their status response.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChatRepr {
+    Text(String),
+    Component {
+        #[serde(default)]
+        text: String,
+    },
+}
+
+impl From<ChatRepr> for Chat {
+    fn from(repr: ChatRepr) -> Self {
+        match repr {
+            ChatRepr::Text(text) | ChatRepr::Component { text } => Self::new(text),
+        }
+    }
+}
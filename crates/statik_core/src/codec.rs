@@ -0,0 +1,565 @@
+use std::io::{Cursor, Read, Write};
+
+use aes::{
+    cipher::{
+        generic_array::GenericArray, inout::InOutBuf, BlockDecryptMut, BlockEncryptMut, KeyIvInit,
+    },
+    Aes128,
+};
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::prelude::*;
+
+/// The most bytes a packet's length prefix can take up - as packets can be at
+/// most [`MAX_PACKET_SIZE`] bytes long, the length always fits in 3 bytes.
+const MAX_LENGTH_PREFIX: usize = 3;
+
+/// The largest a compressed packet may be once decompressed, as on the
+/// notchian server.
+pub const MAX_DATA_LENGTH: usize = 8388608;
+
+/// Splits a stream of bytes into frames (a packet id followed by the packet's
+/// data), and writes packets with their length prefix.
+///
+/// Bytes are accumulated until a whole frame has arrived, so packets split
+/// across several reads (or several packets in one read) are handled.
+///
+/// Once compression is enabled (after a Set Compression packet), frames are
+/// read and written in the [compressed format](https://wiki.vg/Protocol#With_compression).
+/// Once encryption is enabled (after an Encryption Response packet), every
+/// byte in both directions is encrypted with [AES/CFB8](https://wiki.vg/Protocol_Encryption).
+#[derive(Debug, Clone)]
+pub struct PacketCodec {
+    /// The longest frame that will be accepted, in bytes.
+    max_packet_size: usize,
+
+    /// Packets at least this many bytes long are compressed. `None` if
+    /// compression hasn't been enabled.
+    compression_threshold: Option<usize>,
+
+    /// `None` until encryption is enabled.
+    cipher: Option<Cipher>,
+
+    /// How many bytes at the start of the read buffer have already been
+    /// decrypted - the rest were read since the last call to `decode`.
+    decrypted: usize,
+
+    /// Reused to compress packets into, before they're copied after their
+    /// length prefix.
+    compressed: Vec<u8>,
+}
+
+/// The AES/CFB8 stream ciphers for each direction, which both use the
+/// shared secret as their key and IV.
+#[derive(Clone)]
+struct Cipher {
+    encryptor: cfb8::Encryptor<Aes128>,
+    decryptor: cfb8::Decryptor<Aes128>,
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Cipher { #HIDDEN: AES/CFB8 STATE# }")
+    }
+}
+
+impl Cipher {
+    fn encrypt(&mut self, bytes: &mut [u8]) {
+        // CFB8 works on one byte blocks, so there's never anything left over.
+        let (blocks, _) = InOutBuf::from(bytes).into_chunks();
+        self.encryptor.encrypt_blocks_inout_mut(blocks);
+    }
+
+    fn decrypt(&mut self, bytes: &mut [u8]) {
+        let (blocks, _) = InOutBuf::from(bytes).into_chunks();
+        self.decryptor.decrypt_blocks_inout_mut(blocks);
+    }
+}
+
+impl PacketCodec {
+    /// Creates a codec which rejects frames longer than `max_packet_size`
+    /// (capped at [`MAX_PACKET_SIZE`]).
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            max_packet_size: max_packet_size.min(MAX_PACKET_SIZE as usize),
+            compression_threshold: None,
+            cipher: None,
+            decrypted: 0,
+            compressed: Vec::new(),
+        }
+    }
+
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    /// Enables (or with `None`, disables) compression for every packet after
+    /// this - should be called straight after sending or receiving a Set
+    /// Compression packet.
+    pub fn set_compression(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Enables encryption with the 16 byte `shared_secret`, for every byte
+    /// read or written after this - should be called straight after sending
+    /// or receiving an Encryption Response packet.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<()> {
+        ensure!(
+            shared_secret.len() == 16,
+            "shared secret is {} bytes long instead of 16",
+            shared_secret.len()
+        );
+
+        let key = GenericArray::from_slice(shared_secret);
+
+        self.cipher = Some(Cipher {
+            encryptor: cfb8::Encryptor::new(key, key),
+            decryptor: cfb8::Decryptor::new(key, key),
+        });
+
+        // Anything still in the read buffer arrived after the client enabled
+        // encryption, so needs decrypting.
+        self.decrypted = 0;
+
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Turns a compressed frame back into a plain one.
+    fn decompress(&self, threshold: usize, frame: BytesMut) -> Result<BytesMut> {
+        let mut buf = Cursor::new(&frame[..]);
+
+        let data_length = VarInt::decode(&mut buf)?.0;
+
+        ensure!(
+            data_length >= 0,
+            "negative packet data length ({data_length})"
+        );
+
+        let data_length = data_length as usize;
+        let compressed = &frame[buf.position() as usize..];
+
+        // Packets below the threshold are sent uncompressed.
+        if data_length == 0 {
+            return Ok(BytesMut::from(compressed));
+        }
+
+        ensure!(
+            data_length >= threshold,
+            "badly compressed packet - size of {data_length} is below the threshold of {threshold}"
+        );
+
+        ensure!(
+            data_length <= MAX_DATA_LENGTH,
+            "badly compressed packet - size of {data_length} is larger than the max of \
+             {MAX_DATA_LENGTH}"
+        );
+
+        let mut data = Vec::with_capacity(data_length);
+
+        // Reading one byte more than expected catches packets which lie
+        // about their length, without decompressing all of a huge one.
+        ZlibDecoder::new(compressed)
+            .take(data_length as u64 + 1)
+            .read_to_end(&mut data)
+            .context("failed to decompress packet")?;
+
+        ensure!(
+            data.len() == data_length,
+            "badly compressed packet - decompressed to {} bytes instead of {data_length}",
+            data.len()
+        );
+
+        Ok(BytesMut::from(&data[..]))
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new(MAX_PACKET_SIZE as usize)
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = BytesMut;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt(&mut src[self.decrypted..]);
+            self.decrypted = src.len();
+        }
+
+        let mut length = 0;
+        let mut prefix = 0;
+
+        // The length is read byte by byte, as it may not have fully arrived
+        // yet.
+        loop {
+            let Some(&byte) = src.get(prefix) else {
+                return Ok(None);
+            };
+
+            length |= ((byte & 0x7f) as usize) << (7 * prefix);
+            prefix += 1;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+
+            ensure!(
+                prefix < MAX_LENGTH_PREFIX,
+                "packet length prefix is longer than {MAX_LENGTH_PREFIX} bytes"
+            );
+        }
+
+        // Checked before anything is allocated for the frame, so a client
+        // can't make the server reserve more than it's willing to accept.
+        ensure!(
+            length <= self.max_packet_size,
+            "packet is {length} bytes long, while the max packet size is {} bytes",
+            self.max_packet_size
+        );
+
+        if src.len() < prefix + length {
+            src.reserve(prefix + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(prefix);
+
+        let frame = src.split_to(length);
+
+        if self.cipher.is_some() {
+            self.decrypted -= prefix + length;
+        }
+
+        match self.compression_threshold {
+            Some(threshold) => Ok(Some(self.decompress(threshold, frame)?)),
+            None => Ok(Some(frame)),
+        }
+    }
+}
+
+impl<P: Packet> Encoder<P> for PacketCodec {
+    type Error = Error;
+
+    /// Appends `packet` to `dst`, encoding it in place - the only copy made is
+    /// when the packet is compressed.
+    ///
+    /// If encoding fails, `dst` is left as it was.
+    fn encode(&mut self, packet: P, dst: &mut BytesMut) -> Result<()> {
+        self.encode_with(dst, |dst| packet.encode(dst.writer()))
+    }
+}
+
+impl PacketCodec {
+    /// Appends an already encoded `frame` (a packet id followed by the
+    /// packet's data) to `dst`, e.g. to send a packet recorded earlier.
+    ///
+    /// If encoding fails, `dst` is left as it was.
+    pub fn encode_frame(&mut self, frame: &[u8], dst: &mut BytesMut) -> Result<()> {
+        self.encode_with(dst, |dst| {
+            dst.extend_from_slice(frame);
+            Ok(())
+        })
+    }
+
+    /// Appends a frame to `dst`, with its contents written by `write`.
+    fn encode_with(
+        &mut self,
+        dst: &mut BytesMut,
+        write: impl FnOnce(&mut BytesMut) -> Result<()>,
+    ) -> Result<()> {
+        let start = dst.len();
+
+        let res = self.encode_frame_at(dst, start, write);
+
+        if res.is_err() {
+            dst.truncate(start);
+        }
+
+        res
+    }
+
+    fn encode_frame_at(
+        &mut self,
+        dst: &mut BytesMut,
+        start: usize,
+        write: impl FnOnce(&mut BytesMut) -> Result<()>,
+    ) -> Result<()> {
+        // The length isn't known until the packet has been encoded, so space
+        // is left for it to be filled in afterwards.
+        dst.put_bytes(0, MAX_LENGTH_PREFIX);
+
+        // Packets below the compression threshold are sent as they are, with
+        // a data length of 0.
+        if self.compression_threshold.is_some() {
+            dst.put_u8(0);
+        }
+
+        let data_start = dst.len();
+
+        write(dst)?;
+
+        if let Some(threshold) = self.compression_threshold {
+            if dst.len() - data_start >= threshold {
+                self.compress(dst, start, data_start)?;
+            }
+        }
+
+        let length = dst.len() - start - MAX_LENGTH_PREFIX;
+
+        ensure!(
+            length < MAX_PACKET_SIZE as usize,
+            "packet is {length} bytes long, which is more than the max of {} bytes",
+            MAX_PACKET_SIZE - 1
+        );
+
+        put_padded_length(&mut dst[start..start + MAX_LENGTH_PREFIX], length);
+
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut dst[start..]);
+        }
+
+        Ok(())
+    }
+
+    /// Compresses the packet data at `data_start` onwards in `dst`, replacing
+    /// the uncompressed data (and the 0 data length before it) of the frame
+    /// starting at `start`.
+    fn compress(&mut self, dst: &mut BytesMut, start: usize, data_start: usize) -> Result<()> {
+        let data_length = dst.len() - data_start;
+
+        self.compressed.clear();
+
+        let mut encoder = ZlibEncoder::new(&mut self.compressed, Compression::default());
+        encoder.write_all(&dst[data_start..])?;
+        encoder.finish()?;
+
+        dst.truncate(start + MAX_LENGTH_PREFIX);
+        VarInt::from(data_length).encode(dst.writer())?;
+        dst.extend_from_slice(&self.compressed);
+
+        Ok(())
+    }
+}
+
+/// Writes `length` into the 3 bytes of `prefix` as a VarInt, padded with
+/// continuation bytes if it would fit in fewer. Notchian clients and servers
+/// read length prefixes of up to 3 bytes, so accept the padding.
+fn put_padded_length(prefix: &mut [u8], length: usize) {
+    prefix[0] = (length & 0x7f) as u8 | 0x80;
+    prefix[1] = (length >> 7 & 0x7f) as u8 | 0x80;
+    prefix[2] = (length >> 14 & 0x7f) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8; 16] = b"0123456789abcdef";
+
+    /// A frame long enough to be compressed, which compresses well.
+    fn big_frame() -> Vec<u8> {
+        let mut frame = vec![0x2a];
+        frame.extend((0..4000).map(|i| (i % 7) as u8));
+        frame
+    }
+
+    fn encode_all(codec: &mut PacketCodec, frames: &[&[u8]]) -> BytesMut {
+        let mut dst = BytesMut::new();
+
+        for frame in frames {
+            codec.encode_frame(frame, &mut dst).unwrap();
+        }
+
+        dst
+    }
+
+    /// Decodes `wire` a chunk at a time, as if each chunk was a separate read.
+    fn decode_in_chunks(codec: &mut PacketCodec, wire: &[u8], chunk: usize) -> Vec<BytesMut> {
+        let mut src = BytesMut::new();
+        let mut frames = vec![];
+
+        for bytes in wire.chunks(chunk) {
+            src.extend_from_slice(bytes);
+
+            while let Some(frame) = codec.decode(&mut src).unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert!(src.is_empty());
+        frames
+    }
+
+    /// Encodes and then decodes a few frames with codecs set up by `setup`,
+    /// with every way of splitting them up into reads.
+    fn round_trip(setup: impl Fn(&mut PacketCodec)) {
+        let big = big_frame();
+        let frames: [&[u8]; 4] = [b"\x00", b"\x01hello", &big, b"\x02world"];
+
+        let mut writer = PacketCodec::default();
+        setup(&mut writer);
+        let wire = encode_all(&mut writer, &frames);
+
+        for chunk in [1, 2, 3, 7, 100, wire.len()] {
+            let mut reader = PacketCodec::default();
+            setup(&mut reader);
+
+            let decoded = decode_in_chunks(&mut reader, &wire, chunk);
+
+            assert_eq!(decoded.len(), frames.len(), "chunks of {chunk}");
+            for (decoded, frame) in decoded.iter().zip(frames) {
+                assert_eq!(&decoded[..], frame, "chunks of {chunk}");
+            }
+        }
+    }
+
+    /// A compressed frame, with `data_length` as its stated uncompressed size.
+    fn compressed_frame(data_length: usize, data: &[u8]) -> BytesMut {
+        let mut compressed = vec![];
+        let mut encoder = ZlibEncoder::new(&mut compressed, Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap();
+
+        let mut body = vec![];
+        VarInt::from(data_length).encode(&mut body).unwrap();
+        body.extend_from_slice(&compressed);
+
+        let mut frame = vec![];
+        VarInt::from(body.len()).encode(&mut frame).unwrap();
+        frame.extend_from_slice(&body);
+
+        BytesMut::from(&frame[..])
+    }
+
+    #[test]
+    fn plain() {
+        round_trip(|_| {});
+    }
+
+    #[test]
+    fn compressed() {
+        round_trip(|codec| codec.set_compression(Some(256)));
+
+        // Only the big frame is compressed.
+        let mut codec = PacketCodec::default();
+        codec.set_compression(Some(256));
+
+        let small = encode_all(&mut codec, &[b"\x01hello"]);
+        assert_eq!(&small[MAX_LENGTH_PREFIX..], b"\x00\x01hello");
+
+        let big = encode_all(&mut codec, &[&big_frame()]);
+        assert!(big.len() < big_frame().len() / 4);
+    }
+
+    #[test]
+    fn encrypted() {
+        round_trip(|codec| codec.enable_encryption(SECRET).unwrap());
+
+        let mut codec = PacketCodec::default();
+        codec.enable_encryption(SECRET).unwrap();
+
+        let wire = encode_all(&mut codec, &[b"\x01hello"]);
+        assert!(!wire.windows(5).any(|window| window == b"hello"));
+    }
+
+    #[test]
+    fn compressed_and_encrypted() {
+        round_trip(|codec| {
+            codec.set_compression(Some(256));
+            codec.enable_encryption(SECRET).unwrap();
+        });
+    }
+
+    #[test]
+    fn encryption_enabled_mid_read() {
+        let mut writer = PacketCodec::default();
+        let mut wire = encode_all(&mut writer, &[b"\x01response"]);
+        writer.enable_encryption(SECRET).unwrap();
+        wire.extend_from_slice(&encode_all(&mut writer, &[b"\x02secret", b"\x03more"]));
+
+        // Everything arrives in one read, so the encrypted frames are already
+        // in the buffer when encryption is enabled.
+        let mut reader = PacketCodec::default();
+        let mut src = wire;
+
+        assert_eq!(
+            &reader.decode(&mut src).unwrap().unwrap()[..],
+            b"\x01response"
+        );
+        reader.enable_encryption(SECRET).unwrap();
+
+        // Half of the next frame, then the rest.
+        let mut rest = src.split_off(4);
+        assert!(reader.decode(&mut src).unwrap().is_none());
+        src.unsplit(rest.split());
+
+        assert_eq!(
+            &reader.decode(&mut src).unwrap().unwrap()[..],
+            b"\x02secret"
+        );
+        assert_eq!(&reader.decode(&mut src).unwrap().unwrap()[..], b"\x03more");
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn rejects_oversize_frames() {
+        let mut codec = PacketCodec::new(64);
+
+        // Rejected from the length alone, before the rest has arrived.
+        let mut src = BytesMut::new();
+        VarInt(65).encode((&mut src).writer()).unwrap();
+        assert!(codec.decode(&mut src).is_err());
+
+        let mut src = BytesMut::from(&[0xff, 0xff, 0xff, 0x01][..]);
+        assert!(PacketCodec::default().decode(&mut src).is_err());
+
+        // Nothing is written for frames too big to send.
+        let mut dst = BytesMut::from(&b"earlier"[..]);
+        let frame = vec![0; MAX_PACKET_SIZE as usize];
+        assert!(PacketCodec::default()
+            .encode_frame(&frame, &mut dst)
+            .is_err());
+        assert_eq!(&dst[..], b"earlier");
+    }
+
+    #[test]
+    fn rejects_undersized_compressed_frames() {
+        let mut codec = PacketCodec::default();
+        codec.set_compression(Some(256));
+
+        let mut src = compressed_frame(100, &[0; 100]);
+        assert!(codec.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn rejects_zlib_bombs() {
+        let mut codec = PacketCodec::default();
+        codec.set_compression(Some(256));
+
+        // States a small size, but decompresses to far more.
+        let mut src = compressed_frame(1000, &vec![0; 4 * 1024 * 1024]);
+        assert!(src.len() < 8 * 1024);
+        assert!(codec.decode(&mut src).is_err());
+
+        // States a size over the max.
+        let mut src = compressed_frame(MAX_DATA_LENGTH + 1, &[0; 1000]);
+        assert!(codec.decode(&mut src).is_err());
+
+        // Decompresses to less than it states.
+        let mut src = compressed_frame(2000, &[0; 1000]);
+        assert!(codec.decode(&mut src).is_err());
+    }
+}
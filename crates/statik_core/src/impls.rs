@@ -82,6 +82,20 @@ impl Encode for i128 {
     }
 }
 
+// floats \\
+
+impl Encode for f32 {
+    fn encode(&self, mut buffer: impl Write) -> Result<()> {
+        Ok(buffer.write_f32::<BigEndian>(*self)?)
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, mut buffer: impl Write) -> Result<()> {
+        Ok(buffer.write_f64::<BigEndian>(*self)?)
+    }
+}
+
 // Miscellaneous \\
 
 impl Encode for String {
@@ -128,7 +142,7 @@ impl<T: Encode> Encode for Option<T> {
 
 impl<'a, B> Encode for Cow<'a, B>
 where
-    B: ToOwned + Encode + ?Sized,
+    B: ToOwned + Encode,
 {
     fn encode(&self, buffer: impl Write) -> Result<()> {
         self.as_ref().encode(buffer)
@@ -153,6 +167,12 @@ impl Encode for State {
     }
 }
 
+impl Encode for HandshakeIntent {
+    fn encode(&self, buffer: impl std::io::Write) -> Result<()> {
+        VarInt(*self as i32).encode(buffer)
+    }
+}
+
 impl Encode for Chat {
     fn encode(&self, buffer: impl std::io::Write) -> Result<()> {
         serde_json::to_string(self)?.encode(buffer)
@@ -233,6 +253,20 @@ impl Decode for i128 {
     }
 }
 
+// floats \\
+
+impl Decode for f32 {
+    fn decode(mut buffer: impl Read) -> Result<Self> {
+        Ok(buffer.read_f32::<BigEndian>()?)
+    }
+}
+
+impl Decode for f64 {
+    fn decode(mut buffer: impl Read) -> Result<Self> {
+        Ok(buffer.read_f64::<BigEndian>()?)
+    }
+}
+
 // Miscellaneous \\
 
 impl Decode for String {
@@ -241,7 +275,9 @@ impl Decode for String {
 
         ensure!(length >= 0, "attempt to decode struct with negative length");
 
-        let mut buf = Vec::with_capacity(length as usize);
+        // As with `Vec`s, the length could be anything, so only reserve what
+        // could fit in a packet.
+        let mut buf = Vec::with_capacity((length as usize).min(MAX_PACKET_SIZE as usize));
 
         for i in 0..length {
             buf.push(buffer.read_u8().context(format!(
@@ -323,6 +359,20 @@ impl Decode for State {
     }
 }
 
+impl Decode for HandshakeIntent {
+    fn decode(buffer: impl std::io::Read) -> Result<Self> {
+        Ok(match VarInt::decode(buffer)?.0 {
+            1 => Self::Status,
+            2 => Self::Login,
+            3 => Self::Transfer,
+            n => bail!(
+                "parsed VarInt returned an invalid handshake intent: {n}. Only values 1,2 and 3 \
+                 are valid."
+            ),
+        })
+    }
+}
+
 impl Decode for Chat {
     fn decode(buffer: impl std::io::Read) -> Result<Self> {
         Ok(serde_json::from_str(&String::decode(buffer)?)?)
@@ -1,7 +1,10 @@
 pub mod chat;
+pub mod codec;
+pub mod impls;
+pub mod nbt;
 pub mod packet;
+pub mod position;
 pub mod raw;
-pub mod impls;
 pub mod state;
 pub mod varint;
 
@@ -11,8 +14,8 @@ pub mod prelude {
     pub use log::{debug, error, info, log, trace, warn};
 
     pub use crate::{
-        chat::*, impls::*, packet::*, raw::*, state::*, varint::*, MINECRAFT_VERSION,
-        PROTOCOL_VERSION,
+        chat::*, codec::*, nbt::*, packet::*, position::*, raw::*, state::*, varint::*,
+        MINECRAFT_VERSION, PROTOCOL_VERSION,
     };
 }
 
@@ -0,0 +1,352 @@
+use std::io::{Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::prelude::*;
+
+/// The deepest level of nesting [`Nbt`] will decode before giving up, to
+/// stop a malicious client overflowing the stack with nested lists.
+const MAX_NBT_DEPTH: usize = 512;
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+/// A single [NBT](https://wiki.vg/NBT) tag.
+///
+/// Note: strings are written as plain UTF-8 rather than java's "modified"
+/// UTF-8, which only differs for null bytes and characters outside the BMP.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+/// An ordered set of named [`Nbt`] tags.
+///
+/// When used as a packet field, a `Compound` is written as a root tag: the
+/// compound tag id, followed by an empty name and then its contents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Compound(pub Vec<(String, Nbt)>);
+
+impl Compound {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a named tag to this compound, returning it for chaining.
+    pub fn with<S: Into<String>, T: Into<Nbt>>(mut self, name: S, value: T) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    /// Adds a named tag to this compound, replacing any tag with the same
+    /// name.
+    pub fn insert<S: Into<String>, T: Into<Nbt>>(&mut self, name: S, value: T) {
+        let name = name.into();
+        let value = value.into();
+
+        match self.0.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((name, value)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Nbt> {
+        self.0.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+impl Nbt {
+    fn tag_id(&self) -> u8 {
+        match self {
+            Nbt::Byte(_) => TAG_BYTE,
+            Nbt::Short(_) => TAG_SHORT,
+            Nbt::Int(_) => TAG_INT,
+            Nbt::Long(_) => TAG_LONG,
+            Nbt::Float(_) => TAG_FLOAT,
+            Nbt::Double(_) => TAG_DOUBLE,
+            Nbt::ByteArray(_) => TAG_BYTE_ARRAY,
+            Nbt::String(_) => TAG_STRING,
+            Nbt::List(_) => TAG_LIST,
+            Nbt::Compound(_) => TAG_COMPOUND,
+            Nbt::IntArray(_) => TAG_INT_ARRAY,
+            Nbt::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    fn encode_payload(&self, buffer: &mut dyn Write) -> Result<()> {
+        match self {
+            Nbt::Byte(n) => buffer.write_i8(*n)?,
+            Nbt::Short(n) => buffer.write_i16::<BigEndian>(*n)?,
+            Nbt::Int(n) => buffer.write_i32::<BigEndian>(*n)?,
+            Nbt::Long(n) => buffer.write_i64::<BigEndian>(*n)?,
+            Nbt::Float(n) => buffer.write_f32::<BigEndian>(*n)?,
+            Nbt::Double(n) => buffer.write_f64::<BigEndian>(*n)?,
+            Nbt::ByteArray(vec) => {
+                write_length(vec.len(), &mut *buffer)?;
+                for n in vec {
+                    buffer.write_i8(*n)?;
+                }
+            }
+            Nbt::String(s) => write_string(s, &mut *buffer)?,
+            Nbt::List(vec) => {
+                let tag_id = vec.first().map_or(TAG_END, Nbt::tag_id);
+
+                ensure!(
+                    vec.iter().all(|tag| tag.tag_id() == tag_id),
+                    "all elements of an nbt list must be the same type"
+                );
+
+                buffer.write_u8(tag_id)?;
+                write_length(vec.len(), &mut *buffer)?;
+                for tag in vec {
+                    tag.encode_payload(buffer)?;
+                }
+            }
+            Nbt::Compound(compound) => compound.encode_payload(buffer)?,
+            Nbt::IntArray(vec) => {
+                write_length(vec.len(), &mut *buffer)?;
+                for n in vec {
+                    buffer.write_i32::<BigEndian>(*n)?;
+                }
+            }
+            Nbt::LongArray(vec) => {
+                write_length(vec.len(), &mut *buffer)?;
+                for n in vec {
+                    buffer.write_i64::<BigEndian>(*n)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_payload(tag_id: u8, buffer: &mut dyn Read, depth: usize) -> Result<Self> {
+        ensure!(
+            depth <= MAX_NBT_DEPTH,
+            "nbt exceeds the maximum depth of {MAX_NBT_DEPTH}"
+        );
+
+        Ok(match tag_id {
+            TAG_BYTE => Nbt::Byte(buffer.read_i8()?),
+            TAG_SHORT => Nbt::Short(buffer.read_i16::<BigEndian>()?),
+            TAG_INT => Nbt::Int(buffer.read_i32::<BigEndian>()?),
+            TAG_LONG => Nbt::Long(buffer.read_i64::<BigEndian>()?),
+            TAG_FLOAT => Nbt::Float(buffer.read_f32::<BigEndian>()?),
+            TAG_DOUBLE => Nbt::Double(buffer.read_f64::<BigEndian>()?),
+            TAG_BYTE_ARRAY => {
+                let length = read_length(&mut *buffer)?;
+                let mut vec = Vec::with_capacity(length.min(MAX_PACKET_SIZE as usize));
+                for _ in 0..length {
+                    vec.push(buffer.read_i8()?);
+                }
+                Nbt::ByteArray(vec)
+            }
+            TAG_STRING => Nbt::String(read_string(&mut *buffer)?),
+            TAG_LIST => {
+                let element_id = buffer.read_u8()?;
+                let length = read_length(&mut *buffer)?;
+
+                ensure!(
+                    element_id != TAG_END || length == 0,
+                    "nbt list of TAG_End must be empty"
+                );
+
+                let mut vec = Vec::with_capacity(length.min(MAX_PACKET_SIZE as usize / 8));
+                for _ in 0..length {
+                    vec.push(Nbt::decode_payload(element_id, buffer, depth + 1)?);
+                }
+                Nbt::List(vec)
+            }
+            TAG_COMPOUND => Nbt::Compound(Compound::decode_payload(buffer, depth + 1)?),
+            TAG_INT_ARRAY => {
+                let length = read_length(&mut *buffer)?;
+                let mut vec = Vec::with_capacity(length.min(MAX_PACKET_SIZE as usize / 4));
+                for _ in 0..length {
+                    vec.push(buffer.read_i32::<BigEndian>()?);
+                }
+                Nbt::IntArray(vec)
+            }
+            TAG_LONG_ARRAY => {
+                let length = read_length(&mut *buffer)?;
+                let mut vec = Vec::with_capacity(length.min(MAX_PACKET_SIZE as usize / 8));
+                for _ in 0..length {
+                    vec.push(buffer.read_i64::<BigEndian>()?);
+                }
+                Nbt::LongArray(vec)
+            }
+            n => bail!("invalid nbt tag id: {n}"),
+        })
+    }
+}
+
+impl Compound {
+    fn encode_payload(&self, buffer: &mut dyn Write) -> Result<()> {
+        for (name, tag) in &self.0 {
+            buffer.write_u8(tag.tag_id())?;
+            write_string(name, &mut *buffer)?;
+            tag.encode_payload(buffer)?;
+        }
+
+        Ok(buffer.write_u8(TAG_END)?)
+    }
+
+    fn decode_payload(buffer: &mut dyn Read, depth: usize) -> Result<Self> {
+        let mut compound = Compound::new();
+
+        loop {
+            let tag_id = buffer.read_u8()?;
+
+            if tag_id == TAG_END {
+                return Ok(compound);
+            }
+
+            let name = read_string(&mut *buffer)?;
+            let tag = Nbt::decode_payload(tag_id, buffer, depth)?;
+
+            compound.0.push((name, tag));
+        }
+    }
+}
+
+impl Encode for Compound {
+    fn encode(&self, mut buffer: impl Write) -> Result<()> {
+        buffer.write_u8(TAG_COMPOUND)?;
+        write_string("", &mut buffer)?;
+        self.encode_payload(&mut buffer)
+    }
+}
+
+impl Decode for Compound {
+    fn decode(mut buffer: impl Read) -> Result<Self> {
+        let tag_id = buffer.read_u8()?;
+
+        ensure!(
+            tag_id == TAG_COMPOUND,
+            "expected a root compound nbt tag (got tag id {tag_id})"
+        );
+
+        // The root tag's name is always empty on the network.
+        read_string(&mut buffer)?;
+
+        Compound::decode_payload(&mut buffer, 0)
+    }
+}
+
+fn write_length(length: usize, mut buffer: impl Write) -> Result<()> {
+    ensure!(
+        length <= i32::MAX as usize,
+        "length of nbt array ({length}) exceeds i32::MAX"
+    );
+    Ok(buffer.write_i32::<BigEndian>(length as i32)?)
+}
+
+fn read_length(mut buffer: impl Read) -> Result<usize> {
+    let length = buffer.read_i32::<BigEndian>()?;
+    ensure!(length >= 0, "attempted to decode nbt with negative length");
+    Ok(length as usize)
+}
+
+fn write_string(s: &str, mut buffer: impl Write) -> Result<()> {
+    ensure!(
+        s.len() <= u16::MAX as usize,
+        "byte length of nbt string ({}) exceeds u16::MAX",
+        s.len()
+    );
+    buffer.write_u16::<BigEndian>(s.len() as u16)?;
+    Ok(buffer.write_all(s.as_bytes())?)
+}
+
+fn read_string(mut buffer: impl Read) -> Result<String> {
+    let length = buffer.read_u16::<BigEndian>()? as usize;
+    let mut buf = vec![0; length];
+    buffer.read_exact(&mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+impl From<i8> for Nbt {
+    fn from(value: i8) -> Self {
+        Nbt::Byte(value)
+    }
+}
+
+impl From<bool> for Nbt {
+    fn from(value: bool) -> Self {
+        Nbt::Byte(value as i8)
+    }
+}
+
+impl From<i16> for Nbt {
+    fn from(value: i16) -> Self {
+        Nbt::Short(value)
+    }
+}
+
+impl From<i32> for Nbt {
+    fn from(value: i32) -> Self {
+        Nbt::Int(value)
+    }
+}
+
+impl From<i64> for Nbt {
+    fn from(value: i64) -> Self {
+        Nbt::Long(value)
+    }
+}
+
+impl From<f32> for Nbt {
+    fn from(value: f32) -> Self {
+        Nbt::Float(value)
+    }
+}
+
+impl From<f64> for Nbt {
+    fn from(value: f64) -> Self {
+        Nbt::Double(value)
+    }
+}
+
+impl From<String> for Nbt {
+    fn from(value: String) -> Self {
+        Nbt::String(value)
+    }
+}
+
+impl From<&str> for Nbt {
+    fn from(value: &str) -> Self {
+        Nbt::String(value.to_string())
+    }
+}
+
+impl From<Compound> for Nbt {
+    fn from(value: Compound) -> Self {
+        Nbt::Compound(value)
+    }
+}
+
+impl From<Vec<Nbt>> for Nbt {
+    fn from(value: Vec<Nbt>) -> Self {
+        Nbt::List(value)
+    }
+}
@@ -0,0 +1,51 @@
+use std::io::{Read, Write};
+
+use crate::prelude::*;
+
+/// A block position, packed into a single 64 bit integer when sent over the
+/// network: x as a 26 bit integer, followed by z as a 26 bit integer,
+/// followed by y as a 12 bit integer.
+///
+/// See [Position](https://wiki.vg/Protocol#Position) for more details.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl Encode for Position {
+    fn encode(&self, buffer: impl Write) -> Result<()> {
+        ensure!(
+            (-(1 << 25)..(1 << 25)).contains(&self.x)
+                && (-(1 << 25)..(1 << 25)).contains(&self.z)
+                && (-(1 << 11)..(1 << 11)).contains(&self.y),
+            "position {self:?} is out of the encodable range"
+        );
+
+        let packed = ((self.x as i64 & 0x3ffffff) << 38)
+            | ((self.z as i64 & 0x3ffffff) << 12)
+            | (self.y as i64 & 0xfff);
+
+        packed.encode(buffer)
+    }
+}
+
+impl Decode for Position {
+    fn decode(buffer: impl Read) -> Result<Self> {
+        let packed = i64::decode(buffer)?;
+
+        // Arithmetic shifts sign extend each of the packed values.
+        Ok(Self {
+            x: (packed >> 38) as i32,
+            y: (packed << 52 >> 52) as i32,
+            z: (packed << 26 >> 38) as i32,
+        })
+    }
+}
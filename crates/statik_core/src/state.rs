@@ -1,9 +1,30 @@
-use crate::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum State {
     Handshake = 0,
     Status = 1,
     Login = 2,
     Play = 3,
-}
\ No newline at end of file
+}
+
+/// What a client wants to do next, as sent at the end of its handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeIntent {
+    Status = 1,
+    Login = 2,
+    /// Logging in after being sent to this server by another server's
+    /// transfer packet. Only sent by 1.20.5+ clients.
+    Transfer = 3,
+}
+
+impl HandshakeIntent {
+    /// The state the connection should switch to after the handshake.
+    pub fn next_state(&self) -> State {
+        match self {
+            HandshakeIntent::Status => State::Status,
+            HandshakeIntent::Login | HandshakeIntent::Transfer => State::Login,
+        }
+    }
+}
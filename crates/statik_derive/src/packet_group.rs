@@ -84,6 +84,19 @@ pub fn derive_packet_group(item: TokenStream) -> Result<TokenStream> {
                 })
                 .collect::<TokenStream>();
 
+            let name_fields = fields
+                .iter()
+                .map(|(packet_name, variant_name)| {
+                    let name = variant_name.to_string();
+                    quote!(#packet_name::ID => Some(#name),)
+                })
+                .collect::<TokenStream>();
+
+            let id_fields = fields
+                .iter()
+                .map(|(packet_name, _)| quote!(#packet_name::ID))
+                .collect::<Vec<_>>();
+
             // let encode_fields = fields
             //     .iter()
             //     .map(|(packet_name, variant_name)| {
@@ -99,6 +112,27 @@ pub fn derive_packet_group(item: TokenStream) -> Result<TokenStream> {
 
                 #from_fields
 
+                impl #ident {
+                    /// Whether a packet with the given id is part of this group.
+                    pub fn has_id(id: i32) -> bool {
+
+                        use ::statik_core::packet::Packet;
+
+                        [#(#id_fields),*].contains(&id)
+                    }
+
+                    /// The name of the packet in this group with the given id.
+                    pub fn name_of(id: i32) -> Option<&'static str> {
+
+                        use ::statik_core::packet::Packet;
+
+                        match id {
+                            #name_fields
+                            _ => None,
+                        }
+                    }
+                }
+
                 impl ::statik_core::packet::Decode for #ident {
 
                     fn decode(mut _buffer: impl ::std::io::Read) -> ::anyhow::Result<Self> {
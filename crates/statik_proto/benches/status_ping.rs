@@ -0,0 +1,111 @@
+//! Compares two ways of writing the packets sent back for a status ping (a
+//! status response, then a pong) to a socket:
+//!
+//! - `copied_per_packet`: how `Connection` used to write packets - each one is
+//!   encoded into its own buffer, copied after its length prefix into a second
+//!   buffer, then written and flushed on its own.
+//! - `encoded_in_place`: how it writes them now - both are encoded straight
+//!   into one reused buffer by the [`PacketCodec`], then written together.
+
+use std::{
+    io::{self, BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use statik_core::prelude::*;
+use statik_proto::s2c::status::{
+    response::{Players, StatusResponse},
+    S2CPong, S2CStatusResponse,
+};
+use tokio_util::codec::Encoder;
+
+/// A connected socket, with everything written to it read and thrown away on
+/// another thread.
+fn loopback() -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut peer, _) = listener.accept().unwrap();
+
+    thread::spawn(move || io::copy(&mut peer, &mut io::sink()));
+
+    stream.set_nodelay(true).unwrap();
+
+    stream
+}
+
+fn status_response() -> S2CStatusResponse {
+    S2CStatusResponse {
+        json_response: StatusResponse::new(
+            Players::new(20, 0, vec![]),
+            Chat::new("A Statik server!"),
+            None,
+            false,
+        ),
+    }
+}
+
+fn copied_per_packet(
+    stream: &mut BufWriter<TcpStream>,
+    queue: &mut Vec<u8>,
+    staging: &mut Vec<u8>,
+    packet: impl Packet,
+) -> Result<()> {
+    packet.encode(&mut *queue)?;
+
+    VarInt(queue.len() as i32).encode(&mut *staging)?;
+    staging.extend_from_slice(queue);
+
+    stream.write_all(staging)?;
+    stream.flush()?;
+
+    queue.clear();
+    staging.clear();
+
+    Ok(())
+}
+
+fn status_ping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("status_ping");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("copied_per_packet", |b| {
+        let mut stream = BufWriter::new(loopback());
+        let mut queue = Vec::with_capacity(4096);
+        let mut staging = Vec::with_capacity(4096);
+
+        b.iter(|| {
+            copied_per_packet(&mut stream, &mut queue, &mut staging, status_response()).unwrap();
+            copied_per_packet(
+                &mut stream,
+                &mut queue,
+                &mut staging,
+                S2CPong { payload: 0 },
+            )
+            .unwrap();
+        })
+    });
+
+    group.bench_function("encoded_in_place", |b| {
+        let mut stream = loopback();
+        let mut codec = PacketCodec::new(4096);
+        let mut write_buffer = BytesMut::with_capacity(4096);
+
+        b.iter(|| {
+            codec.encode(status_response(), &mut write_buffer).unwrap();
+            codec
+                .encode(S2CPong { payload: 0 }, &mut write_buffer)
+                .unwrap();
+
+            stream.write_all(&write_buffer).unwrap();
+            write_buffer.clear();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, status_ping);
+criterion_main!(benches);
@@ -1,23 +1,37 @@
 pub mod handshake;
 pub mod login;
+pub mod play;
 pub mod status;
 
 use handshake::*;
 use login::*;
+use play::*;
 use statik_derive::PacketGroup;
 use status::*;
 
+// Packet ids are only unique within a single state, so each state has its own
+// group to decode into.
+
 #[derive(Debug, PacketGroup)]
-pub enum C2SPacket {
-    //Handshake
+pub enum C2SHandshakePacket {
     Handshake(C2SHandshake),
+}
 
-    //Status
+#[derive(Debug, PacketGroup)]
+pub enum C2SStatusPacket {
     StatusRequest(C2SStatusRequest),
     Ping(C2SPing),
+}
 
-    //Login
+#[derive(Debug, PacketGroup)]
+pub enum C2SLoginPacket {
     LoginStart(C2SLoginStart),
     EncryptionResponse(C2SEncryptionResponse),
     LoginPluginResponse(C2SLoginPluginResponse),
 }
+
+#[derive(Debug, PacketGroup)]
+pub enum C2SPlayPacket {
+    ConfirmTeleportation(C2SConfirmTeleportation),
+    KeepAlive(C2SKeepAlive),
+}
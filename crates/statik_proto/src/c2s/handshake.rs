@@ -14,6 +14,58 @@ pub struct C2SHandshake {
     pub server_address: String,
     ///Default is 25565. The Notchian server does not use this information.
     pub server_port: u16,
-    ///1 for Status, 2 for Login.
-    pub next_state: State,
+    ///1 for Status, 2 for Login, 3 for Transfer.
+    pub next_state: HandshakeIntent,
+}
+
+impl C2SHandshake {
+    /// The hostname the client connected with, without any data a modded
+    /// client or proxy appended to it.
+    pub fn hostname(&self) -> &str {
+        self.server_address.split('\0').next().unwrap_or_default()
+    }
+
+    /// The version of Forge's networking the client supports, if it is a
+    /// modded client.
+    pub fn fml_version(&self) -> Option<FmlVersion> {
+        self.server_address
+            .split('\0')
+            .skip(1)
+            .find_map(FmlVersion::from_marker)
+    }
+}
+
+/// The markers Forge and NeoForge clients append to the server address in the
+/// handshake, after a null byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FmlVersion {
+    /// Forge 1.7 to 1.12.2.
+    Fml1,
+    /// Forge 1.13 to 1.17.1.
+    Fml2,
+    /// Forge 1.18 and above.
+    Fml3,
+    /// NeoForge 1.20.2 and above.
+    NeoForge,
+}
+
+impl FmlVersion {
+    pub fn from_marker(marker: &str) -> Option<Self> {
+        match marker {
+            "FML" => Some(Self::Fml1),
+            "FML2" => Some(Self::Fml2),
+            "FML3" => Some(Self::Fml3),
+            "FORGE" => Some(Self::NeoForge),
+            _ => None,
+        }
+    }
+
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Self::Fml1 => "FML",
+            Self::Fml2 => "FML2",
+            Self::Fml3 => "FML3",
+            Self::NeoForge => "FORGE",
+        }
+    }
 }
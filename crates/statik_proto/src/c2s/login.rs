@@ -16,10 +16,11 @@ pub struct C2SLoginStart {
 #[derive(Debug, Packet)]
 #[packet(id = 0x01, state = State::Login)]
 pub struct C2SEncryptionResponse {
-    /// The server's public key, as bytes.
-    pub public_key: Vec<u8>,
-    /// A sequence of random bytes generated by the server. Always of Length
-    /// 4 when send by a notchian server.
+    /// The 16 byte shared secret generated by the client, encrypted with the
+    /// server's public key.
+    pub shared_secret: Vec<u8>,
+    /// The verify token sent by the server, encrypted with the server's
+    /// public key.
     pub verify_token: Vec<u8>,
 }
 
@@ -0,0 +1,18 @@
+use statik_core::prelude::*;
+use statik_derive::*;
+
+/// Sent by the client to confirm it received a
+/// [`S2CSynchronizePlayerPosition`](crate::s2c::play::S2CSynchronizePlayerPosition).
+#[derive(Debug, Packet)]
+#[packet(id = 0x00, state = State::Play)]
+pub struct C2SConfirmTeleportation {
+    /// The ID given by the synchronize player position packet.
+    pub teleport_id: VarInt,
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x12, state = State::Play)]
+pub struct C2SKeepAlive {
+    /// Should be the same as the id sent by the server.
+    pub keep_alive_id: i64,
+}
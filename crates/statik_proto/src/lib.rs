@@ -4,7 +4,10 @@ pub mod s2c;
 pub mod prelude {
 
     pub use crate::{
-        c2s::{handshake::*, login::*, status::*, C2SPacket},
-        s2c::{login::*, status::*, S2CPacket},
+        c2s::{
+            handshake::*, login::*, play::*, status::*, C2SHandshakePacket, C2SLoginPacket,
+            C2SPlayPacket, C2SStatusPacket,
+        },
+        s2c::{login::*, play::*, status::*, S2CLoginPacket, S2CPlayPacket, S2CStatusPacket},
     };
 }
@@ -1,20 +1,38 @@
 pub mod login;
+pub mod play;
 pub mod status;
 
 use login::*;
+use play::*;
 use statik_derive::PacketGroup;
 use status::*;
 
+// Packet ids are only unique within a single state, so each state has its own
+// group to decode into.
+
 #[derive(Debug, PacketGroup)]
-pub enum S2CPacket {
-    //Status
+pub enum S2CStatusPacket {
     StatusResponse(S2CStatusResponse),
     Pong(S2CPong),
+}
 
-    //Login
+#[derive(Debug, PacketGroup)]
+pub enum S2CLoginPacket {
     Disconnect(S2CDisconnect),
     EncryptionRequest(S2CEncryptionRequest),
     LoginSuccess(S2CLoginSuccess),
     SetCompression(S2CSetCompression),
     LoginPluginRequest(S2CLoginPluginRequest),
 }
+
+#[derive(Debug, PacketGroup)]
+pub enum S2CPlayPacket {
+    Disconnect(S2CPlayDisconnect),
+    KeepAlive(S2CKeepAlive),
+    ChunkDataAndUpdateLight(S2CChunkDataAndUpdateLight),
+    Login(S2CPlayLogin),
+    SynchronizePlayerPosition(S2CSynchronizePlayerPosition),
+    SetActionBarText(S2CSetActionBarText),
+    SetCenterChunk(S2CSetCenterChunk),
+    SetDefaultSpawnPosition(S2CSetDefaultSpawnPosition),
+}
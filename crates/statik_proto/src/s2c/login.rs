@@ -2,7 +2,7 @@ use statik_core::prelude::*;
 use statik_derive::*;
 
 #[derive(Debug, Packet)]
-#[packet(id = 0x00)]
+#[packet(id = 0x00, state = State::Login)]
 pub struct S2CDisconnect {
     /// Why the client was disconnected before login success.
     pub reason: Chat,
@@ -20,6 +20,7 @@ pub struct S2CEncryptionRequest {
     pub verify_token: Vec<u8>,
 }
 
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Packet)]
@@ -27,7 +28,58 @@ use uuid::Uuid;
 pub struct S2CLoginSuccess {
     pub uuid: Uuid,
     pub username: String,
-    // pub properties: Property,
+    /// Profile properties, such as the player's skin and cape (under the
+    /// `textures` property).
+    pub properties: Vec<Property>,
+}
+
+impl From<GameProfile> for S2CLoginSuccess {
+    fn from(profile: GameProfile) -> Self {
+        Self {
+            uuid: profile.id,
+            username: profile.name,
+            properties: profile.properties,
+        }
+    }
+}
+
+/// A single property of a player's profile.
+#[derive(Clone, PartialEq, Eq, Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    /// Base64 encoded, usually json.
+    pub value: String,
+    /// Base64 encoded signature of `value`, signed with Yggdrasil's private
+    /// key. Only present for properties from an authenticated profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A player's profile, as resolved by a session server (or forwarded by a
+/// proxy).
+///
+/// # Examples
+///
+/// A sample profile in json, as returned by Mojang's session server:
+/// ```json
+/// {
+///     "id": "4566e69fc90748ee8d71d7ba5aa00d20",
+///     "name": "thinkofdeath",
+///     "properties": [
+///         {
+///             "name": "textures",
+///             "value": "<base64 string>",
+///             "signature": "<base64 string; signed data>"
+///         }
+///     ]
+/// }
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct GameProfile {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<Property>,
 }
 
 #[derive(Debug, Packet)]
@@ -39,8 +91,11 @@ pub struct S2CSetCompression {
 #[derive(Debug, Packet)]
 #[packet(id = 0x04, state = State::Login)]
 pub struct S2CLoginPluginRequest {
+    /// Generated by the server - should be unique to the connection.
     pub message_id: VarInt,
     /// See: https://wiki.vg/Protocol#Identifier
     pub channel: String,
-    pub data: Vec<u8>,
+    /// Any data, depending on the channel. The length of this array must be
+    /// inferred from the packet length.
+    pub data: RawBytes,
 }
@@ -0,0 +1,145 @@
+use statik_core::prelude::*;
+use statik_derive::*;
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x1a, state = State::Play)]
+pub struct S2CPlayDisconnect {
+    /// Displayed to the client when the connection terminates.
+    pub reason: Chat,
+}
+
+/// The client must respond with the same id within 15 seconds, or the server
+/// should disconnect it.
+#[derive(Debug, Packet)]
+#[packet(id = 0x23, state = State::Play)]
+pub struct S2CKeepAlive {
+    pub keep_alive_id: i64,
+}
+
+/// See [Chunk Format](https://wiki.vg/Chunk_Format) for how `data` is laid out.
+#[derive(Debug, Packet)]
+#[packet(id = 0x24, state = State::Play)]
+pub struct S2CChunkDataAndUpdateLight {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// Compound containing one long array named `MOTION_BLOCKING`, and
+    /// optionally one named `WORLD_SURFACE`.
+    pub heightmaps: Compound,
+    /// Every chunk section from the bottom of the world to the top.
+    pub data: Vec<u8>,
+    pub block_entities: Vec<ChunkBlockEntity>,
+    /// BitSet of which sections (including one below and one above the world)
+    /// have sky light data sent.
+    pub sky_light_mask: Vec<u64>,
+    /// BitSet of which sections have block light data sent.
+    pub block_light_mask: Vec<u64>,
+    /// BitSet of which sections have all zero sky light.
+    pub empty_sky_light_mask: Vec<u64>,
+    /// BitSet of which sections have all zero block light.
+    pub empty_block_light_mask: Vec<u64>,
+    /// One 2048 byte array for each bit set in `sky_light_mask`.
+    pub sky_light_arrays: Vec<Vec<u8>>,
+    /// One 2048 byte array for each bit set in `block_light_mask`.
+    pub block_light_arrays: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ChunkBlockEntity {
+    /// The x and z coordinates relative to the chunk, packed into the high and
+    /// low nibbles.
+    pub packed_xz: u8,
+    pub y: i16,
+    pub kind: VarInt,
+    pub data: Compound,
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x28, state = State::Play)]
+pub struct S2CPlayLogin {
+    /// The player's Entity ID (EID).
+    pub entity_id: i32,
+    pub is_hardcore: bool,
+    /// 0: Survival, 1: Creative, 2: Adventure, 3: Spectator.
+    pub game_mode: u8,
+    /// -1: Undefined (null), or one of the game modes above.
+    pub previous_game_mode: i8,
+    /// Identifiers of all dimensions on the server.
+    pub dimension_names: Vec<String>,
+    /// Represents certain registries that are sent from the server and are
+    /// applied on the client.
+    pub registry_codec: Compound,
+    /// Name of the dimension type being spawned into.
+    pub dimension_type: String,
+    /// Name of the dimension being spawned into.
+    pub dimension_name: String,
+    /// First 8 bytes of the SHA-256 hash of the world's seed.
+    pub hashed_seed: i64,
+    /// Was once used by the client to draw the player list, but now is
+    /// ignored.
+    pub max_players: VarInt,
+    /// Render distance (2-32).
+    pub view_distance: VarInt,
+    /// The distance that the client will process specific things, such as
+    /// entities.
+    pub simulation_distance: VarInt,
+    pub reduced_debug_info: bool,
+    /// Set to false when the doImmediateRespawn gamerule is true.
+    pub enable_respawn_screen: bool,
+    /// True if the world is a debug mode world.
+    pub is_debug: bool,
+    /// True if the world is a superflat world (changes the void fog and
+    /// horizon).
+    pub is_flat: bool,
+    pub death_location: Option<DeathLocation>,
+    /// The number of ticks until the player can use the portal again.
+    pub portal_cooldown: VarInt,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct DeathLocation {
+    pub dimension_name: String,
+    pub location: Position,
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x3c, state = State::Play)]
+pub struct S2CSynchronizePlayerPosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    /// Absolute or relative rotation on the X axis, in degrees.
+    pub yaw: f32,
+    /// Absolute or relative rotation on the Y axis, in degrees.
+    pub pitch: f32,
+    /// Bit field of which of the above values are relative rather than
+    /// absolute.
+    pub flags: u8,
+    /// The client should confirm this packet with a
+    /// [`C2SConfirmTeleportation`](crate::c2s::play::C2SConfirmTeleportation)
+    /// containing the same teleport ID.
+    pub teleport_id: VarInt,
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x46, state = State::Play)]
+pub struct S2CSetActionBarText {
+    pub action_bar_text: Chat,
+}
+
+/// Sets the center position of the client's chunk loading area.
+#[derive(Debug, Packet)]
+#[packet(id = 0x4e, state = State::Play)]
+pub struct S2CSetCenterChunk {
+    pub chunk_x: VarInt,
+    pub chunk_z: VarInt,
+}
+
+/// Sent by the server after login to specify the coordinates of the spawn
+/// point (the point at which players spawn at, and which the compass points
+/// to).
+#[derive(Debug, Packet)]
+#[packet(id = 0x50, state = State::Play)]
+pub struct S2CSetDefaultSpawnPosition {
+    pub location: Position,
+    pub angle: f32,
+}
@@ -0,0 +1,429 @@
+use std::io::Cursor;
+
+use serde::{Deserialize, Serialize};
+use statik_core::prelude::*;
+
+/// The `forgeData` field of a status response, sent by Forge (1.13+) and
+/// NeoForge servers. Modded clients show a red X in the server list if it's
+/// missing, or doesn't match their mods.
+///
+/// Since 1.18.2, Forge sends its mods and channels compressed into the `d`
+/// field rather than listing them, to keep the response small - see
+/// [`ForgeData::compressed`].
+///
+/// # Examples
+///
+/// Sample forgeData field in json:
+/// ```json
+/// {
+///     "channels": [
+///         {
+///             "res": "examplemod:main",
+///             "version": "1",
+///             "required": true
+///         }
+///     ],
+///     "mods": [
+///         {
+///             "modId": "examplemod",
+///             "modmarker": "1.0.0"
+///         }
+///     ],
+///     "fmlNetworkVersion": 3,
+///     "truncated": false
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgeData {
+    channels: Vec<ForgeChannel>,
+    mods: Vec<ForgeMod>,
+    fml_network_version: i32,
+    #[serde(default)]
+    truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    d: Option<String>,
+}
+
+impl ForgeData {
+    /// Lists the mods and channels directly, as Forge did before 1.18.2.
+    pub fn new(fml_network_version: i32, mods: Vec<ForgeMod>, channels: Vec<ForgeChannel>) -> Self {
+        Self {
+            channels,
+            mods,
+            fml_network_version,
+            truncated: false,
+            d: None,
+        }
+    }
+
+    /// Compresses the mods and channels into the `d` field, as Forge does
+    /// from 1.18.2 onwards.
+    pub fn compressed(
+        fml_network_version: i32,
+        mods: Vec<ForgeMod>,
+        channels: Vec<ForgeChannel>,
+    ) -> Result<Self> {
+        Ok(Self {
+            channels: vec![],
+            mods: vec![],
+            fml_network_version,
+            truncated: false,
+            d: Some(encode_optimized(&mods, &channels)?),
+        })
+    }
+
+    pub fn fml_network_version(&self) -> i32 {
+        self.fml_network_version
+    }
+
+    /// Whether the server left some mods or channels out to keep the
+    /// response small.
+    pub fn truncated(&self) -> Result<bool> {
+        match &self.d {
+            Some(d) => Ok(decode_optimized(d)?.2),
+            None => Ok(self.truncated),
+        }
+    }
+
+    /// The server's mods, decompressing them from `d` if needed.
+    pub fn mods(&self) -> Result<Vec<ForgeMod>> {
+        match &self.d {
+            Some(d) => Ok(decode_optimized(d)?.0),
+            None => Ok(self.mods.clone()),
+        }
+    }
+
+    /// The server's network channels, decompressing them from `d` if needed.
+    pub fn channels(&self) -> Result<Vec<ForgeChannel>> {
+        match &self.d {
+            Some(d) => Ok(decode_optimized(d)?.1),
+            None => Ok(self.channels.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeMod {
+    #[serde(rename = "modId")]
+    pub mod_id: String,
+    /// Usually the mod's version.
+    pub modmarker: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeChannel {
+    /// The channel's identifier, e.g. `examplemod:main`.
+    pub res: String,
+    pub version: String,
+    /// Whether clients must also have this channel to join.
+    pub required: bool,
+}
+
+/// The `modinfo` field of a status response, sent by Forge servers on 1.12.2
+/// and below.
+///
+/// # Examples
+///
+/// Sample modinfo field in json:
+/// ```json
+/// {
+///     "type": "FML",
+///     "modList": [
+///         {
+///             "modid": "examplemod",
+///             "version": "1.0.0"
+///         }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModInfo {
+    #[serde(rename = "type")]
+    kind: String,
+    mod_list: Vec<ModInfoEntry>,
+}
+
+impl ModInfo {
+    pub fn new(mod_list: Vec<ModInfoEntry>) -> Self {
+        Self {
+            kind: "FML".to_string(),
+            mod_list,
+        }
+    }
+
+    pub fn mod_list(&self) -> &[ModInfoEntry] {
+        &self.mod_list
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModInfoEntry {
+    pub modid: String,
+    pub version: String,
+}
+
+/// Writes mods and channels in Forge's binary format, then packs the bytes
+/// into a string 15 bits per character (as java strings are UTF-16, any 15 bit
+/// value is a valid character).
+fn encode_optimized(mods: &[ForgeMod], channels: &[ForgeChannel]) -> Result<String> {
+    let mut buf = Vec::new();
+
+    // Never truncated.
+    false.encode(&mut buf)?;
+
+    ensure!(
+        mods.len() <= u16::MAX as usize,
+        "too many forge mods to encode ({})",
+        mods.len()
+    );
+    (mods.len() as u16).encode(&mut buf)?;
+
+    // Channels are grouped under the mod with the same namespace.
+    let namespace = |channel: &ForgeChannel| {
+        channel
+            .res
+            .split_once(':')
+            .map_or("minecraft", |(namespace, _)| namespace)
+            .to_string()
+    };
+
+    for forge_mod in mods {
+        let mod_channels = channels
+            .iter()
+            .filter(|channel| namespace(channel) == forge_mod.mod_id)
+            .collect::<Vec<_>>();
+
+        // The lowest bit marks mods with no version (that are only needed
+        // server side), which isn't needed here.
+        VarInt((mod_channels.len() << 1) as i32).encode(&mut buf)?;
+        forge_mod.mod_id.encode(&mut buf)?;
+        forge_mod.modmarker.encode(&mut buf)?;
+
+        for channel in mod_channels {
+            let path = channel
+                .res
+                .split_once(':')
+                .map_or(channel.res.as_str(), |(_, path)| path);
+
+            path.to_string().encode(&mut buf)?;
+            channel.version.encode(&mut buf)?;
+            channel.required.encode(&mut buf)?;
+        }
+    }
+
+    let other_channels = channels
+        .iter()
+        .filter(|channel| !mods.iter().any(|m| m.mod_id == namespace(channel)))
+        .collect::<Vec<_>>();
+
+    VarInt::from(other_channels.len()).encode(&mut buf)?;
+    for channel in other_channels {
+        channel.res.encode(&mut buf)?;
+        channel.version.encode(&mut buf)?;
+        channel.required.encode(&mut buf)?;
+    }
+
+    let mut d = String::new();
+
+    // The byte length, split over two characters.
+    ensure!(
+        buf.len() < 1 << 30,
+        "forge data is too long to encode ({} bytes)",
+        buf.len()
+    );
+    d.push(encode_char(buf.len() as u32 & 0x7fff)?);
+    d.push(encode_char((buf.len() >> 15) as u32 & 0x7fff)?);
+
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for byte in buf {
+        bits |= (byte as u32) << bit_count;
+        bit_count += 8;
+
+        if bit_count >= 15 {
+            d.push(encode_char(bits & 0x7fff)?);
+            bits >>= 15;
+            bit_count -= 15;
+        }
+    }
+
+    if bit_count > 0 {
+        d.push(encode_char(bits & 0x7fff)?);
+    }
+
+    Ok(d)
+}
+
+/// Unpacks and reads the mods and channels written by [`encode_optimized`],
+/// along with whether they were truncated.
+fn decode_optimized(d: &str) -> Result<(Vec<ForgeMod>, Vec<ForgeChannel>, bool)> {
+    let chars = d.encode_utf16().collect::<Vec<_>>();
+
+    ensure!(chars.len() >= 2, "forge data is missing its length");
+
+    let length = chars[0] as usize | (chars[1] as usize) << 15;
+
+    let mut buf = Vec::with_capacity(length.min(MAX_PACKET_SIZE as usize));
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for c in &chars[2..] {
+        // Anything after the stated length is ignored, as Forge does.
+        if buf.len() == length {
+            break;
+        }
+
+        bits |= (*c as u32 & 0x7fff) << bit_count;
+        bit_count += 15;
+
+        while bit_count >= 8 && buf.len() < length {
+            buf.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    }
+
+    ensure!(
+        buf.len() == length,
+        "forge data is shorter than its stated length of {length} bytes"
+    );
+
+    let mut buf = Cursor::new(buf);
+
+    let truncated = bool::decode(&mut buf)?;
+    let mod_count = u16::decode(&mut buf)?;
+
+    let mut mods = Vec::new();
+    let mut channels = Vec::new();
+
+    for _ in 0..mod_count {
+        let flags = VarInt::decode(&mut buf)?.0;
+        let mod_id = String::decode(&mut buf)?;
+
+        // Mods which are only needed on the server don't have a version.
+        let modmarker = match flags & 1 {
+            0 => String::decode(&mut buf)?,
+            _ => String::new(),
+        };
+
+        for _ in 0..flags >> 1 {
+            channels.push(ForgeChannel {
+                res: format!("{mod_id}:{}", String::decode(&mut buf)?),
+                version: String::decode(&mut buf)?,
+                required: bool::decode(&mut buf)?,
+            });
+        }
+
+        mods.push(ForgeMod { mod_id, modmarker });
+    }
+
+    for _ in 0..VarInt::decode(&mut buf)?.0 {
+        channels.push(ForgeChannel {
+            res: String::decode(&mut buf)?,
+            version: String::decode(&mut buf)?,
+            required: bool::decode(&mut buf)?,
+        });
+    }
+
+    Ok((mods, channels, truncated))
+}
+
+/// Every 15 bit value is below the UTF-16 surrogate range, so is a valid
+/// `char`.
+fn encode_char(value: u32) -> Result<char> {
+    char::from_u32(value).with_context(|| format!("{value:#x} is not a valid forge data character"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `d` field of a Forge 1.20.1 server with no other mods, packed the
+    /// way Forge's `ServerStatusPing` does it.
+    const FORGE_1_20_1_D: &str = concat!(
+        "\u{a5}\u{0}\u{0}\u{804}\u{3424}\u{734b}\u{3656}",
+        "\u{2e4c}\u{1998}\u{33a}\u{2e31}\u{6064}\u{44b8}\u{2851}",
+        "\u{26e7}\u{6cae}\u{5a59}\u{3a39}\u{7265}\u{c08}\u{3135}",
+        "\u{19a}\u{2080}\u{6cae}\u{5a59}\u{3a39}\u{7265}\u{c08}",
+        "\u{3135}\u{19a}\u{5080}\u{6cc0}\u{5c9b}\u{32b3}\u{3406}",
+        "\u{5c6e}\u{38c8}\u{6181}\u{1740}\u{4cad}\u{57dc}\u{37b9}",
+        "\u{7472}\u{5cd2}\u{d9d}\u{7188}\u{302}\u{60a0}\u{1c1c}",
+        "\u{34b6}\u{374}\u{5c62}\u{4c4}\u{6028}\u{76f6}\u{4d2c}",
+        "\u{15b}\u{1719}\u{2e30}\u{260}\u{4010}\u{b63}\u{5796}",
+        "\u{4640}\u{c0b}\u{1817}\u{201}\u{4c20}\u{31b5}\u{61d3}",
+        "\u{76f6}\u{4d2c}\u{1ddb}\u{30b9}\u{7070}\u{64ca}\u{1811}",
+        "\u{626a}\u{1334}\u{41a0}\u{1b59}\u{1d36}\u{6168}\u{48dc}",
+        "\u{21cd}\u{5b0b}\u{4656}\u{28c0}\u{5313}\u{99}",
+    );
+
+    fn channel(res: &str, version: &str, required: bool) -> ForgeChannel {
+        ForgeChannel {
+            res: res.to_string(),
+            version: version.to_string(),
+            required,
+        }
+    }
+
+    fn forge_1_20_1() -> (Vec<ForgeMod>, Vec<ForgeChannel>) {
+        let mods = [("minecraft", "1.20.1"), ("forge", "47.2.0")]
+            .into_iter()
+            .map(|(mod_id, modmarker)| ForgeMod {
+                mod_id: mod_id.to_string(),
+                modmarker: modmarker.to_string(),
+            })
+            .collect();
+
+        let channels = vec![
+            channel("minecraft:unregister", "FML3", false),
+            channel("minecraft:register", "FML3", false),
+            channel("forge:tier_sorting", "1.0", false),
+            channel("forge:split", "1.1", true),
+            channel("forge:login", "2.0.0", true),
+            channel("forge:play", "2.0.0", true),
+            channel("fml:loginwrapper", "FML3", true),
+            channel("fml:handshake", "FML3", true),
+        ];
+
+        (mods, channels)
+    }
+
+    #[test]
+    fn decodes_forge_d() {
+        let (mods, channels) = forge_1_20_1();
+
+        assert_eq!(
+            decode_optimized(FORGE_1_20_1_D).unwrap(),
+            (mods, channels, false)
+        );
+    }
+
+    #[test]
+    fn round_trips_forge_d() {
+        let (mods, channels) = forge_1_20_1();
+        let forge_data = ForgeData::compressed(3, mods.clone(), channels.clone()).unwrap();
+
+        assert_eq!(forge_data.d.as_deref(), Some(FORGE_1_20_1_D));
+        assert_eq!(forge_data.mods().unwrap(), mods);
+        assert_eq!(forge_data.channels().unwrap(), channels);
+        assert!(!forge_data.truncated().unwrap());
+    }
+
+    #[test]
+    fn ignores_trailing_chars() {
+        let (mods, channels) = forge_1_20_1();
+        let d = format!("{FORGE_1_20_1_D}{}", "\u{7fff}".repeat(16));
+
+        assert_eq!(decode_optimized(&d).unwrap(), (mods, channels, false));
+    }
+
+    #[test]
+    fn rejects_short_d() {
+        assert!(decode_optimized("").is_err());
+
+        let d = FORGE_1_20_1_D.chars().take(40).collect::<String>();
+        assert!(decode_optimized(&d).is_err());
+    }
+}
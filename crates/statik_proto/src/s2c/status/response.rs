@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use statik_core::prelude::*;
 use uuid::Uuid;
 
+use super::forge::{ForgeData, ModInfo};
+
 /// # Examples
 ///
 /// A sample status response in json:
@@ -66,7 +68,21 @@ pub struct StatusResponse {
     ///byte slice of an image encoded as base64, prefixed by
     #[serde(skip_serializing_if = "Option::is_none")]
     favicon: Option<String>,
+    #[serde(default)]
     enforces_secure_chat: bool,
+
+    /// Only sent by servers with chat previews enabled, which was removed in
+    /// 1.19.3.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previews_chat: Option<bool>,
+
+    /// Sent by Forge and NeoForge servers on 1.13 and above.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forge_data: Option<Box<ForgeData>>,
+
+    /// Sent by Forge servers on 1.12.2 and below.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modinfo: Option<Box<ModInfo>>,
 }
 
 impl std::fmt::Debug for StatusResponse {
@@ -83,6 +99,9 @@ impl std::fmt::Debug for StatusResponse {
                 },
             )
             .field("enforces_secure_chat", &self.enforces_secure_chat)
+            .field("previews_chat", &self.previews_chat)
+            .field("forge_data", &self.forge_data)
+            .field("modinfo", &self.modinfo)
             .finish()
     }
 }
@@ -101,8 +120,64 @@ impl StatusResponse {
             description,
             favicon: favicon.map(|data| format!("data:image/png;base64,{data}")),
             enforces_secure_chat,
+            previews_chat: None,
+            forge_data: None,
+            modinfo: None,
         }
     }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn players(&self) -> &Players {
+        &self.players
+    }
+
+    pub fn description(&self) -> &Chat {
+        &self.description
+    }
+
+    /// The server's icon, as a `data:image/png;base64,` URI.
+    pub fn favicon(&self) -> Option<&str> {
+        self.favicon.as_deref()
+    }
+
+    pub fn enforces_secure_chat(&self) -> bool {
+        self.enforces_secure_chat
+    }
+
+    pub fn previews_chat(&self) -> Option<bool> {
+        self.previews_chat
+    }
+
+    pub fn forge_data(&self) -> Option<&ForgeData> {
+        self.forge_data.as_deref()
+    }
+
+    pub fn modinfo(&self) -> Option<&ModInfo> {
+        self.modinfo.as_deref()
+    }
+
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_previews_chat(mut self, previews_chat: bool) -> Self {
+        self.previews_chat = Some(previews_chat);
+        self
+    }
+
+    pub fn with_forge_data(mut self, forge_data: ForgeData) -> Self {
+        self.forge_data = Some(Box::new(forge_data));
+        self
+    }
+
+    pub fn with_mod_info(mut self, modinfo: ModInfo) -> Self {
+        self.modinfo = Some(Box::new(modinfo));
+        self
+    }
 }
 
 impl Encode for StatusResponse {
@@ -129,25 +204,35 @@ impl Decode for StatusResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Version {
     name: Cow<'static, str>,
-    protocol: usize,
+    /// Signed, as some servers (e.g. proxies) report -1 to mark every client
+    /// as incompatible.
+    protocol: i32,
 }
 
 impl Default for Version {
     fn default() -> Self {
         Self {
             name: Cow::Borrowed(MINECRAFT_VERSION),
-            protocol: PROTOCOL_VERSION,
+            protocol: PROTOCOL_VERSION as i32,
         }
     }
 }
 
 impl Version {
-    pub fn new<S: Into<Cow<'static, str>>>(name: S, protocol: usize) -> Self {
+    pub fn new<S: Into<Cow<'static, str>>>(name: S, protocol: i32) -> Self {
         Self {
             name: name.into(),
             protocol,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn protocol(&self) -> i32 {
+        self.protocol
+    }
 }
 
 /// # Examples
@@ -169,6 +254,7 @@ impl Version {
 pub struct Players {
     max: i32,
     online: i32,
+    #[serde(default)]
     sample: Vec<PlayerSample>,
 }
 
@@ -180,6 +266,18 @@ impl Players {
             sample,
         }
     }
+
+    pub fn max(&self) -> i32 {
+        self.max
+    }
+
+    pub fn online(&self) -> i32 {
+        self.online
+    }
+
+    pub fn sample(&self) -> &[PlayerSample] {
+        &self.sample
+    }
 }
 
 /// # Examples
@@ -204,4 +302,12 @@ impl PlayerSample {
             id,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
 }
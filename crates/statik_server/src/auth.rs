@@ -0,0 +1,263 @@
+//! Checks players really own the account they join with, the way a notchian
+//! server in online mode does - see [Protocol Encryption](https://wiki.vg/Protocol_Encryption).
+
+use std::{net::IpAddr, time::Duration};
+
+use reqwest::StatusCode;
+use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use sha1::{Digest, Sha1};
+use statik_core::prelude::*;
+use statik_proto::s2c::login::GameProfile;
+
+/// The size of the server's RSA keypair, in bits. Clients expect exactly this.
+const KEY_BITS: usize = 1024;
+
+/// How long the session server has to respond before a player is turned
+/// away.
+const SESSION_SERVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The keypair the shared secret for each connection is encrypted with. Like
+/// the notchian server, a new one is generated each time statik starts.
+pub struct ServerKey {
+    private_key: RsaPrivateKey,
+
+    /// The public key, encoded as an ASN.1 DER SubjectPublicKeyInfo - as it is
+    /// sent to clients.
+    public_key: Vec<u8>,
+}
+
+impl std::fmt::Debug for ServerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ServerKey")
+            .field("private_key", &"#HIDDEN: RSA PRIVATE KEY#")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+impl ServerKey {
+    pub fn generate() -> Result<Self> {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), KEY_BITS)?;
+
+        let public_key = RsaPublicKey::from(&private_key)
+            .to_public_key_der()?
+            .as_bytes()
+            .to_vec();
+
+        Ok(Self {
+            private_key,
+            public_key,
+        })
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Decrypts data the client encrypted with the public key.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.private_key
+            .decrypt(Pkcs1v15Encrypt, data)
+            .context("failed to decrypt data sent by the client")
+    }
+}
+
+/// The hash the client and session server use to identify this login,
+/// which is a SHA-1 digest printed as a signed (two's complement) hex number
+/// rather than the usual unsigned one.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut digest: [u8; 20] = Sha1::new()
+        .chain_update(server_id)
+        .chain_update(shared_secret)
+        .chain_update(public_key)
+        .finalize()
+        .into();
+
+    let negative = digest[0] & 0x80 != 0;
+
+    if negative {
+        // Negate the two's complement number, to print its magnitude.
+        let mut carry = true;
+
+        for byte in digest.iter_mut().rev() {
+            (*byte, carry) = (!*byte).overflowing_add(carry as u8);
+        }
+    }
+
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    let hex = match hex.trim_start_matches('0') {
+        "" => "0",
+        hex => hex,
+    };
+
+    match negative {
+        true => format!("-{hex}"),
+        false => hex.to_string(),
+    }
+}
+
+/// Asks the session server whether `username` has joined using
+/// `server_hash`, returning their profile if they have.
+///
+/// If `ip` is given, the session server also checks it is the address the
+/// player authenticated from.
+pub async fn has_joined(
+    session_server: &str,
+    username: &str,
+    server_hash: &str,
+    ip: Option<IpAddr>,
+) -> Result<Option<GameProfile>> {
+    let url = format!(
+        "{}/session/minecraft/hasJoined",
+        session_server.trim_end_matches('/')
+    );
+
+    let mut query = vec![
+        ("username", username.to_string()),
+        ("serverId", server_hash.to_string()),
+    ];
+
+    if let Some(ip) = ip {
+        query.push(("ip", ip.to_string()));
+    }
+
+    let response = reqwest::Client::builder()
+        .timeout(SESSION_SERVER_TIMEOUT)
+        .build()?
+        .get(&url)
+        .query(&query)
+        .send()
+        .await
+        .with_context(|| format!("failed to reach the session server at {url}"))?;
+
+    match response.status() {
+        StatusCode::OK => {
+            Ok(Some(response.json().await.context(
+                "session server responded with an invalid profile",
+            )?))
+        }
+        // The player hasn't joined (e.g. they don't own the account).
+        StatusCode::NO_CONTENT => Ok(None),
+        status => bail!("session server at {url} responded with {status}"),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use reqwest::Url;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+
+    /// Answers requests on a local port like the session server would, with
+    /// `status` and `body`. The query of each request is sent down the
+    /// returned channel.
+    pub(crate) async fn session_server(
+        status: u16,
+        body: &'static str,
+    ) -> (String, mpsc::UnboundedReceiver<HashMap<String, String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (queries, received) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    if stream.read_buf(&mut request).await.unwrap_or(0) == 0 {
+                        break;
+                    }
+                }
+
+                let request = String::from_utf8_lossy(&request);
+                let target = request.split(' ').nth(1).unwrap_or_default();
+                let url = Url::parse(&format!("http://stub{target}")).unwrap();
+
+                assert_eq!(url.path(), "/session/minecraft/hasJoined");
+                let _ = queries.send(url.query_pairs().into_owned().collect());
+
+                let response = format!(
+                    "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\nContent-Length: \
+                     {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (url, received)
+    }
+
+    pub(crate) const PROFILE: &str = r#"{
+        "id": "069a79f444e94726a5befca90e38aaf5",
+        "name": "Notch",
+        "properties": [{ "name": "textures", "value": "e30=", "signature": "c2ln" }]
+    }"#;
+
+    #[test]
+    fn server_hashes() {
+        // From https://wiki.vg/Protocol_Encryption#Sample_Code.
+        assert_eq!(
+            server_hash("Notch", &[], &[]),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+        assert_eq!(
+            server_hash("jeb_", &[], &[]),
+            "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+        );
+        assert_eq!(
+            server_hash("simon", &[], &[]),
+            "88e16a1019277b15d58faf0541e11910eb756f6"
+        );
+    }
+
+    #[tokio::test]
+    async fn joined() {
+        let (url, mut queries) = session_server(200, PROFILE).await;
+        let ip = "192.0.2.7".parse().unwrap();
+
+        let profile = has_joined(&url, "Notch", "-7c9d5b", Some(ip))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(profile.name, "Notch");
+        assert_eq!(profile.properties[0].name, "textures");
+
+        let query = queries.recv().await.unwrap();
+        assert_eq!(query["username"], "Notch");
+        assert_eq!(query["serverId"], "-7c9d5b");
+        assert_eq!(query["ip"], "192.0.2.7");
+    }
+
+    #[tokio::test]
+    async fn not_joined() {
+        let (url, mut queries) = session_server(204, "").await;
+
+        assert!(has_joined(&url, "Notch", "4ed1f4", None)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!queries.recv().await.unwrap().contains_key("ip"));
+    }
+
+    #[tokio::test]
+    async fn session_server_down() {
+        let (url, _) = session_server(503, "").await;
+        assert!(has_joined(&url, "Notch", "4ed1f4", None).await.is_err());
+
+        let (url, _) = session_server(200, "not a profile").await;
+        assert!(has_joined(&url, "Notch", "4ed1f4", None).await.is_err());
+    }
+}
@@ -0,0 +1,671 @@
+use std::{
+    fmt::Debug,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use statik_core::prelude::*;
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, watch},
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    config::{BackendConfig, LauncherConfig, ReadyCheck, ServerConfig},
+    shutdown::Shutdown,
+};
+
+pub mod docker;
+pub mod kubernetes;
+pub mod process;
+pub mod webhook;
+pub mod wol;
+
+/// Whether the real minecraft server statik is standing in for is up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendStatus {
+    Offline,
+
+    /// Statik has started the real server, but it isn't responding yet.
+    Starting,
+
+    Online,
+
+    /// Statik is stopping the real server.
+    Stopping,
+}
+
+/// A way for statik to start and stop the real server, e.g. by running it as
+/// a child process. Servers are started when a player tries to join while
+/// they are down, and stopped once they've been idle for the backend's
+/// `idle_timeout`.
+#[async_trait]
+pub trait Backend: Debug + Send + Sync {
+    /// Starts the real server, unless it is already running. Called for
+    /// every player who joins while it's offline or starting.
+    async fn start(&self) -> Result<()>;
+
+    /// Stops the real server, unless it isn't running.
+    async fn stop(&self) -> Result<()>;
+
+    /// Called when statik shuts down. Backends whose server can't keep
+    /// running without statik should stop it - by default, it's left alone.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The real server's current status.
+    fn status(&self) -> BackendStatus {
+        *self.subscribe().borrow()
+    }
+
+    /// Changes to the real server's status - both those made by the backend
+    /// itself, and those found by checking whether the server responds.
+    fn subscribe(&self) -> watch::Receiver<BackendStatus>;
+}
+
+/// Creates the backend configured by `config`, which reports any changes to
+/// the real server's status to `status`. Any tasks it runs stop once
+/// `shutdown` is received.
+fn launcher(
+    config: &LauncherConfig,
+    status: Arc<watch::Sender<BackendStatus>>,
+    shutdown: Shutdown,
+) -> Result<Arc<dyn Backend>> {
+    Ok(match config {
+        LauncherConfig::Process(config) => {
+            Arc::new(process::ProcessBackend::new(config.clone(), status))
+        }
+        LauncherConfig::Kubernetes(config) => {
+            kubernetes::KubernetesBackend::spawn(config.clone(), status, shutdown)
+        }
+        LauncherConfig::Docker(config) => {
+            docker::DockerBackend::spawn(config.clone(), status, shutdown)
+        }
+        LauncherConfig::Webhook(config) => {
+            webhook::WebhookBackend::spawn(*config.clone(), status, shutdown)?
+        }
+        LauncherConfig::WakeOnLan(config) => {
+            Arc::new(wol::WakeOnLanBackend::new(config.clone(), status)?)
+        }
+    })
+}
+
+/// Checks the settings of `backend` make sense together.
+fn validate(backend: &BackendConfig) -> Result<()> {
+    let wake_on_lan = match &backend.launcher {
+        Some(LauncherConfig::WakeOnLan(config)) => Some(config),
+        _ => None,
+    };
+
+    if let Some(config) = wake_on_lan {
+        ensure!(
+            backend.address.is_some(),
+            "a Wake-on-LAN backend has no address, so statik can't tell when the real server has \
+             woken up"
+        );
+
+        wol::parse_mac(&config.mac)
+            .with_context(|| format!("invalid Wake-on-LAN MAC address \"{}\"", config.mac))?;
+    }
+
+    if backend.idle_timeout > 0 {
+        ensure!(
+            backend.address.is_some() && backend.check == ReadyCheck::Status,
+            "a backend has an idle_timeout, but statik can't see how many players are online \
+             without its address (and check = \"status\")"
+        );
+
+        ensure!(
+            backend.launcher.is_some() && wake_on_lan.is_none(),
+            "a backend has an idle_timeout, but no launcher that can stop it"
+        );
+    }
+
+    Ok(())
+}
+
+/// Changes `status` to whatever `update` returns for the current one,
+/// returning the new status if it changed.
+pub fn transition(
+    status: &watch::Sender<BackendStatus>,
+    update: impl FnOnce(BackendStatus) -> BackendStatus,
+) -> Option<BackendStatus> {
+    let mut new_status = None;
+
+    status.send_if_modified(|status| {
+        let updated = update(*status);
+        let changed = *status != updated;
+
+        if changed {
+            *status = updated;
+            new_status = Some(updated);
+        }
+
+        changed
+    });
+
+    new_status
+}
+
+/// A real server: whether it is up, and how to start it if statik can.
+#[derive(Debug, Clone)]
+struct Entry {
+    status: watch::Receiver<BackendStatus>,
+    launcher: Option<Arc<dyn Backend>>,
+
+    /// Set while the launcher is being asked to start the real server.
+    starting: Arc<AtomicBool>,
+}
+
+/// Whether each of the real servers statik is standing in for is up: the one
+/// in the top level `backend` config, and those of any host profiles with
+/// their own.
+#[derive(Debug, Clone)]
+pub struct Backends {
+    default: Entry,
+
+    /// One per entry in `config.hosts`, in the same order. `None` for
+    /// profiles without their own backend, which share the default.
+    hosts: Vec<Option<Entry>>,
+}
+
+impl Backends {
+    /// Starts monitoring every backend in `config`, until `notify_shutdown`
+    /// is sent.
+    ///
+    /// Every backend is checked before anything is spawned, so an invalid one
+    /// doesn't leave the others' tasks running.
+    pub fn spawn(
+        config: &ServerConfig,
+        notify_shutdown: &broadcast::Sender<Option<String>>,
+    ) -> Result<Self> {
+        let host_backends = config.hosts.iter().filter_map(|host| host.backend.as_ref());
+
+        for backend in std::iter::once(&config.backend).chain(host_backends) {
+            validate(backend)?;
+        }
+
+        let spawn_backend = |backend: &BackendConfig| {
+            let status = Arc::new(watch::channel(BackendStatus::Offline).0);
+
+            if let Some(address) = backend.address.clone() {
+                tokio::spawn(monitor(
+                    address,
+                    Duration::from_secs(backend.poll_interval.max(1)),
+                    backend.check,
+                    status.clone(),
+                    Shutdown::new(notify_shutdown.subscribe()),
+                ));
+            }
+
+            let launcher = backend
+                .launcher
+                .as_ref()
+                .map(|config| {
+                    launcher(
+                        config,
+                        status.clone(),
+                        Shutdown::new(notify_shutdown.subscribe()),
+                    )
+                })
+                .transpose()?;
+
+            if backend.idle_timeout > 0 {
+                // `validate` checked both are set.
+                if let (Some(address), Some(launcher)) = (&backend.address, launcher.clone()) {
+                    tokio::spawn(stop_when_idle(
+                        address.clone(),
+                        Duration::from_secs(backend.poll_interval.max(1)),
+                        Duration::from_secs(backend.idle_timeout),
+                        launcher,
+                        Shutdown::new(notify_shutdown.subscribe()),
+                    ));
+                }
+            }
+
+            Ok::<_, anyhow::Error>(Entry {
+                status: status.subscribe(),
+                launcher,
+                starting: Arc::default(),
+            })
+        };
+
+        let default = spawn_backend(&config.backend)?;
+
+        let hosts = config
+            .hosts
+            .iter()
+            .map(|host| host.backend.as_ref().map(spawn_backend).transpose())
+            .collect::<Result<_>>()?;
+
+        Ok(Self { default, hosts })
+    }
+
+    /// The backend used by the host profile at `host` in `config.hosts`, or
+    /// the top level backend if `host` is `None`.
+    fn entry(&self, host: Option<usize>) -> &Entry {
+        host.and_then(|host| self.hosts.get(host))
+            .and_then(Option::as_ref)
+            .unwrap_or(&self.default)
+    }
+
+    /// The status of the backend used by the host profile at `host` in
+    /// `config.hosts`, or of the top level backend if `host` is `None`.
+    pub fn status(&self, host: Option<usize>) -> watch::Receiver<BackendStatus> {
+        self.entry(host).status.clone()
+    }
+
+    /// Starts the real server used by the host profile at `host`, if statik
+    /// is able to.
+    ///
+    /// It's started in the background so nobody has to wait for it, and only
+    /// once at a time however many players join while it's starting. Any
+    /// errors are logged, as there's nothing those players can do about them.
+    pub fn start(&self, host: Option<usize>) {
+        let entry = self.entry(host);
+
+        let Some(launcher) = entry.launcher.clone() else {
+            return;
+        };
+
+        if entry.starting.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let starting = entry.starting.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = launcher.start().await {
+                error!("Failed to start the real server: {e:#}");
+            }
+
+            starting.store(false, Ordering::Release);
+        });
+    }
+
+    /// Lets every backend know statik is shutting down.
+    pub async fn shutdown(&self) {
+        let launchers = std::iter::once(&self.default)
+            .chain(self.hosts.iter().flatten())
+            .filter_map(|entry| entry.launcher.as_ref());
+
+        for launcher in launchers {
+            if let Err(e) = launcher.shutdown().await {
+                error!("Failed to stop the real server: {e:#}");
+            }
+        }
+    }
+}
+
+/// Whether statik knows when the real server configured by `config` is up,
+/// either by checking whether it responds, or from the backend running it.
+pub fn is_watched(config: &BackendConfig) -> bool {
+    match &config.launcher {
+        _ if config.address.is_some() => true,
+        Some(LauncherConfig::Kubernetes(_) | LauncherConfig::Docker(_)) => true,
+        Some(LauncherConfig::Webhook(webhook)) => webhook.status.is_some(),
+        Some(LauncherConfig::Process(_) | LauncherConfig::WakeOnLan(_)) | None => false,
+    }
+}
+
+/// Splits a "host:port" address into its host and port, defaulting to port
+/// 25565 if none is given. IPv6 addresses must be given with a port, e.g.
+/// "[::1]:25565".
+pub fn split_address(address: &str) -> Result<(String, u16)> {
+    if let Ok(address) = address.parse::<SocketAddr>() {
+        return Ok((address.ip().to_string(), address.port()));
+    }
+
+    match address.split_once(':') {
+        Some((host, port)) => Ok((
+            host.to_string(),
+            port.parse()
+                .with_context(|| format!("invalid port in address \"{address}\""))?,
+        )),
+        None => Ok((address.to_string(), 25565)),
+    }
+}
+
+/// Periodically checks whether the real server at `address` responds to a
+/// status request, publishing any changes to `status`, until `shutdown` is
+/// received.
+///
+/// A server which accepts connections isn't necessarily ready for players yet
+/// (e.g. behind a proxy, or while still loading), so a full status request is
+/// used rather than just connecting.
+///
+/// While statik is starting or stopping the real server, it is up to the
+/// [`Backend`] doing so to say when it has stopped - a server which is still
+/// starting isn't expected to respond yet.
+pub async fn monitor(
+    address: String,
+    poll_interval: Duration,
+    check: ReadyCheck,
+    status: Arc<watch::Sender<BackendStatus>>,
+    mut shutdown: Shutdown,
+) {
+    let (host, port) = match split_address(&address) {
+        Ok(address) => address,
+        Err(e) => {
+            error!("Not checking whether the real server is up: {e:#}");
+            return;
+        }
+    };
+
+    let mut ticker = time::interval(poll_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while !shutdown.is_shutdown() {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let online = match time::timeout(poll_interval, is_up(&host, port, check)).await {
+                    Ok(Ok(latency)) => {
+                        trace!("The real server at {address} responded in {}ms.", latency.as_millis());
+                        true
+                    }
+                    Ok(Err(e)) => {
+                        trace!("The real server at {address} didn't respond: {e:#}");
+                        false
+                    }
+                    Err(_) => false,
+                };
+
+                let new_status = transition(&status, |status| match (online, status) {
+                    (true, BackendStatus::Stopping) => status,
+                    (true, _) => BackendStatus::Online,
+                    (false, BackendStatus::Online) => BackendStatus::Offline,
+                    (false, _) => status,
+                });
+
+                if let Some(new_status) = new_status {
+                    info!("The real server at {address} is now {new_status:?}.");
+                }
+            }
+            _ = shutdown.recv() => {}
+        }
+    }
+}
+
+/// Stops the real server at `address` with `launcher` once it has been up
+/// without any players for `idle_timeout`, checking every `poll_interval`
+/// until `shutdown` is received.
+pub async fn stop_when_idle(
+    address: String,
+    poll_interval: Duration,
+    idle_timeout: Duration,
+    launcher: Arc<dyn Backend>,
+    mut shutdown: Shutdown,
+) {
+    let (host, port) = match split_address(&address) {
+        Ok(address) => address,
+        Err(e) => {
+            error!("Not stopping the real server when it's idle: {e:#}");
+            return;
+        }
+    };
+
+    let mut ticker = time::interval(poll_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // When the server was first seen without any players.
+    let mut idle_since = None;
+
+    while !shutdown.is_shutdown() {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if launcher.status() != BackendStatus::Online {
+                    idle_since = None;
+                    continue;
+                }
+
+                // Anything but a response with no players (e.g. a server
+                // that's lagging too much to respond) doesn't count as idle.
+                let players = match time::timeout(poll_interval, statik_client::status(&host, port)).await {
+                    Ok(Ok((status, _))) => status.players().online(),
+                    _ => continue,
+                };
+
+                if players > 0 {
+                    idle_since = None;
+                    continue;
+                }
+
+                if idle_since.get_or_insert_with(Instant::now).elapsed() < idle_timeout {
+                    continue;
+                }
+
+                info!(
+                    "The real server at {address} has had no players for {}s, so it's being stopped.",
+                    idle_timeout.as_secs()
+                );
+
+                idle_since = None;
+
+                if let Err(e) = launcher.stop().await {
+                    error!("Failed to stop the real server: {e:#}");
+                }
+            }
+            _ = shutdown.recv() => {}
+        }
+    }
+}
+
+/// Checks whether the real server at `host`:`port` is up, returning how long
+/// it took to respond.
+async fn is_up(host: &str, port: u16, check: ReadyCheck) -> Result<Duration> {
+    match check {
+        ReadyCheck::Status => Ok(statik_client::status(host, port).await?.1),
+        ReadyCheck::Connect => {
+            let start = time::Instant::now();
+            TcpStream::connect((host, port)).await?;
+
+            Ok(start.elapsed())
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        config::WakeOnLanConfig, connection::Connection, limits::Limits, status_cache::StatusCache,
+    };
+
+    /// Waits for `backend` to have `status`, failing the test if it takes too
+    /// long.
+    pub(crate) async fn wait_for_status(backend: &dyn Backend, status: BackendStatus) {
+        time::timeout(
+            Duration::from_secs(5),
+            backend.subscribe().wait_for(|current| *current == status),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("the backend didn't become {status:?} in time"))
+        .unwrap();
+    }
+
+    /// Counts how many times it's started and stopped.
+    #[derive(Debug)]
+    struct FakeBackend {
+        status: watch::Sender<BackendStatus>,
+        starts: AtomicUsize,
+        stops: AtomicUsize,
+    }
+
+    impl FakeBackend {
+        fn new(status: BackendStatus) -> Arc<Self> {
+            Arc::new(Self {
+                status: watch::channel(status).0,
+                starts: AtomicUsize::new(0),
+                stops: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Backend for FakeBackend {
+        async fn start(&self) -> Result<()> {
+            self.starts.fetch_add(1, Ordering::SeqCst);
+            time::sleep(Duration::from_millis(100)).await;
+
+            Ok(())
+        }
+
+        async fn stop(&self) -> Result<()> {
+            self.stops.fetch_add(1, Ordering::SeqCst);
+            self.status.send_replace(BackendStatus::Offline);
+
+            Ok(())
+        }
+
+        fn subscribe(&self) -> watch::Receiver<BackendStatus> {
+            self.status.subscribe()
+        }
+    }
+
+    #[tokio::test]
+    async fn starts_once_at_a_time() {
+        let backend = FakeBackend::new(BackendStatus::Offline);
+
+        let backends = Backends {
+            default: Entry {
+                status: backend.subscribe(),
+                launcher: Some(backend.clone()),
+                starting: Arc::default(),
+            },
+            hosts: vec![],
+        };
+
+        for _ in 0..10 {
+            backends.start(None);
+        }
+
+        time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(backend.starts.load(Ordering::SeqCst), 1);
+
+        // Once the first attempt is over, it can be started again.
+        backends.start(None);
+
+        time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(backend.starts.load(Ordering::SeqCst), 2);
+    }
+
+    /// Answers status requests with statik itself, which never has any
+    /// players, until `notify_shutdown` is sent.
+    async fn empty_server(notify_shutdown: &broadcast::Sender<Option<String>>) -> String {
+        let config = ServerConfig::default();
+        let backends = Arc::new(Backends::spawn(&config, notify_shutdown).unwrap());
+        let limits = Arc::new(Limits::new(&config.limits));
+        let config = Arc::new(tokio::sync::RwLock::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                let mut connection = Connection::new(
+                    config.clone(),
+                    backends.clone(),
+                    Arc::new(StatusCache::default()),
+                    None,
+                    limits.clone(),
+                    stream,
+                    peer,
+                )
+                .await;
+
+                tokio::spawn(async move { connection.handle_connection().await });
+            }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn stops_idle_servers() {
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let address = empty_server(&notify_shutdown).await;
+        let backend = FakeBackend::new(BackendStatus::Online);
+
+        tokio::spawn(stop_when_idle(
+            address,
+            Duration::from_millis(50),
+            Duration::from_millis(200),
+            backend.clone(),
+            Shutdown::new(notify_shutdown.subscribe()),
+        ));
+
+        wait_for_status(backend.as_ref(), BackendStatus::Offline).await;
+
+        // Once stopped, it isn't online to be stopped again.
+        assert_eq!(backend.stops.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_idle_timeout_without_player_count() {
+        let (notify_shutdown, _) = broadcast::channel(1);
+
+        let mut config = ServerConfig::default();
+        config.backend.idle_timeout = 60;
+        config.backend.launcher = Some(LauncherConfig::Process(Default::default()));
+
+        assert!(Backends::spawn(&config, &notify_shutdown).is_err());
+
+        config.backend.address = Some("127.0.0.1:25566".to_string());
+        config.backend.check = ReadyCheck::Connect;
+
+        assert!(Backends::spawn(&config, &notify_shutdown).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_wake_on_lan_without_address() {
+        let (notify_shutdown, _) = broadcast::channel(1);
+
+        let mut config = ServerConfig::default();
+        config.backend.launcher = Some(LauncherConfig::WakeOnLan(WakeOnLanConfig {
+            mac: "aa:bb:cc:01:23:ef".to_string(),
+            ..Default::default()
+        }));
+
+        assert!(Backends::spawn(&config, &notify_shutdown).is_err());
+
+        config.backend.address = Some("192.0.2.10:25565".to_string());
+
+        assert!(Backends::spawn(&config, &notify_shutdown).is_ok());
+    }
+
+    #[tokio::test]
+    async fn validates_before_spawning() {
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let mut config = ServerConfig::default();
+        config.backend.address = Some(listener.local_addr().unwrap().to_string());
+        config.hosts.push(crate::config::HostConfig {
+            hostnames: vec!["wol.example.com".to_string()],
+            backend: Some(BackendConfig {
+                launcher: Some(LauncherConfig::WakeOnLan(Default::default())),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        assert!(Backends::spawn(&config, &notify_shutdown).is_err());
+
+        // The default backend would be checked straight away if it was being
+        // monitored.
+        assert!(time::timeout(Duration::from_millis(500), listener.accept())
+            .await
+            .is_err());
+    }
+}
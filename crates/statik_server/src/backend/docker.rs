@@ -0,0 +1,615 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::{Buf, BytesMut};
+use hyper::{body::HttpBody, header, Body, Method, Request, Response};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+use statik_core::prelude::*;
+use tokio::{net::UnixStream, sync::watch, task::JoinHandle, time};
+
+use super::{transition, Backend, BackendStatus};
+use crate::{config::DockerConfig, shutdown::Shutdown};
+
+/// How long to wait before following the container's events again after
+/// failing to.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs the real server in an existing Docker container, which is started and
+/// stopped through the Docker Engine API.
+///
+/// If the container has a `HEALTHCHECK`, the real server is only considered
+/// up once it is healthy - otherwise, as soon as the container is running.
+#[derive(Debug)]
+pub struct DockerBackend {
+    config: DockerConfig,
+    status: Arc<watch::Sender<BackendStatus>>,
+
+    /// Logs the container's output, while it is running and `config.logs`
+    /// is set.
+    logs: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// The parts of a container's details (from `GET /containers/{id}/json`)
+/// statik uses.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Container {
+    state: ContainerState,
+    config: ContainerConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerState {
+    running: bool,
+    health: Option<Health>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Health {
+    /// "starting", "healthy" or "unhealthy".
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerConfig {
+    /// Whether the container has a TTY, in which case its logs aren't
+    /// multiplexed.
+    tty: bool,
+}
+
+/// Something which happened to the container, from `GET /events`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Event {
+    /// e.g. "start", "die" or "health_status: healthy".
+    action: String,
+    actor: Actor,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Actor {
+    attributes: HashMap<String, String>,
+}
+
+/// An error response from the Docker Engine API.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl DockerBackend {
+    /// Creates the backend, and follows the container's events until
+    /// `shutdown` is received.
+    pub fn spawn(
+        config: DockerConfig,
+        status: Arc<watch::Sender<BackendStatus>>,
+        shutdown: Shutdown,
+    ) -> Arc<Self> {
+        let backend = Arc::new(Self {
+            config,
+            status,
+            logs: Mutex::new(None),
+        });
+
+        tokio::spawn(backend.clone().follow_events(shutdown));
+
+        backend
+    }
+
+    async fn request(&self, method: Method, url: Url) -> Result<Response<Body>> {
+        request(&self.config.socket, method, url).await
+    }
+
+    /// The API's URL for `path` in the container, e.g. "/json" for its details.
+    fn url(&self, path: &str) -> Result<Url> {
+        Ok(Url::parse(&format!(
+            "http://docker/containers/{}{path}",
+            self.config.container
+        ))?)
+    }
+
+    async fn inspect(&self) -> Result<Container> {
+        let response = self.request(Method::GET, self.url("/json")?).await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Updates the real server's status from the container's details, and
+    /// starts logging its output if it's running.
+    async fn refresh(&self) -> Result<()> {
+        let container = self.inspect().await?;
+        let state = &container.state;
+
+        let container_status = match (state.running, state.health.as_ref()) {
+            (false, _) => BackendStatus::Offline,
+            (true, None) => BackendStatus::Online,
+            (true, Some(health)) => match health.status.as_str() {
+                "healthy" => BackendStatus::Online,
+                "starting" => BackendStatus::Starting,
+                _ => BackendStatus::Offline,
+            },
+        };
+
+        let new_status = transition(&self.status, |status| match (status, container_status) {
+            // Docker waits for the server to exit before stopping the
+            // container, during which it is still running.
+            (BackendStatus::Stopping, BackendStatus::Offline) => BackendStatus::Offline,
+            (BackendStatus::Stopping, _) => status,
+            (_, container_status) => container_status,
+        });
+
+        if let Some(new_status) = new_status {
+            info!(
+                "The real server (container \"{}\") is now {new_status:?}.",
+                self.config.container
+            );
+
+            // Running containers are only offline when unhealthy.
+            if state.running && new_status == BackendStatus::Offline {
+                warn!(
+                    "The real server's container \"{}\" is unhealthy.",
+                    self.config.container
+                );
+            }
+        }
+
+        if state.running && self.config.logs {
+            self.follow_logs(container.config.tty);
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the real server's status up to date with the container's events
+    /// until `shutdown` is received, retrying whenever they can't be followed.
+    async fn follow_events(self: Arc<Self>, mut shutdown: Shutdown) {
+        while !shutdown.is_shutdown() {
+            tokio::select! {
+                res = self.follow_events_once() => {
+                    let Err(e) = res else {
+                        continue;
+                    };
+
+                    warn!(
+                        "Failed to follow the events of container \"{}\", retrying in {}s: {e:#}",
+                        self.config.container,
+                        RETRY_DELAY.as_secs()
+                    );
+
+                    tokio::select! {
+                        _ = time::sleep(RETRY_DELAY) => {}
+                        _ = shutdown.recv() => {}
+                    }
+                }
+                _ = shutdown.recv() => {}
+            }
+        }
+    }
+
+    /// Checks the container's current status, then follows its events until
+    /// the Docker daemon ends the stream.
+    async fn follow_events_once(&self) -> Result<()> {
+        let filters = json!({
+            "container": [self.config.container],
+            "type": ["container"],
+        });
+
+        let url =
+            Url::parse_with_params("http://docker/events", [("filters", filters.to_string())])?;
+
+        // Subscribed to first, so nothing that happens in between is missed.
+        let mut events = self.request(Method::GET, url).await?.into_body();
+
+        self.refresh().await?;
+
+        let mut buffer = BytesMut::new();
+
+        while let Some(chunk) = events.data().await {
+            buffer.extend_from_slice(&chunk?);
+
+            while let Some(line) = next_line(&mut buffer) {
+                let event: Event = serde_json::from_slice(&line)?;
+
+                if event.action == "die" {
+                    let exit_code = event
+                        .actor
+                        .attributes
+                        .get("exitCode")
+                        .map(String::as_str)
+                        .unwrap_or("unknown");
+
+                    match exit_code {
+                        "0" => info!(
+                            "The real server's container \"{}\" has stopped.",
+                            self.config.container
+                        ),
+                        _ => warn!(
+                            "The real server's container \"{}\" has stopped with exit code \
+                             {exit_code}.",
+                            self.config.container
+                        ),
+                    }
+                }
+
+                // Exec events (e.g. from health checks) don't change anything.
+                if !event.action.starts_with("exec_") {
+                    self.refresh().await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts logging the container's output, unless it's already being
+    /// logged. Logging stops once the container does.
+    fn follow_logs(&self, tty: bool) {
+        let mut logs = self.logs.lock().unwrap_or_else(|e| e.into_inner());
+
+        if logs.as_ref().is_some_and(|logs| !logs.is_finished()) {
+            return;
+        }
+
+        let Ok(mut url) = self.url("/logs") else {
+            return;
+        };
+
+        url.query_pairs_mut().extend_pairs([
+            ("follow", "true"),
+            ("stdout", "true"),
+            ("stderr", "true"),
+            ("tail", "0"),
+        ]);
+
+        let socket = self.config.socket.clone();
+        let container = self.config.container.clone();
+
+        *logs = Some(tokio::spawn(async move {
+            let output = match request(&socket, Method::GET, url).await {
+                Ok(response) => response.into_body(),
+                Err(e) => {
+                    warn!("Failed to follow the logs of container \"{container}\": {e:#}");
+                    return;
+                }
+            };
+
+            if let Err(e) = log_output(output, tty).await {
+                debug!("Stopped following the logs of container \"{container}\": {e:#}");
+            }
+        }));
+    }
+}
+
+#[async_trait]
+impl Backend for DockerBackend {
+    async fn start(&self) -> Result<()> {
+        let starting = transition(&self.status, |status| match status {
+            BackendStatus::Offline | BackendStatus::Stopping => BackendStatus::Starting,
+            status => status,
+        });
+
+        // Already starting (e.g. for another player), or up.
+        if starting.is_none() {
+            return Ok(());
+        }
+
+        info!(
+            "Starting the real server's container \"{}\".",
+            self.config.container
+        );
+
+        // Already running containers are left as they are.
+        if let Err(e) = self.request(Method::POST, self.url("/start")?).await {
+            transition(&self.status, |_| BackendStatus::Offline);
+
+            return Err(e);
+        }
+
+        self.refresh().await
+    }
+
+    async fn stop(&self) -> Result<()> {
+        let stopping = transition(&self.status, |status| match status {
+            BackendStatus::Starting | BackendStatus::Online => BackendStatus::Stopping,
+            status => status,
+        });
+
+        if stopping.is_none() {
+            return Ok(());
+        }
+
+        info!(
+            "Stopping the real server's container \"{}\".",
+            self.config.container
+        );
+
+        let mut url = self.url("/stop")?;
+        url.query_pairs_mut()
+            .append_pair("t", &self.config.stop_timeout.to_string());
+
+        // Returns once the container has stopped.
+        if let Err(e) = self.request(Method::POST, url).await {
+            // Whatever state it was left in is its real one.
+            transition(&self.status, |_| BackendStatus::Offline);
+            self.refresh().await?;
+
+            return Err(e);
+        }
+
+        self.refresh().await
+    }
+
+    fn subscribe(&self) -> watch::Receiver<BackendStatus> {
+        self.status.subscribe()
+    }
+}
+
+/// Sends a request to the Docker Engine API, returning the response if it
+/// was successful.
+async fn request(socket: &str, method: Method, url: Url) -> Result<Response<Body>> {
+    let stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("failed to connect to \"{socket}\""))?;
+
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Connection to the Docker Engine API failed: {e}");
+        }
+    });
+
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::HOST, "docker")
+        .body(Body::empty())?;
+
+    let response = sender.send_request(request).await?;
+
+    if response.status().is_client_error() || response.status().is_server_error() {
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(error) => bail!("{} ({status})", error.message),
+            Err(_) => bail!("{status}"),
+        }
+    }
+
+    Ok(response)
+}
+
+/// Takes the next whole line (without the trailing newline) from `buffer`.
+fn next_line(buffer: &mut BytesMut) -> Option<BytesMut> {
+    let end = buffer.iter().position(|byte| *byte == b'\n')?;
+    let mut line = buffer.split_to(end + 1);
+    line.truncate(end);
+
+    Some(line)
+}
+
+/// Logs everything the container writes to its stdout and stderr, line by
+/// line, until it stops.
+///
+/// Unless the container has a TTY, its output is multiplexed: each chunk of
+/// it is preceded by an 8 byte header, the last 4 bytes of which are the
+/// chunk's length.
+async fn log_output(mut output: Body, tty: bool) -> Result<()> {
+    let mut buffer = BytesMut::new();
+
+    while let Some(chunk) = output.data().await {
+        buffer.extend_from_slice(&chunk?);
+
+        if tty {
+            while let Some(line) = next_line(&mut buffer) {
+                debug!(
+                    "(real server) {}",
+                    String::from_utf8_lossy(&line).trim_end()
+                );
+            }
+
+            continue;
+        }
+
+        while buffer.len() >= 8 {
+            let length = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+
+            if buffer.len() < 8 + length {
+                break;
+            }
+
+            buffer.advance(8);
+            let chunk = buffer.split_to(length);
+
+            for line in String::from_utf8_lossy(&chunk).lines() {
+                debug!("(real server) {line}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixListener,
+        sync::broadcast,
+    };
+
+    use super::*;
+    use crate::backend::tests::wait_for_status;
+
+    /// The container, as the fake Docker Engine API sees it.
+    #[derive(Debug, Default)]
+    struct FakeContainer {
+        running: bool,
+        health: Option<&'static str>,
+        starts: usize,
+    }
+
+    /// Serves just enough of the Docker Engine API on a unix socket for a
+    /// container called "minecraft", sending anything sent to `events` to
+    /// whoever is following them. Returns the socket's path.
+    async fn fake_engine(
+        container: Arc<Mutex<FakeContainer>>,
+        events: broadcast::Sender<String>,
+    ) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("statik-docker-{nanos}.sock"));
+        let listener = UnixListener::bind(&path).unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let container = container.clone();
+                let mut events = events.subscribe();
+
+                tokio::spawn(async move {
+                    let mut request = vec![];
+
+                    while !request.ends_with(b"\r\n\r\n") {
+                        request.push(stream.read_u8().await.unwrap());
+                    }
+
+                    let request = String::from_utf8(request).unwrap();
+                    let request_line = request.lines().next().unwrap().to_string();
+
+                    let body = match request_line.split(' ').take(2).collect::<Vec<_>>()[..] {
+                        ["GET", path] if path.starts_with("/events?") => {
+                            // Streamed until the connection is closed.
+                            stream
+                                .write_all(
+                                    b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n",
+                                )
+                                .await
+                                .unwrap();
+
+                            while let Ok(event) = events.recv().await {
+                                if stream
+                                    .write_all(format!("{event}\n").as_bytes())
+                                    .await
+                                    .is_err()
+                                {
+                                    return;
+                                }
+                            }
+
+                            return;
+                        }
+                        ["GET", "/containers/minecraft/json"] => {
+                            let container = container.lock().unwrap();
+                            let health = container.health.map(|status| json!({ "Status": status }));
+
+                            json!({
+                                "State": { "Running": container.running, "Health": health },
+                                "Config": { "Tty": false }
+                            })
+                            .to_string()
+                        }
+                        ["POST", "/containers/minecraft/start"] => {
+                            let mut container = container.lock().unwrap();
+                            container.starts += 1;
+                            container.running = true;
+                            container.health = Some("starting");
+
+                            String::new()
+                        }
+                        _ => panic!("unexpected request: {request_line}"),
+                    };
+
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        path
+    }
+
+    fn event(action: &str, attributes: Value) -> String {
+        json!({ "Action": action, "Actor": { "Attributes": attributes } }).to_string()
+    }
+
+    #[tokio::test]
+    async fn starts_and_follows_health() {
+        let container = Arc::new(Mutex::new(FakeContainer::default()));
+        let (events, _) = broadcast::channel(16);
+        let socket = fake_engine(container.clone(), events.clone()).await;
+
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let backend = DockerBackend::spawn(
+            DockerConfig {
+                socket: socket.to_str().unwrap().to_string(),
+                ..Default::default()
+            },
+            Arc::new(watch::channel(BackendStatus::Offline).0),
+            Shutdown::new(notify_shutdown.subscribe()),
+        );
+
+        // Wait until the events are being followed.
+        time::timeout(Duration::from_secs(5), async {
+            while events.receiver_count() == 0 {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // However many players join at once, it's only started once.
+        let (first, second) = tokio::join!(backend.start(), backend.start());
+        first.unwrap();
+        second.unwrap();
+
+        assert_eq!(container.lock().unwrap().starts, 1);
+        assert_eq!(backend.status(), BackendStatus::Starting);
+
+        // It's only online once it's healthy...
+        container.lock().unwrap().health = Some("healthy");
+        events
+            .send(event("health_status: healthy", json!({})))
+            .unwrap();
+
+        wait_for_status(backend.as_ref(), BackendStatus::Online).await;
+
+        // ...and offline once it's stopped.
+        container.lock().unwrap().running = false;
+        events
+            .send(event("die", json!({ "exitCode": "0" })))
+            .unwrap();
+
+        wait_for_status(backend.as_ref(), BackendStatus::Offline).await;
+
+        std::fs::remove_file(socket).unwrap();
+    }
+}
@@ -0,0 +1,487 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use k8s_openapi::{
+    api::{
+        apps::v1::{Deployment, StatefulSet},
+        core::v1::Pod,
+    },
+    NamespaceResourceScope,
+};
+use kube::{
+    api::{Api, ListParams, Patch, PatchParams, WatchEvent, WatchParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    Client, Resource, ResourceExt,
+};
+use serde_json::json;
+use statik_core::prelude::*;
+use tokio::{
+    sync::{watch, OnceCell},
+    time,
+};
+
+use super::{transition, Backend, BackendStatus};
+use crate::{
+    config::{KubernetesConfig, WorkloadKind},
+    shutdown::Shutdown,
+};
+
+/// How long to wait before watching the workload's pods again after failing
+/// to.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs the real server in a Kubernetes deployment or stateful set, which is
+/// scaled up from zero replicas to start it, and back down to stop it.
+///
+/// The workload's pods are watched to know when the real server is ready, so
+/// it doesn't need an `address` to be checked - unless it should be scaled
+/// back down once nobody is playing, with the backend's `idle_timeout`.
+pub struct KubernetesBackend {
+    config: KubernetesConfig,
+    status: Arc<watch::Sender<BackendStatus>>,
+
+    /// Connected to the cluster when first needed.
+    client: OnceCell<Client>,
+}
+
+impl std::fmt::Debug for KubernetesBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KubernetesBackend")
+            .field("config", &self.config)
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KubernetesBackend {
+    /// Creates the backend, and watches the workload's pods until `shutdown`
+    /// is received.
+    pub fn spawn(
+        config: KubernetesConfig,
+        status: Arc<watch::Sender<BackendStatus>>,
+        shutdown: Shutdown,
+    ) -> Arc<Self> {
+        let backend = Arc::new(Self {
+            config,
+            status,
+            client: OnceCell::new(),
+        });
+
+        tokio::spawn(backend.clone().watch_pods(shutdown));
+
+        backend
+    }
+
+    async fn client(&self) -> Result<Client> {
+        self.client
+            .get_or_try_init(|| connect(&self.config))
+            .await
+            .cloned()
+    }
+
+    fn api<K>(&self, client: Client) -> Api<K>
+    where
+        K: Resource<Scope = NamespaceResourceScope>,
+        K::DynamicType: Default,
+    {
+        match &self.config.namespace {
+            Some(namespace) => Api::namespaced(client, namespace),
+            None => Api::default_namespaced(client),
+        }
+    }
+
+    /// The workload's kind and name, for logging.
+    fn workload(&self) -> String {
+        let kind = match self.config.kind {
+            WorkloadKind::Deployment => "deployment",
+            WorkloadKind::StatefulSet => "stateful set",
+        };
+
+        format!("{kind} \"{}\"", self.config.name)
+    }
+
+    /// Scales the workload to `replicas` replicas.
+    async fn scale(&self, replicas: i32) -> Result<()> {
+        let client = self.client().await?;
+        let name = &self.config.name;
+        let params = PatchParams::default();
+        let patch = Patch::Merge(json!({ "spec": { "replicas": replicas } }));
+
+        match self.config.kind {
+            WorkloadKind::Deployment => {
+                self.api::<Deployment>(client)
+                    .patch_scale(name, &params, &patch)
+                    .await
+            }
+            WorkloadKind::StatefulSet => {
+                self.api::<StatefulSet>(client)
+                    .patch_scale(name, &params, &patch)
+                    .await
+            }
+        }
+        .with_context(|| format!("failed to scale {} to {replicas}", self.workload()))?;
+
+        Ok(())
+    }
+
+    /// The label selector for the workload's pods.
+    async fn selector(&self, client: Client) -> Result<String> {
+        if let Some(selector) = &self.config.selector {
+            return Ok(selector.clone());
+        }
+
+        let name = &self.config.name;
+
+        let selector = match self.config.kind {
+            WorkloadKind::Deployment => self
+                .api::<Deployment>(client)
+                .get(name)
+                .await?
+                .spec
+                .map(|spec| spec.selector),
+            WorkloadKind::StatefulSet => self
+                .api::<StatefulSet>(client)
+                .get(name)
+                .await?
+                .spec
+                .map(|spec| spec.selector),
+        };
+
+        let labels = selector
+            .and_then(|selector| selector.match_labels)
+            .unwrap_or_default();
+
+        ensure!(
+            !labels.is_empty(),
+            "{} has no labels to find its pods by, so a `selector` must be configured",
+            self.workload()
+        );
+
+        Ok(labels
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(","))
+    }
+
+    /// Keeps the real server's status up to date with the workload's pods
+    /// until `shutdown` is received, retrying whenever they can't be watched.
+    async fn watch_pods(self: Arc<Self>, mut shutdown: Shutdown) {
+        while !shutdown.is_shutdown() {
+            tokio::select! {
+                res = self.watch_pods_once() => {
+                    let Err(e) = res else {
+                        continue;
+                    };
+
+                    warn!(
+                        "Failed to watch the pods of {}, retrying in {}s: {e:#}",
+                        self.workload(),
+                        RETRY_DELAY.as_secs()
+                    );
+
+                    tokio::select! {
+                        _ = time::sleep(RETRY_DELAY) => {}
+                        _ = shutdown.recv() => {}
+                    }
+                }
+                _ = shutdown.recv() => {}
+            }
+        }
+    }
+
+    /// Lists the workload's pods, then watches them until the API server ends
+    /// the watch.
+    async fn watch_pods_once(&self) -> Result<()> {
+        let client = self.client().await?;
+        let selector = self.selector(client.clone()).await?;
+        let pods = self.api::<Pod>(client);
+
+        let list = pods.list(&ListParams::default().labels(&selector)).await?;
+
+        // Whether each of the workload's pods is ready, by name.
+        let mut ready: HashMap<String, bool> = list
+            .items
+            .iter()
+            .map(|pod| (pod.name_any(), is_ready(pod)))
+            .collect();
+
+        self.update_status(&ready);
+
+        let version = list.metadata.resource_version.unwrap_or_default();
+        let events = pods
+            .watch(&WatchParams::default().labels(&selector), &version)
+            .await?;
+        let mut events = std::pin::pin!(events);
+
+        while let Some(event) = events.try_next().await? {
+            match event {
+                WatchEvent::Added(pod) | WatchEvent::Modified(pod) => {
+                    ready.insert(pod.name_any(), is_ready(&pod));
+                }
+                WatchEvent::Deleted(pod) => {
+                    ready.remove(&pod.name_any());
+                }
+                WatchEvent::Bookmark(_) => continue,
+                WatchEvent::Error(e) => bail!("{e}"),
+            }
+
+            self.update_status(&ready);
+        }
+
+        Ok(())
+    }
+
+    /// Updates the real server's status from whether each of the workload's
+    /// pods is ready.
+    fn update_status(&self, pods: &HashMap<String, bool>) {
+        let any_ready = pods.values().any(|ready| *ready);
+
+        let new_status = transition(&self.status, |status| {
+            match (status, pods.is_empty(), any_ready) {
+                // Pods take a while to be created after scaling up, and to go
+                // away after scaling down.
+                (BackendStatus::Starting, true, _) => status,
+                (BackendStatus::Stopping, false, _) => status,
+                (_, true, _) => BackendStatus::Offline,
+                (_, false, true) => BackendStatus::Online,
+                (_, false, false) => BackendStatus::Starting,
+            }
+        });
+
+        if let Some(new_status) = new_status {
+            info!(
+                "The real server ({}) is now {new_status:?}.",
+                self.workload()
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for KubernetesBackend {
+    async fn start(&self) -> Result<()> {
+        let starting = transition(&self.status, |status| match status {
+            BackendStatus::Offline | BackendStatus::Stopping => BackendStatus::Starting,
+            status => status,
+        });
+
+        // Already starting (e.g. for another player), or up.
+        if starting.is_none() {
+            return Ok(());
+        }
+
+        info!(
+            "Starting the real server by scaling {} up to {}.",
+            self.workload(),
+            self.config.replicas
+        );
+
+        if let Err(e) = self.scale(self.config.replicas).await {
+            transition(&self.status, |_| BackendStatus::Offline);
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        let stopping = transition(&self.status, |status| match status {
+            BackendStatus::Starting | BackendStatus::Online => BackendStatus::Stopping,
+            status => status,
+        });
+
+        if stopping.is_none() {
+            return Ok(());
+        }
+
+        info!(
+            "Stopping the real server by scaling {} down to 0.",
+            self.workload()
+        );
+
+        self.scale(0).await
+    }
+
+    fn subscribe(&self) -> watch::Receiver<BackendStatus> {
+        self.status.subscribe()
+    }
+}
+
+/// Connects to the cluster with the configured kubeconfig and context, or
+/// whatever is available if neither is configured.
+async fn connect(config: &KubernetesConfig) -> Result<Client> {
+    let options = KubeConfigOptions {
+        context: config.context.clone(),
+        ..Default::default()
+    };
+
+    let client_config = match (&config.kubeconfig, &config.context) {
+        (Some(path), _) => {
+            let kubeconfig = Kubeconfig::read_from(path)
+                .with_context(|| format!("failed to read kubeconfig \"{path}\""))?;
+
+            kube::Config::from_custom_kubeconfig(kubeconfig, &options).await?
+        }
+        (None, Some(_)) => kube::Config::from_kubeconfig(&options).await?,
+        (None, None) => kube::Config::infer().await?,
+    };
+
+    Ok(Client::try_from(client_config)?)
+}
+
+/// Whether `pod` has passed its readiness checks, and so the real server in
+/// it is accepting players.
+fn is_ready(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{body, Body, Method, Request, Response};
+    use serde_json::Value;
+    use tower_test::mock::{self, Handle};
+
+    use super::*;
+    use crate::backend::tests::wait_for_status;
+
+    const PODS: &str = "/api/v1/namespaces/games/pods";
+    const SCALE: &str = "/apis/apps/v1/namespaces/games/deployments/minecraft/scale";
+
+    fn pod(ready: bool) -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "minecraft-0" },
+            "status": {
+                "conditions": [{ "type": "Ready", "status": if ready { "True" } else { "False" } }]
+            }
+        })
+    }
+
+    /// Waits for the next request to the API, checking its method and path.
+    async fn expect_request(
+        handle: &mut Handle<Request<Body>, Response<Body>>,
+        method: Method,
+        path: &str,
+    ) -> (Request<Value>, mock::SendResponse<Response<Body>>) {
+        let (request, send) = time::timeout(Duration::from_secs(5), handle.next_request())
+            .await
+            .expect("no request was made in time")
+            .unwrap();
+
+        assert_eq!(request.method(), method);
+        assert_eq!(request.uri().path(), path);
+
+        let (parts, request_body) = request.into_parts();
+        let request_body = body::to_bytes(request_body).await.unwrap();
+        let request_body = serde_json::from_slice(&request_body).unwrap_or(Value::Null);
+
+        (Request::from_parts(parts, request_body), send)
+    }
+
+    /// Checks the workload is scaled to `replicas`, and responds as if it was.
+    async fn expect_scale(handle: &mut Handle<Request<Body>, Response<Body>>, replicas: i32) {
+        let (request, send) = expect_request(handle, Method::PATCH, SCALE).await;
+        assert_eq!(request.body(), &json!({ "spec": { "replicas": replicas } }));
+
+        send.send_response(Response::new(Body::from(
+            json!({
+                "apiVersion": "autoscaling/v1",
+                "kind": "Scale",
+                "metadata": { "name": "minecraft", "namespace": "games" },
+                "spec": { "replicas": replicas }
+            })
+            .to_string(),
+        )));
+    }
+
+    #[tokio::test]
+    async fn scales_up_and_down() {
+        let (service, mut handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let (notify_shutdown, _) = tokio::sync::broadcast::channel(1);
+
+        let status = Arc::new(watch::channel(BackendStatus::Offline).0);
+        let backend = Arc::new(KubernetesBackend {
+            config: KubernetesConfig {
+                namespace: Some("games".to_string()),
+                selector: Some("app=minecraft".to_string()),
+                ..Default::default()
+            },
+            status: status.clone(),
+            client: OnceCell::new_with(Some(Client::new(service, "default"))),
+        });
+
+        // Starting it scales it up from 0.
+        let start = tokio::spawn({
+            let backend = backend.clone();
+            async move { backend.start().await }
+        });
+
+        expect_scale(&mut handle, 1).await;
+        start.await.unwrap().unwrap();
+        assert_eq!(backend.status(), BackendStatus::Starting);
+
+        // It's only online once its pod is ready.
+        tokio::spawn(
+            backend
+                .clone()
+                .watch_pods(Shutdown::new(notify_shutdown.subscribe())),
+        );
+
+        let (request, send) = expect_request(&mut handle, Method::GET, PODS).await;
+        assert_eq!(
+            request.uri().query(),
+            Some("&labelSelector=app%3Dminecraft")
+        );
+
+        send.send_response(Response::new(Body::from(
+            json!({
+                "apiVersion": "v1",
+                "kind": "PodList",
+                "metadata": { "resourceVersion": "1" },
+                "items": [pod(false)]
+            })
+            .to_string(),
+        )));
+
+        let (request, send) = expect_request(&mut handle, Method::GET, PODS).await;
+        assert!(request.uri().query().unwrap().contains("watch=true"));
+
+        let (mut events, body) = Body::channel();
+        send.send_response(Response::new(body));
+
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(backend.status(), BackendStatus::Starting);
+
+        let event = json!({ "type": "MODIFIED", "object": pod(true) });
+        events.send_data(format!("{event}\n").into()).await.unwrap();
+
+        wait_for_status(backend.as_ref(), BackendStatus::Online).await;
+
+        // Stopping it scales it back down to 0.
+        let stop = tokio::spawn({
+            let backend = backend.clone();
+            async move { backend.stop().await }
+        });
+
+        expect_scale(&mut handle, 0).await;
+        stop.await.unwrap().unwrap();
+        assert_eq!(backend.status(), BackendStatus::Stopping);
+
+        // ...and it's offline once its pod has gone.
+        let event = json!({ "type": "DELETED", "object": pod(false) });
+        events.send_data(format!("{event}\n").into()).await.unwrap();
+
+        wait_for_status(backend.as_ref(), BackendStatus::Offline).await;
+    }
+}
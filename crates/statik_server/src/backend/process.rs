@@ -0,0 +1,212 @@
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use statik_core::prelude::*;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    sync::{oneshot, watch, Mutex},
+    task::JoinHandle,
+    time,
+};
+
+use super::{transition, Backend, BackendStatus};
+use crate::config::ProcessConfig;
+
+/// Runs the real server as a child process of statik, stopping it (with its
+/// `stop_command`) when statik shuts down.
+#[derive(Debug)]
+pub struct ProcessBackend {
+    config: ProcessConfig,
+    status: Arc<watch::Sender<BackendStatus>>,
+
+    /// The running server, if it has been started.
+    process: Mutex<Option<Process>>,
+}
+
+/// A handle to the real server's process.
+#[derive(Debug)]
+struct Process {
+    stdin: Option<ChildStdin>,
+
+    /// Kills the process, if it hasn't exited already.
+    kill: Option<oneshot::Sender<()>>,
+
+    /// Waits for the process to exit, then marks the server as offline.
+    exited: JoinHandle<()>,
+}
+
+impl ProcessBackend {
+    pub fn new(config: ProcessConfig, status: Arc<watch::Sender<BackendStatus>>) -> Self {
+        Self {
+            config,
+            status,
+            process: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for ProcessBackend {
+    async fn start(&self) -> Result<()> {
+        let mut process = self.process.lock().await;
+
+        if process
+            .as_ref()
+            .is_some_and(|process| !process.exited.is_finished())
+        {
+            return Ok(());
+        }
+
+        let (program, args) = self
+            .config
+            .command
+            .split_first()
+            .context("no command to run the real server with was configured")?;
+
+        let mut command = Command::new(program);
+
+        command
+            .args(args)
+            .envs(&self.config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // Should statik exit without shutting down properly, the server
+            // shouldn't be left running without anything to stop it.
+            .kill_on_drop(true);
+
+        if let Some(working_directory) = &self.config.working_directory {
+            command.current_dir(working_directory);
+        }
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to run \"{}\"", self.config.command.join(" ")))?;
+
+        info!(
+            "Started the real server with \"{}\" (pid {}).",
+            self.config.command.join(" "),
+            child.id().unwrap_or_default()
+        );
+
+        transition(&self.status, |_| BackendStatus::Starting);
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(log_output(stdout));
+        }
+
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(log_output(stderr));
+        }
+
+        let stdin = child.stdin.take();
+        let (kill, killed) = oneshot::channel();
+        let status = self.status.clone();
+
+        let exited = tokio::spawn(async move {
+            let exit = tokio::select! {
+                exit = child.wait() => exit,
+                Ok(()) = killed => match child.kill().await {
+                    Ok(()) => child.wait().await,
+                    Err(e) => Err(e),
+                },
+            };
+
+            match exit {
+                Ok(exit) if exit.success() => info!("The real server has stopped."),
+                Ok(exit) => warn!("The real server has stopped: {exit}."),
+                Err(e) => error!("Failed to wait for the real server to stop: {e}"),
+            }
+
+            transition(&status, |_| BackendStatus::Offline);
+        });
+
+        *process = Some(Process {
+            stdin,
+            kill: Some(kill),
+            exited,
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        let Some(mut process) = self.process.lock().await.take() else {
+            return Ok(());
+        };
+
+        if process.exited.is_finished() {
+            return Ok(());
+        }
+
+        transition(&self.status, |_| BackendStatus::Stopping);
+
+        let asked = match (&mut process.stdin, self.config.stop_command.is_empty()) {
+            (Some(stdin), false) => {
+                info!("Stopping the real server...");
+
+                stdin
+                    .write_all(format!("{}\n", self.config.stop_command).as_bytes())
+                    .await
+                    .is_ok()
+            }
+            _ => false,
+        };
+
+        if asked {
+            let stop_timeout = Duration::from_secs(self.config.stop_timeout);
+
+            if time::timeout(stop_timeout, &mut process.exited)
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+
+            warn!(
+                "The real server didn't stop within {}s, so it's being killed.",
+                stop_timeout.as_secs()
+            );
+        } else {
+            info!("Killing the real server...");
+        }
+
+        if let Some(kill) = process.kill.take() {
+            let _ = kill.send(());
+        }
+
+        process.exited.await?;
+
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.stop().await
+    }
+
+    fn subscribe(&self) -> watch::Receiver<BackendStatus> {
+        self.status.subscribe()
+    }
+}
+
+/// Logs everything the real server writes to `output`, line by line.
+///
+/// The output has to be read even if nothing is logged, as the server would
+/// block once the pipe fills up.
+async fn log_output(output: impl AsyncRead + Unpin) {
+    let mut output = BufReader::new(output);
+    let mut line = vec![];
+
+    loop {
+        line.clear();
+
+        match output.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => debug!(
+                "(real server) {}",
+                String::from_utf8_lossy(&line).trim_end()
+            ),
+        }
+    }
+}
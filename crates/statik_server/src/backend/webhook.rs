@@ -0,0 +1,445 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use reqwest::{Client, Method, Response};
+use serde_json::Value;
+use statik_core::prelude::*;
+use tokio::{
+    sync::watch,
+    time::{self, MissedTickBehavior},
+};
+
+use super::{transition, Backend, BackendStatus};
+use crate::{
+    config::{WebhookConfig, WebhookRequest},
+    shutdown::Shutdown,
+};
+
+/// How long to wait before retrying a failed request for the first time.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Starts and stops the real server by calling HTTP endpoints, e.g. a game
+/// panel's API, a cloud function, or a home automation hook.
+#[derive(Debug)]
+pub struct WebhookBackend {
+    config: WebhookConfig,
+    status: Arc<watch::Sender<BackendStatus>>,
+    client: Client,
+
+    /// Whether the `start` request is being sent (or retried), during which
+    /// the real server isn't expected to have started yet.
+    sending_start: Arc<AtomicBool>,
+}
+
+impl WebhookBackend {
+    /// Creates the backend, and polls the `status` request (if there is one)
+    /// until `shutdown` is received.
+    pub fn spawn(
+        config: WebhookConfig,
+        status: Arc<watch::Sender<BackendStatus>>,
+        shutdown: Shutdown,
+    ) -> Result<Arc<Self>> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()?;
+
+        let backend = Arc::new(Self {
+            config,
+            status,
+            client,
+            sending_start: Arc::default(),
+        });
+
+        if backend.config.status.is_some() {
+            tokio::spawn(backend.clone().poll_status(shutdown));
+        }
+
+        Ok(backend)
+    }
+
+    /// Sends the `status` request every `poll_interval` until `shutdown` is
+    /// received, updating the real server's status from its response.
+    async fn poll_status(self: Arc<Self>, mut shutdown: Shutdown) {
+        let mut ticker = time::interval(Duration::from_secs(self.config.poll_interval.max(1)));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        while !shutdown.is_shutdown() {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if let Err(e) = self.refresh().await {
+                        warn!("Failed to check whether the real server is up: {e:#}");
+                    }
+                }
+                _ = shutdown.recv() => {}
+            }
+        }
+    }
+
+    /// Fetches the real server's state with the `status` request.
+    async fn refresh(&self) -> Result<()> {
+        let Some(request) = &self.config.status else {
+            return Ok(());
+        };
+
+        // Not retried, as it'll be sent again at the next poll anyway.
+        let body = send_once(&self.client, request, Method::GET)
+            .await?
+            .text()
+            .await?;
+        let state = read_state(&body, &self.config.state_path)?;
+
+        let is_one_of = |states: &[String]| states.iter().any(|s| s.eq_ignore_ascii_case(&state));
+
+        let new_status = if is_one_of(&self.config.online_states) {
+            BackendStatus::Online
+        } else if is_one_of(&self.config.starting_states) {
+            BackendStatus::Starting
+        } else if is_one_of(&self.config.stopping_states) {
+            BackendStatus::Stopping
+        } else {
+            BackendStatus::Offline
+        };
+
+        trace!("The real server's state is \"{state}\".");
+
+        let new_status = transition(&self.status, |status| match (status, new_status) {
+            (BackendStatus::Starting, BackendStatus::Offline)
+                if self.sending_start.load(Ordering::Relaxed) =>
+            {
+                status
+            }
+            _ => new_status,
+        });
+
+        if let Some(new_status) = new_status {
+            info!("The real server is now {new_status:?} (its state is \"{state}\").");
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for WebhookBackend {
+    async fn start(&self) -> Result<()> {
+        let starting = transition(&self.status, |status| match status {
+            BackendStatus::Offline | BackendStatus::Stopping => BackendStatus::Starting,
+            status => status,
+        });
+
+        if starting.is_none() {
+            return Ok(());
+        }
+
+        info!("Starting the real server with {}.", self.config.start.url);
+
+        self.sending_start.store(true, Ordering::Relaxed);
+
+        let client = self.client.clone();
+        let request = self.config.start.clone();
+        let retries = self.config.retries;
+        let status = self.status.clone();
+        let sending_start = self.sending_start.clone();
+
+        // Retrying can take a while, so happens in the background - players
+        // are already waiting for it to be up.
+        tokio::spawn(async move {
+            if let Err(e) = send(&client, &request, Method::POST, retries).await {
+                error!("Failed to start the real server: {e:#}");
+                transition(&status, |_| BackendStatus::Offline);
+            }
+
+            sending_start.store(false, Ordering::Relaxed);
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        let request = self
+            .config
+            .stop
+            .as_ref()
+            .context("no request to stop the real server with was configured")?;
+
+        let stopping = transition(&self.status, |status| match status {
+            BackendStatus::Starting | BackendStatus::Online => BackendStatus::Stopping,
+            status => status,
+        });
+
+        if stopping.is_none() {
+            return Ok(());
+        }
+
+        info!("Stopping the real server with {}.", request.url);
+
+        send(&self.client, request, Method::POST, self.config.retries).await?;
+
+        // Without a status request, there's nothing else to say when it has
+        // stopped.
+        if self.config.status.is_none() {
+            transition(&self.status, |_| BackendStatus::Offline);
+        }
+
+        Ok(())
+    }
+
+    fn subscribe(&self) -> watch::Receiver<BackendStatus> {
+        self.status.subscribe()
+    }
+}
+
+/// Sends `request`, retrying up to `retries` times with exponential backoff if
+/// it fails.
+async fn send(
+    client: &Client,
+    request: &WebhookRequest,
+    default_method: Method,
+    retries: u32,
+) -> Result<Response> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        match send_once(client, request, default_method.clone()).await {
+            Ok(response) => return Ok(response),
+            Err(e) if attempt > retries => return Err(e),
+            Err(e) => {
+                warn!(
+                    "Request to {} failed (attempt {attempt}), retrying in {}s: {e:#}",
+                    request.url,
+                    backoff.as_secs()
+                );
+
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn send_once(
+    client: &Client,
+    request: &WebhookRequest,
+    default_method: Method,
+) -> Result<Response> {
+    let method = match &request.method {
+        Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+            .with_context(|| format!("invalid HTTP method \"{method}\""))?,
+        None => default_method,
+    };
+
+    let mut builder = client.request(method, &request.url);
+
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+
+    Ok(builder.send().await?.error_for_status()?)
+}
+
+/// Reads the real server's state from the `status` response `body`, at
+/// `path`.
+///
+/// Paths are a small subset of JSONPath: object keys and array indices from
+/// the root `$`, e.g. "$.attributes.current_state" or "$.servers[0].status".
+/// The path "$" is the whole body, which is used as-is if it isn't JSON.
+fn read_state(body: &str, path: &str) -> Result<String> {
+    let path = path.trim().trim_start_matches('$');
+
+    let root: Value = match serde_json::from_str(body) {
+        Ok(root) => root,
+        Err(_) if path.is_empty() => return Ok(body.trim().to_string()),
+        Err(e) => return Err(e).context("the status response isn't JSON"),
+    };
+
+    let mut value = &root;
+
+    for segment in path.split(['.', '[']).filter(|segment| !segment.is_empty()) {
+        value = match segment.strip_suffix(']') {
+            Some(index) => index
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| value.get(index)),
+            None => value.get(segment),
+        }
+        .with_context(|| format!("the status response has nothing at \"${path}\""))?;
+    }
+
+    Ok(match value {
+        Value::String(state) => state.clone(),
+        value => value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::broadcast,
+    };
+
+    use super::*;
+    use crate::backend::tests::wait_for_status;
+
+    /// Answers every request on a local port with `status` and `body`, or
+    /// never at all if `status` is `None`. Returns its URL, and how many
+    /// requests it has been sent.
+    async fn endpoint(status: Option<u16>, body: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn({
+            let requests = requests.clone();
+
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut request = vec![];
+
+                    while !request.ends_with(b"\r\n\r\n") {
+                        if stream.read_buf(&mut request).await.unwrap_or(0) == 0 {
+                            break;
+                        }
+                    }
+
+                    requests.fetch_add(1, Ordering::SeqCst);
+
+                    let Some(status) = status else {
+                        // Held open until the test ends.
+                        tokio::spawn(async move { stream.read_u8().await });
+                        continue;
+                    };
+
+                    let response = format!(
+                        "HTTP/1.1 {status} Stub\r\nContent-Length: {}\r\nConnection: \
+                         close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn request(url: &str) -> WebhookRequest {
+        WebhookRequest {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn backend(config: WebhookConfig) -> (Arc<WebhookBackend>, broadcast::Sender<Option<String>>) {
+        let (notify_shutdown, _) = broadcast::channel(1);
+
+        let backend = WebhookBackend::spawn(
+            config,
+            Arc::new(watch::channel(BackendStatus::Offline).0),
+            Shutdown::new(notify_shutdown.subscribe()),
+        )
+        .unwrap();
+
+        (backend, notify_shutdown)
+    }
+
+    #[tokio::test]
+    async fn start_returns_straight_away() {
+        let (url, requests) = endpoint(None, "").await;
+
+        let (backend, _shutdown) = backend(WebhookConfig {
+            start: request(&url),
+            ..Default::default()
+        });
+
+        time::timeout(Duration::from_millis(500), backend.start())
+            .await
+            .expect("start waited for the request")
+            .unwrap();
+
+        assert_eq!(backend.status(), BackendStatus::Starting);
+
+        // Starting it again while it's starting doesn't send another request.
+        backend.start().await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_start_is_offline() {
+        let (url, requests) = endpoint(Some(500), "").await;
+
+        let (backend, _shutdown) = backend(WebhookConfig {
+            start: request(&url),
+            retries: 1,
+            ..Default::default()
+        });
+
+        backend.start().await.unwrap();
+        wait_for_status(backend.as_ref(), BackendStatus::Offline).await;
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn status_is_not_retried() {
+        let (url, requests) = endpoint(Some(500), "").await;
+
+        let (backend, _shutdown) = backend(WebhookConfig {
+            status: Some(request(&url)),
+            poll_interval: 60,
+            ..Default::default()
+        });
+
+        // Polling sends the first one straight away.
+        time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        assert!(backend.refresh().await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn status_from_state() {
+        let (url, _) = endpoint(Some(200), r#"{"attributes":{"state":"running"}}"#).await;
+
+        let (backend, _shutdown) = backend(WebhookConfig {
+            status: Some(request(&url)),
+            state_path: "$.attributes.state".to_string(),
+            ..Default::default()
+        });
+
+        wait_for_status(backend.as_ref(), BackendStatus::Online).await;
+    }
+
+    #[test]
+    fn reads_state() {
+        let body = r#"{"servers":[{"status":"starting"}],"up":true}"#;
+
+        assert_eq!(read_state(body, "$.servers[0].status").unwrap(), "starting");
+        assert_eq!(read_state(body, "$.up").unwrap(), "true");
+        assert_eq!(read_state("online\n", "$").unwrap(), "online");
+        assert!(read_state(body, "$.servers[1].status").is_err());
+        assert!(read_state("online", "$.status").is_err());
+    }
+}
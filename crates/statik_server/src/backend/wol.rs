@@ -0,0 +1,328 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use statik_core::prelude::*;
+use tokio::{
+    net::UdpSocket,
+    sync::watch,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use super::{transition, Backend, BackendStatus};
+use crate::config::WakeOnLanConfig;
+
+/// How long to wait between repeated magic packets.
+const REPEAT_DELAY: Duration = Duration::from_millis(100);
+
+/// How often the magic packets are sent again while the real server hasn't
+/// woken up yet.
+const RESEND_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wakes up the machine running the real server (e.g. a physical server which
+/// suspends itself when idle) by broadcasting a Wake-on-LAN magic packet.
+///
+/// Whether it has woken up is only known from checking its `address`, which
+/// is what turns it online. Until then the magic packets are sent again every
+/// so often, as there's no telling whether the earlier ones arrived, and it
+/// goes back offline if it doesn't wake up within the `wake_timeout`.
+#[derive(Debug)]
+pub struct WakeOnLanBackend {
+    config: WakeOnLanConfig,
+    status: Arc<watch::Sender<BackendStatus>>,
+
+    /// The magic packet: 6 bytes of 0xFF, then the MAC address 16 times.
+    packet: Arc<[u8]>,
+
+    resend_interval: Duration,
+}
+
+impl WakeOnLanBackend {
+    pub fn new(config: WakeOnLanConfig, status: Arc<watch::Sender<BackendStatus>>) -> Result<Self> {
+        let mac = parse_mac(&config.mac)
+            .with_context(|| format!("invalid Wake-on-LAN MAC address \"{}\"", config.mac))?;
+
+        let mut packet = vec![0xff; 6];
+        for _ in 0..16 {
+            packet.extend_from_slice(&mac);
+        }
+
+        Ok(Self {
+            config,
+            status,
+            packet: packet.into(),
+            resend_interval: RESEND_INTERVAL,
+        })
+    }
+}
+
+/// Sends the magic `packet` `repeat` times.
+async fn wake(config: &WakeOnLanConfig, packet: &[u8]) -> Result<()> {
+    let target = format!("{}:{}", config.broadcast_address, config.port);
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.set_broadcast(true)?;
+
+    for i in 0..config.repeat.max(1) {
+        if i > 0 {
+            time::sleep(REPEAT_DELAY).await;
+        }
+
+        socket
+            .send_to(packet, &target)
+            .await
+            .with_context(|| format!("failed to send the magic packet to {target}"))?;
+    }
+
+    Ok(())
+}
+
+/// Sends the magic `packet` again every `resend_interval` while the real
+/// server is starting, until it wakes up (or something else changes its
+/// `status`). After the `wake_timeout`, it's given up on and goes back
+/// offline, so the next player to join tries again.
+async fn keep_waking(
+    config: WakeOnLanConfig,
+    packet: Arc<[u8]>,
+    status: Arc<watch::Sender<BackendStatus>>,
+    resend_interval: Duration,
+) {
+    let mut changes = status.subscribe();
+
+    let give_up = time::sleep(Duration::from_secs(config.wake_timeout));
+    tokio::pin!(give_up);
+
+    let mut resend = time::interval_at(Instant::now() + resend_interval, resend_interval);
+    resend.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        // The status `wait_for` returns can't be held across an await.
+        let woken = async {
+            let _ = changes
+                .wait_for(|status| *status != BackendStatus::Starting)
+                .await;
+        };
+
+        tokio::select! {
+            _ = woken => return,
+            _ = &mut give_up => {
+                if transition(&status, |status| match status {
+                    BackendStatus::Starting => BackendStatus::Offline,
+                    status => status,
+                })
+                .is_some()
+                {
+                    warn!(
+                        "The real server ({}) didn't wake up within {}s.",
+                        config.mac, config.wake_timeout
+                    );
+                }
+
+                return;
+            }
+            _ = resend.tick() => {
+                trace!("Sending the magic packets to {} again.", config.mac);
+
+                if let Err(e) = wake(&config, &packet).await {
+                    warn!("Failed to wake up the real server again: {e:#}");
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for WakeOnLanBackend {
+    async fn start(&self) -> Result<()> {
+        if self.status() == BackendStatus::Online {
+            return Ok(());
+        }
+
+        // Players joining while it's starting send the magic packets again
+        // too, on top of the ones sent by `keep_waking`.
+        if transition(&self.status, |_| BackendStatus::Starting).is_some() {
+            info!("Waking up the real server ({}).", self.config.mac);
+
+            tokio::spawn(keep_waking(
+                self.config.clone(),
+                self.packet.clone(),
+                self.status.clone(),
+                self.resend_interval,
+            ));
+        }
+
+        if let Err(e) = wake(&self.config, &self.packet).await {
+            transition(&self.status, |_| BackendStatus::Offline);
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        bail!("Wake-on-LAN can only wake the real server's machine up, not put it to sleep");
+    }
+
+    fn subscribe(&self) -> watch::Receiver<BackendStatus> {
+        self.status.subscribe()
+    }
+}
+
+/// Parses a MAC address written as "aa:bb:cc:dd:ee:ff", "aa-bb-cc-dd-ee-ff",
+/// "aabb.ccdd.eeff" or "aabbccddeeff".
+pub(super) fn parse_mac(mac: &str) -> Result<[u8; 6]> {
+    let digits: String = mac
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | '.'))
+        .collect();
+
+    // `from_str_radix` would also accept a sign.
+    ensure!(
+        digits.len() == 12 && digits.chars().all(|c| c.is_ascii_hexdigit()),
+        "expected 6 bytes in hex"
+    );
+
+    let mut bytes = [0; 6];
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)?;
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::tests::wait_for_status;
+
+    const MAC: [u8; 6] = [0xaa, 0xbb, 0xcc, 0x01, 0x23, 0xef];
+
+    #[test]
+    fn parses_macs() {
+        for mac in [
+            "aa:bb:cc:01:23:ef",
+            "AA-BB-CC-01-23-EF",
+            "aabb.cc01.23ef",
+            "aabbcc0123ef",
+        ] {
+            assert_eq!(parse_mac(mac).unwrap(), MAC, "{mac}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_macs() {
+        for mac in [
+            "",
+            "aa:bb:cc:01:23",
+            "aa:bb:cc:01:23:ef:00",
+            "aabbcc0123e",
+            "aa:bb:cc:01:23:eg",
+            "+a:bb:cc:01:23:ef",
+            "aa:bb:cc:01:23:é",
+        ] {
+            assert!(parse_mac(mac).is_err(), "{mac}");
+        }
+    }
+
+    #[test]
+    fn magic_packet() {
+        let status = Arc::new(watch::channel(BackendStatus::Offline).0);
+        let backend = WakeOnLanBackend::new(
+            WakeOnLanConfig {
+                mac: "aa:bb:cc:01:23:ef".to_string(),
+                ..Default::default()
+            },
+            status,
+        )
+        .unwrap();
+
+        assert_eq!(backend.packet.len(), 102);
+        assert_eq!(backend.packet[..6], [0xff; 6]);
+        assert!(backend.packet[6..].chunks(6).all(|chunk| chunk == MAC));
+    }
+
+    #[tokio::test]
+    async fn gives_up_if_it_never_wakes() {
+        // Nothing ever answers at the backend's address, so nothing turns it
+        // online.
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let status = Arc::new(watch::channel(BackendStatus::Offline).0);
+        let mut backend = WakeOnLanBackend::new(
+            WakeOnLanConfig {
+                mac: "aa:bb:cc:01:23:ef".to_string(),
+                broadcast_address: "127.0.0.1".to_string(),
+                port: receiver.local_addr().unwrap().port(),
+                repeat: 1,
+                wake_timeout: 1,
+            },
+            status,
+        )
+        .unwrap();
+        backend.resend_interval = Duration::from_millis(200);
+
+        let recv_packet = || async {
+            let mut packet = [0; 128];
+            let length = time::timeout(Duration::from_secs(5), receiver.recv(&mut packet))
+                .await
+                .expect("no magic packet was sent")
+                .unwrap();
+
+            assert_eq!(packet[..length], *backend.packet);
+        };
+
+        backend.start().await.unwrap();
+        assert_eq!(backend.status(), BackendStatus::Starting);
+
+        // Sent straight away, then again while it's starting.
+        for _ in 0..3 {
+            recv_packet().await;
+        }
+
+        wait_for_status(&backend, BackendStatus::Offline).await;
+
+        let mut packet = [0; 128];
+        while receiver.try_recv(&mut packet).is_ok() {}
+
+        // The next player to join tries again.
+        backend.start().await.unwrap();
+        assert_eq!(backend.status(), BackendStatus::Starting);
+        recv_packet().await;
+    }
+
+    #[tokio::test]
+    async fn stops_resending_once_awake() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let status = Arc::new(watch::channel(BackendStatus::Offline).0);
+        let mut backend = WakeOnLanBackend::new(
+            WakeOnLanConfig {
+                mac: "aa:bb:cc:01:23:ef".to_string(),
+                broadcast_address: "127.0.0.1".to_string(),
+                port: receiver.local_addr().unwrap().port(),
+                repeat: 1,
+                ..Default::default()
+            },
+            status.clone(),
+        )
+        .unwrap();
+        backend.resend_interval = Duration::from_millis(100);
+
+        backend.start().await.unwrap();
+
+        let mut packet = [0; 128];
+        receiver.recv(&mut packet).await.unwrap();
+
+        // What `monitor` does once the real server answers.
+        transition(&status, |_| BackendStatus::Online);
+
+        // Anything sent before the change was seen.
+        time::sleep(Duration::from_millis(200)).await;
+        while receiver.try_recv(&mut packet).is_ok() {}
+
+        time::sleep(Duration::from_millis(300)).await;
+        assert!(receiver.try_recv(&mut packet).is_err());
+        assert_eq!(backend.status(), BackendStatus::Online);
+    }
+}
@@ -0,0 +1,252 @@
+//! Answers the pings Bedrock Edition clients send to fill in their server
+//! list, over [RakNet](https://wiki.vg/Raknet_Protocol).
+
+use std::{net::SocketAddr, sync::Arc};
+
+use statik_core::prelude::*;
+use tokio::{net::UdpSocket, sync::RwLock};
+
+use crate::{config::ServerConfig, shutdown::Shutdown};
+
+/// Sent by clients looking for servers.
+const UNCONNECTED_PING: u8 = 0x01;
+
+/// Sent by clients looking for servers with open slots - answered the same
+/// way as [`UNCONNECTED_PING`].
+const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// Every offline (unconnected) RakNet message contains these bytes.
+const OFFLINE_MESSAGE_ID: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// The length of a ping: id, time, magic and client guid.
+const PING_LENGTH: usize = 1 + 8 + 16 + 8;
+
+/// Answers Bedrock pings on `socket` until `shutdown` is received.
+pub async fn listen(socket: UdpSocket, config: Arc<RwLock<ServerConfig>>, mut shutdown: Shutdown) {
+    let server_guid = config
+        .read()
+        .await
+        .bedrock
+        .server_guid
+        .unwrap_or_else(rand::random);
+
+    // Pings are tiny, but RakNet datagrams can be up to the MTU.
+    let mut buf = [0; 1500];
+
+    while !shutdown.is_shutdown() {
+        tokio::select! {
+            res = socket.recv_from(&mut buf) => {
+                let (length, address) = match res {
+                    Ok(res) => res,
+                    Err(e) => {
+                        error!("Failed to receive bedrock packet: {e}");
+                        continue;
+                    }
+                };
+
+                let Some(time) = parse_ping(&buf[..length]) else {
+                    trace!("ignoring {length} byte bedrock packet from {address}.");
+                    continue;
+                };
+
+                debug!("Bedrock ping from {address}.");
+
+                if let Err(e) = respond(&socket, address, time, server_guid, &config).await {
+                    warn!("Failed to respond to bedrock ping from {address}: {e:#}");
+                }
+            }
+            _ = shutdown.recv() => {}
+        }
+    }
+}
+
+/// Returns the time sent in an unconnected ping, or `None` if `packet` isn't
+/// one.
+fn parse_ping(packet: &[u8]) -> Option<i64> {
+    if packet.len() < PING_LENGTH
+        || !matches!(
+            packet[0],
+            UNCONNECTED_PING | UNCONNECTED_PING_OPEN_CONNECTIONS
+        )
+        || packet[9..25] != OFFLINE_MESSAGE_ID
+    {
+        return None;
+    }
+
+    Some(i64::from_be_bytes(packet[1..9].try_into().ok()?))
+}
+
+async fn respond(
+    socket: &UdpSocket,
+    address: SocketAddr,
+    time: i64,
+    server_guid: u64,
+    config: &RwLock<ServerConfig>,
+) -> Result<()> {
+    let server_id = server_id(&*config.read().await, server_guid);
+
+    ensure!(
+        server_id.len() <= u16::MAX as usize,
+        "server id is too long ({} bytes)",
+        server_id.len()
+    );
+
+    let mut pong = Vec::with_capacity(1 + 8 + 8 + 16 + 2 + server_id.len());
+
+    pong.push(UNCONNECTED_PONG);
+    pong.extend_from_slice(&time.to_be_bytes());
+    pong.extend_from_slice(&server_guid.to_be_bytes());
+    pong.extend_from_slice(&OFFLINE_MESSAGE_ID);
+    pong.extend_from_slice(&(server_id.len() as u16).to_be_bytes());
+    pong.extend_from_slice(server_id.as_bytes());
+
+    socket.send_to(&pong, address).await?;
+
+    trace!("(↑) bedrock pong sent to {address}: {server_id}");
+
+    Ok(())
+}
+
+/// Builds the "MCPE" string Bedrock clients show in their server list, e.g.
+/// `MCPE;A Statik server!;594;1.20.10;0;20;1234;Statik;Survival;1;19132;19133;`
+fn server_id(config: &ServerConfig, server_guid: u64) -> String {
+    // Fields are separated by semicolons, so they can't contain any.
+    let escape = |field: &str| field.replace(';', ",");
+
+    let mut motd = config.mc.motd.lines();
+    let line_1 = escape(motd.next().unwrap_or_default());
+    let line_2 = escape(motd.next().unwrap_or("Statik"));
+
+    let bedrock = &config.bedrock;
+
+    // Nobody is ever actually playing on statik.
+    let online = 0;
+
+    format!(
+        "MCPE;{line_1};{};{};{online};{};{server_guid};{line_2};{};1;{};{};",
+        bedrock.protocol_version,
+        escape(&bedrock.version),
+        config.mc.max_players,
+        escape(&bedrock.game_mode),
+        bedrock.port,
+        bedrock.port_v6,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME: i64 = 0x0102_0304_0506_0708;
+
+    const CLIENT_GUID: u64 = 0x1122_3344_5566_7788;
+
+    fn ping(id: u8) -> Vec<u8> {
+        let mut ping = vec![id];
+        ping.extend_from_slice(&TIME.to_be_bytes());
+        ping.extend_from_slice(&OFFLINE_MESSAGE_ID);
+        ping.extend_from_slice(&CLIENT_GUID.to_be_bytes());
+        ping
+    }
+
+    #[test]
+    fn parses_pings() {
+        assert_eq!(parse_ping(&ping(UNCONNECTED_PING)), Some(TIME));
+        assert_eq!(
+            parse_ping(&ping(UNCONNECTED_PING_OPEN_CONNECTIONS)),
+            Some(TIME)
+        );
+
+        // Anything after the client's guid is ignored.
+        let mut padded = ping(UNCONNECTED_PING);
+        padded.extend_from_slice(&[0; 8]);
+        assert_eq!(parse_ping(&padded), Some(TIME));
+    }
+
+    #[test]
+    fn ignores_other_packets() {
+        let valid = ping(UNCONNECTED_PING);
+
+        // Truncated anywhere, including right before the client's guid ends.
+        for length in [0, 1, 9, 25, PING_LENGTH - 1] {
+            assert_eq!(parse_ping(&valid[..length]), None, "{length} bytes");
+        }
+
+        // A connected packet, or an open connection request.
+        for id in [0x00, 0x05, UNCONNECTED_PONG] {
+            assert_eq!(parse_ping(&ping(id)), None, "id {id:#04x}");
+        }
+
+        let mut bad_magic = valid.clone();
+        bad_magic[9] = 0xff;
+        assert_eq!(parse_ping(&bad_magic), None);
+
+        let mut bad_magic = valid;
+        bad_magic[24] = 0x00;
+        assert_eq!(parse_ping(&bad_magic), None);
+    }
+
+    #[test]
+    fn server_id_fields() {
+        let mut config = ServerConfig::default();
+        config.mc.motd = "First; line\nSecond line\nThird line".to_string();
+        config.mc.max_players = 50;
+        config.bedrock.protocol_version = 600;
+        config.bedrock.version = "1.20.30".to_string();
+        config.bedrock.game_mode = "Creative".to_string();
+        config.bedrock.port = 19000;
+        config.bedrock.port_v6 = 19001;
+
+        assert_eq!(
+            server_id(&config, 42),
+            "MCPE;First, line;600;1.20.30;0;50;42;Second line;Creative;1;19000;19001;"
+        );
+    }
+
+    #[test]
+    fn server_id_defaults() {
+        assert_eq!(
+            server_id(&ServerConfig::default(), u64::MAX),
+            format!(
+                "MCPE;A Statik server!;594;1.20.10;0;20;{};Statik;Survival;1;19132;19133;",
+                u64::MAX
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn pong_layout() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let config = RwLock::new(ServerConfig::default());
+        let server_guid = 0x0a0b_0c0d_0e0f_1011;
+
+        respond(
+            &server,
+            client.local_addr().unwrap(),
+            TIME,
+            server_guid,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        let mut pong = [0; 1500];
+        let length = client.recv(&mut pong).await.unwrap();
+        let pong = &pong[..length];
+
+        let server_id = server_id(&*config.read().await, server_guid);
+
+        assert_eq!(pong[0], UNCONNECTED_PONG);
+        assert_eq!(pong[1..9], TIME.to_be_bytes());
+        assert_eq!(pong[9..17], server_guid.to_be_bytes());
+        assert_eq!(pong[17..33], OFFLINE_MESSAGE_ID);
+        assert_eq!(pong[33..35], (server_id.len() as u16).to_be_bytes());
+        assert_eq!(&pong[35..], server_id.as_bytes());
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    fmt::Debug,
+    io::Cursor,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use statik_core::prelude::*;
+use statik_proto::{
+    c2s::{C2SHandshakePacket, C2SLoginPacket, C2SPlayPacket, C2SStatusPacket},
+    s2c::{S2CLoginPacket, S2CPlayPacket, S2CStatusPacket},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::{self, Instant},
+};
+use tokio_util::codec::Decoder;
+
+/// How long to keep printing what the server sends after the last packet has
+/// been replayed.
+const REPLAY_LINGER: Duration = Duration::from_secs(5);
+
+/// Which way a frame was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Sent by the client to the server.
+    C2S,
+    /// Sent by the server to the client.
+    S2C,
+}
+
+impl Direction {
+    fn arrow(&self) -> &'static str {
+        match self {
+            Direction::C2S => "→",
+            Direction::S2C => "←",
+        }
+    }
+}
+
+/// A single frame sent over a captured connection. Captures are stored as one
+/// of these per line, encoded as JSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// When the frame was sent, in microseconds since the unix epoch.
+    pub time: u64,
+
+    pub direction: Direction,
+
+    /// The state of the connection the frame was sent in.
+    pub state: State,
+
+    /// The packet's id.
+    pub id: i32,
+
+    /// The name of the packet, if statik knows of it.
+    pub name: Option<String>,
+
+    /// The whole frame (the packet id followed by the packet's data),
+    /// uncompressed and base64 encoded.
+    pub data: String,
+}
+
+impl Record {
+    pub fn frame(&self) -> Result<Vec<u8>> {
+        Ok(BASE64_STANDARD.decode(&self.data)?)
+    }
+}
+
+/// Writes every frame sent over a connection to a capture file.
+#[derive(Debug)]
+pub struct Capture {
+    file: File,
+    path: PathBuf,
+}
+
+impl Capture {
+    /// Creates a new capture file in `directory` for the connection from
+    /// `address`.
+    pub async fn create(directory: &str, address: SocketAddr) -> Result<Self> {
+        fs::create_dir_all(directory)
+            .await
+            .with_context(|| format!("failed to create the capture directory \"{directory}\""))?;
+
+        let path = Path::new(directory).join(format!(
+            "{}-{}-{}.jsonl",
+            now_micros(),
+            address.ip(),
+            address.port()
+        ));
+
+        let file = File::create(&path)
+            .await
+            .with_context(|| format!("failed to create capture file \"{}\"", path.display()))?;
+
+        Ok(Self { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `frame` to the capture file. Each frame is flushed to the file
+    /// straight away, so nothing is lost if the connection (or statik) dies -
+    /// though it isn't synced, so may not have reached the disk if the whole
+    /// machine does.
+    pub async fn record(&mut self, direction: Direction, state: State, frame: &[u8]) -> Result<()> {
+        let id = VarInt::decode(Cursor::new(frame))?.0;
+
+        let record = Record {
+            time: now_micros(),
+            direction,
+            state,
+            id,
+            name: packet_name(direction, state, id).map(str::to_string),
+            data: BASE64_STANDARD.encode(frame),
+        };
+
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        self.file.write_all(&line).await?;
+
+        // Tokio writes files in the background, so without this the last
+        // records could still be waiting to be written.
+        self.file.flush().await?;
+
+        Ok(())
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// The name of the packet with `id` sent in `direction` and `state`, if statik
+/// knows of it.
+pub fn packet_name(direction: Direction, state: State, id: i32) -> Option<&'static str> {
+    match (direction, state) {
+        (Direction::C2S, State::Handshake) => C2SHandshakePacket::name_of(id),
+        (Direction::C2S, State::Status) => C2SStatusPacket::name_of(id),
+        (Direction::C2S, State::Login) => C2SLoginPacket::name_of(id),
+        (Direction::C2S, State::Play) => C2SPlayPacket::name_of(id),
+        (Direction::S2C, State::Handshake) => None,
+        (Direction::S2C, State::Status) => S2CStatusPacket::name_of(id),
+        (Direction::S2C, State::Login) => S2CLoginPacket::name_of(id),
+        (Direction::S2C, State::Play) => S2CPlayPacket::name_of(id),
+    }
+}
+
+/// Decodes `frame` with the packet group for `direction` and `state`, and
+/// formats it for printing.
+pub fn describe(direction: Direction, state: State, frame: &[u8]) -> String {
+    fn debug<P: Decode + Debug>(frame: &[u8]) -> String {
+        match P::decode(Cursor::new(frame)) {
+            Ok(packet) => format!("{packet:?}"),
+            Err(e) => format!("({} bytes, couldn't be decoded: {e:#})", frame.len()),
+        }
+    }
+
+    let id = VarInt::decode(Cursor::new(frame))
+        .map(|id| id.0)
+        .unwrap_or(-1);
+
+    let unknown = || format!("unknown packet {id:#04x} ({} bytes)", frame.len());
+
+    if packet_name(direction, state, id).is_none() {
+        return unknown();
+    }
+
+    match (direction, state) {
+        (Direction::C2S, State::Handshake) => debug::<C2SHandshakePacket>(frame),
+        (Direction::C2S, State::Status) => debug::<C2SStatusPacket>(frame),
+        (Direction::C2S, State::Login) => debug::<C2SLoginPacket>(frame),
+        (Direction::C2S, State::Play) => debug::<C2SPlayPacket>(frame),
+        (Direction::S2C, State::Handshake) => unknown(),
+        (Direction::S2C, State::Status) => debug::<S2CStatusPacket>(frame),
+        (Direction::S2C, State::Login) => debug::<S2CLoginPacket>(frame),
+        (Direction::S2C, State::Play) => debug::<S2CPlayPacket>(frame),
+    }
+}
+
+/// Reads every record in the capture file at `path`.
+pub async fn read(path: &Path) -> Result<Vec<Record>> {
+    let file = File::open(path)
+        .await
+        .with_context(|| format!("failed to open capture file \"{}\"", path.display()))?;
+
+    let mut lines = BufReader::new(file).lines();
+    let mut records = vec![];
+    let mut line_number = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        records.push(
+            serde_json::from_str(&line)
+                .with_context(|| format!("invalid record on line {line_number}"))?,
+        );
+    }
+
+    Ok(records)
+}
+
+/// Prints every packet in the capture file at `path`.
+pub async fn print(path: &Path) -> Result<()> {
+    let records = read(path).await?;
+
+    let Some(start) = records.first().map(|record| record.time) else {
+        println!("{} is empty.", path.display());
+        return Ok(());
+    };
+
+    for record in &records {
+        let elapsed = Duration::from_micros(record.time.saturating_sub(start));
+
+        println!(
+            "{:>9.3}s {} {:<9} {}",
+            elapsed.as_secs_f64(),
+            record.direction.arrow(),
+            format!("{:?}", record.state).to_lowercase(),
+            describe(record.direction, record.state, &record.frame()?)
+        );
+    }
+
+    Ok(())
+}
+
+/// Sends the client's side of the capture file at `path` to the server at
+/// `address`, with the same timing as when it was captured, printing
+/// everything the server sends back.
+///
+/// Connections which were encrypted can't be replayed, as the shared secret
+/// was encrypted with the key of the server they were captured on.
+pub async fn replay(path: &Path, address: &str) -> Result<()> {
+    use statik_proto::{
+        c2s::handshake::C2SHandshake,
+        s2c::login::{S2CLoginSuccess, S2CSetCompression},
+    };
+
+    let records = read(path).await?;
+
+    let mut stream = TcpStream::connect(address)
+        .await
+        .with_context(|| format!("failed to connect to {address}"))?;
+
+    let mut codec = PacketCodec::default();
+    let mut buffer = BytesMut::new();
+    let mut write_buffer = BytesMut::new();
+
+    // The state the server is in, used to decode what it sends.
+    let mut state = State::Handshake;
+
+    let start = Instant::now();
+    let captured_start = records
+        .first()
+        .map(|record| record.time)
+        .unwrap_or_default();
+
+    let mut sent = records
+        .iter()
+        .filter(|record| record.direction == Direction::C2S);
+
+    let mut next = sent.next();
+    let mut linger_until = None;
+
+    loop {
+        let due = match next {
+            Some(record) => {
+                start + Duration::from_micros(record.time.saturating_sub(captured_start))
+            }
+            None => *linger_until.get_or_insert_with(|| Instant::now() + REPLAY_LINGER),
+        };
+
+        tokio::select! {
+            res = stream.read_buf(&mut buffer) => {
+                if res? == 0 {
+                    println!("The server closed the connection.");
+                    return Ok(());
+                }
+
+                while let Some(frame) = codec.decode(&mut buffer)? {
+                    println!(
+                        "{:>9.3}s {} {:<9} {}",
+                        start.elapsed().as_secs_f64(),
+                        Direction::S2C.arrow(),
+                        format!("{state:?}").to_lowercase(),
+                        describe(Direction::S2C, state, &frame)
+                    );
+
+                    let id = VarInt::decode(Cursor::new(&frame[..]))?.0;
+
+                    if state == State::Login && id == S2CSetCompression::ID {
+                        let set_compression = S2CSetCompression::decode(Cursor::new(&frame[..]))?;
+                        let threshold = set_compression.threshold.0;
+
+                        codec.set_compression((threshold >= 0).then_some(threshold as usize));
+                    } else if state == State::Login && id == S2CLoginSuccess::ID {
+                        state = State::Play;
+                    }
+                }
+            }
+            _ = time::sleep_until(due) => {
+                let Some(record) = next else {
+                    return Ok(());
+                };
+
+                let frame = record.frame()?;
+
+                if record.name.as_deref() == Some("EncryptionResponse") {
+                    bail!("the captured connection was encrypted, so can't be replayed any further");
+                }
+
+                println!(
+                    "{:>9.3}s {} {:<9} {}",
+                    start.elapsed().as_secs_f64(),
+                    Direction::C2S.arrow(),
+                    format!("{:?}", record.state).to_lowercase(),
+                    describe(Direction::C2S, record.state, &frame)
+                );
+
+                if record.state == State::Handshake {
+                    if let Ok(C2SHandshakePacket::Handshake(C2SHandshake { next_state, .. })) =
+                        C2SHandshakePacket::decode(Cursor::new(&frame[..]))
+                    {
+                        state = next_state.next_state();
+                    }
+                }
+
+                codec.encode_frame(&frame, &mut write_buffer)?;
+                stream.write_all(&write_buffer).await?;
+                write_buffer.clear();
+
+                next = sent.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_are_written_straight_away() {
+        let directory = std::env::temp_dir().join(format!("statik-capture-{}", now_micros()));
+        let directory = directory.to_str().unwrap();
+
+        let mut capture = Capture::create(directory, "192.0.2.1:50000".parse().unwrap())
+            .await
+            .unwrap();
+
+        // A status request.
+        capture
+            .record(Direction::C2S, State::Status, &[0x00])
+            .await
+            .unwrap();
+
+        // Read while the capture is still open, as if statik had died.
+        let records = read(capture.path()).await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].direction, Direction::C2S);
+        assert_eq!(records[0].name.as_deref(), Some("StatusRequest"));
+        assert_eq!(records[0].frame().unwrap(), [0x00]);
+
+        fs::remove_dir_all(directory).await.unwrap();
+    }
+}
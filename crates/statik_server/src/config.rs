@@ -1,12 +1,29 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 #[serde(default)]
-
 pub struct ServerConfig {
     pub general: GeneralServerConfig,
     pub mc: McServerConfig,
     pub api: ApiServerConfig,
+    pub limbo: LimboConfig,
+    pub forwarding: ForwardingConfig,
+    pub backend: BackendConfig,
+    pub forge: ForgeConfig,
+    pub bedrock: BedrockConfig,
+    pub query: QueryConfig,
+    pub auth: AuthConfig,
+    pub proxy_protocol: ProxyProtocolConfig,
+    pub limits: LimitsConfig,
+    pub capture: CaptureConfig,
+
+    /// Profiles for the different hostnames players connect with, e.g. to
+    /// give "survival.example.com" and "creative.example.com" their own MOTD
+    /// and backend. Players whose hostname doesn't match any profile get the
+    /// settings above.
+    pub hosts: Vec<HostConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,10 +70,21 @@ pub struct McServerConfig {
     /// your actual minecraft server.
     pub motd: String,
 
+    /// The version name shown in the server list, e.g. "Paper 1.20.1".
+    /// Defaults to statik's minecraft version.
+    pub version: Option<String>,
+
     /// The maximum size (in bytes) that a packet can be.
     /// Defaults to 4096.
     pub max_packet_size: usize,
 
+    /// Packets at least this many bytes long are compressed, once a player
+    /// logs in. Negative values disable compression. Defaults to 256.
+    ///
+    /// Note: for continuity, this should probably match the
+    /// `network-compression-threshold` of your actual minecraft server.
+    pub compression_threshold: i32,
+
     /// The URI (unique reference identifier) corresponding to the ~~website
     /// link or~~ local file containing the server icon.
     ///
@@ -80,23 +108,100 @@ pub struct McServerConfig {
     /// and [Examples](https://github.com/Keats/tera/tree/master/examples) for possible
     /// templates.
     pub disconnect_msg: String,
+
+    /// The message players are disconnected with when they join while limbo
+    /// is disabled. `{username}` is replaced with their username. Defaults to
+    /// "{username}, the server is now starting. It will be up in around
+    /// 30s-1m!"
+    pub starting_msg: String,
+
+    /// Whether the status response says the server previews chat messages.
+    /// Only used by 1.19 to 1.19.2 clients. Defaults to none (not sent).
+    pub previews_chat: Option<bool>,
 }
 
 impl Default for McServerConfig {
     fn default() -> Self {
         Self {
             max_packet_size: 4096,
+            compression_threshold: 256,
             port: 25565,
             max_players: 20,
             hide_player_count: false,
             motd: "A Statik server!".to_string(),
+            version: None,
             icon: None,
             hidden: false,
             disconnect_msg: "Disconnected from the server.".to_string(),
+            starting_msg: "{username}, the server is now starting. It will be up in around 30s-1m!"
+                .to_string(),
+            previews_chat: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Whether every packet sent over each connection is written to a capture
+    /// file, which can be printed or replayed with `statik capture`. Defaults
+    /// to false.
+    ///
+    /// Note: captures include everything players send, including their
+    /// forwarded details from a proxy - only enable this while debugging.
+    pub enabled: bool,
+
+    /// The directory capture files are written to, one per connection.
+    /// Defaults to "captures".
+    pub directory: String,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "captures".to_string(),
         }
     }
 }
 
+/// Settings for players connecting with particular hostnames. Anything left
+/// unset falls back to the top level setting.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostConfig {
+    /// The hostnames this profile is used for, e.g. "survival.example.com".
+    /// "*.example.com" matches any subdomain of example.com, and "*" matches
+    /// any hostname. If several profiles match, an exact match is used over
+    /// the most specific wildcard.
+    pub hostnames: Vec<String>,
+
+    /// Overrides `mc.motd`.
+    pub motd: Option<String>,
+
+    /// Overrides `mc.icon`.
+    pub icon: Option<String>,
+
+    /// Overrides `mc.max_players`.
+    pub max_players: Option<i32>,
+
+    /// Overrides `mc.version`.
+    pub version: Option<String>,
+
+    /// Overrides `mc.disconnect_msg`.
+    pub disconnect_msg: Option<String>,
+
+    /// Overrides `mc.starting_msg`.
+    pub starting_msg: Option<String>,
+
+    /// Overrides `limbo.reconnect_msg`.
+    pub reconnect_msg: Option<String>,
+
+    /// Replaces the whole `backend` section, so these players are sent to
+    /// (and wait on) a different real server.
+    pub backend: Option<BackendConfig>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ApiServerConfig {
@@ -109,3 +214,647 @@ impl Default for ApiServerConfig {
         Self { port: 8080 }
     }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimboConfig {
+    /// Whether players who join while the real server is starting are held in
+    /// an empty "limbo" world, rather than being disconnected straight away.
+    /// Defaults to true.
+    ///
+    /// Note: only clients on the same protocol version as statik can join
+    /// the limbo world.
+    pub enabled: bool,
+
+    /// Roughly how long (in seconds) the real server takes to start up, used
+    /// for the countdown shown to players waiting in limbo. Defaults to 60.
+    pub wait_time: u64,
+
+    /// The text shown above the hotbar of players waiting in limbo. `{seconds}`
+    /// is replaced with the number of seconds left on the countdown.
+    ///
+    /// Defaults to "The server is starting, please wait... {seconds}s"
+    pub action_bar_msg: String,
+
+    /// The message players in limbo are disconnected with once the countdown
+    /// has finished. They have to reconnect by hand, as 1.20.1 clients can't
+    /// be sent to the real server with a transfer packet. Defaults to "The
+    /// server should now be up, please reconnect!"
+    pub reconnect_msg: String,
+}
+
+impl Default for LimboConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            wait_time: 60,
+            action_bar_msg: "The server is starting, please wait... {seconds}s".to_string(),
+            reconnect_msg: "The server should now be up, please reconnect!".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardingMode {
+    /// Players connect to statik directly. The default.
+    #[default]
+    None,
+
+    /// Players connect through a [Velocity](https://velocitypowered.com/) proxy
+    /// using "modern" forwarding, which sends their details in a login plugin
+    /// message signed with the forwarding secret. Players who connect directly
+    /// are disconnected.
+    Velocity,
+
+    /// Players connect through a BungeeCord proxy (or velocity in "legacy"
+    /// mode), which appends their details to the hostname in the handshake.
+    /// Players who connect directly are disconnected.
+    ///
+    /// Note: legacy forwarding isn't signed, so statik must not be reachable
+    /// other than through the proxy.
+    #[serde(rename = "bungeecord")]
+    BungeeCord,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardingConfig {
+    /// How player details (their address, UUID and skin) are forwarded by a
+    /// proxy in front of statik. Defaults to "none".
+    pub mode: ForwardingMode,
+
+    /// The secret shared with the proxy, used to check forwarded details
+    /// weren't forged. Must match the `forwarding.secret` file of Velocity,
+    /// and be set for velocity forwarding. Unused for BungeeCord forwarding.
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    /// The address ("host:port") of the real minecraft server statik is
+    /// standing in for. When set, statik checks whether it is up, and asks
+    /// players waiting in limbo to reconnect once it is. Defaults to none.
+    pub address: Option<String>,
+
+    /// Whether new connections are forwarded to the real server at `address`
+    /// while it is up, so players can keep connecting to statik's port.
+    /// While it is down, statik answers them itself. Defaults to false.
+    pub proxy: bool,
+
+    /// How often (in seconds) to check whether the real server is up.
+    /// Defaults to 5.
+    pub poll_interval: u64,
+
+    /// How statik checks whether the real server at `address` is up.
+    /// Defaults to "status".
+    pub check: ReadyCheck,
+
+    /// How statik starts the real server when a player tries to join while
+    /// it is down, e.g. `{ type = "process", command = ["java", "-jar",
+    /// "server.jar", "nogui"] }`. Defaults to none, in which case starting it
+    /// is left to something else.
+    pub launcher: Option<LauncherConfig>,
+
+    /// How long (in seconds) the real server can be up without any players
+    /// before the `launcher` stops it, or 0 to leave it running. Needs the
+    /// `address` to be set with the "status" `check`, to see how many
+    /// players are online. Defaults to 0.
+    pub idle_timeout: u64,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            address: None,
+            proxy: false,
+            poll_interval: 5,
+            check: ReadyCheck::default(),
+            launcher: None,
+            idle_timeout: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadyCheck {
+    /// Sends the real server a status request, so it's only considered up
+    /// once it's ready for players. The default.
+    #[default]
+    Status,
+
+    /// Only connects to the real server - e.g. to know when a machine woken
+    /// up by statik is back on the network.
+    Connect,
+}
+
+/// How statik starts (and stops) the real server, chosen by `type`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LauncherConfig {
+    /// Runs the real server as a child process of statik.
+    Process(ProcessConfig),
+
+    /// Scales a Kubernetes workload running the real server up from zero.
+    Kubernetes(KubernetesConfig),
+
+    /// Starts a Docker container running the real server.
+    Docker(DockerConfig),
+
+    /// Calls HTTP endpoints (e.g. a panel's API, or a cloud function) to start
+    /// and stop the real server.
+    Webhook(Box<WebhookConfig>),
+
+    /// Wakes up the (suspended) machine running the real server with a
+    /// Wake-on-LAN magic packet. The `address` must be set, to know when it
+    /// has woken up.
+    WakeOnLan(WakeOnLanConfig),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessConfig {
+    /// The program to run the real server with, followed by its arguments.
+    /// Defaults to `["java", "-jar", "server.jar", "nogui"]`.
+    pub command: Vec<String>,
+
+    /// The directory to run `command` in. Defaults to statik's working
+    /// directory.
+    pub working_directory: Option<String>,
+
+    /// Extra environment variables to run `command` with. Defaults to none.
+    pub env: HashMap<String, String>,
+
+    /// The console command written to the real server's standard input to
+    /// stop it gracefully. If empty, it is killed straight away instead.
+    /// Defaults to "stop".
+    pub stop_command: String,
+
+    /// How long (in seconds) to wait for the real server to exit after
+    /// `stop_command` before killing it. Defaults to 30.
+    pub stop_timeout: u64,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            command: ["java", "-jar", "server.jar", "nogui"]
+                .map(String::from)
+                .to_vec(),
+            working_directory: None,
+            env: HashMap::new(),
+            stop_command: "stop".to_string(),
+            stop_timeout: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KubernetesConfig {
+    /// The kind of workload running the real server. Defaults to
+    /// "deployment".
+    pub kind: WorkloadKind,
+
+    /// The name of the workload running the real server. Defaults to
+    /// "minecraft".
+    pub name: String,
+
+    /// The namespace the workload is in. Defaults to the namespace statik is
+    /// running in, or that of the kubeconfig's context.
+    pub namespace: Option<String>,
+
+    /// How many replicas the workload is scaled up to when starting the real
+    /// server. Defaults to 1.
+    pub replicas: i32,
+
+    /// The label selector (e.g. "app=minecraft") for the workload's pods,
+    /// which are watched to know when the real server is ready. Defaults to
+    /// the workload's own selector.
+    pub selector: Option<String>,
+
+    /// The path of the kubeconfig file used to connect to the cluster.
+    /// Defaults to using statik's service account when running in a cluster,
+    /// or `$KUBECONFIG` (or `~/.kube/config`) otherwise.
+    pub kubeconfig: Option<String>,
+
+    /// The kubeconfig context to use. Defaults to the current context.
+    pub context: Option<String>,
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> Self {
+        Self {
+            kind: WorkloadKind::default(),
+            name: "minecraft".to_string(),
+            namespace: None,
+            replicas: 1,
+            selector: None,
+            kubeconfig: None,
+            context: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DockerConfig {
+    /// The name (or id) of the container running the real server. It must
+    /// already exist - statik only starts and stops it. Defaults to
+    /// "minecraft".
+    pub container: String,
+
+    /// The path of the Docker Engine API's unix socket. Defaults to
+    /// "/var/run/docker.sock".
+    pub socket: String,
+
+    /// How long (in seconds) Docker waits for the real server to exit when
+    /// stopping it, before killing it. Defaults to 30.
+    pub stop_timeout: u64,
+
+    /// Whether the container's output is logged by statik (at debug level).
+    /// Defaults to false.
+    pub logs: bool,
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        Self {
+            container: "minecraft".to_string(),
+            socket: "/var/run/docker.sock".to_string(),
+            stop_timeout: 30,
+            logs: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// The request which starts the real server.
+    pub start: WebhookRequest,
+
+    /// The request which stops the real server. Defaults to none, in which
+    /// case statik can't stop it.
+    pub stop: Option<WebhookRequest>,
+
+    /// The request which fetches the real server's state, which is polled to
+    /// know when it's up. Defaults to none, in which case only `address` (if
+    /// set) is checked.
+    pub status: Option<WebhookRequest>,
+
+    /// Where to find the real server's state in the JSON response to the
+    /// `status` request, e.g. "$.attributes.current_state". Defaults to "$",
+    /// the whole response - which doesn't have to be JSON.
+    pub state_path: String,
+
+    /// The states meaning the real server is up. Defaults to `["running",
+    /// "online"]`.
+    pub online_states: Vec<String>,
+
+    /// The states meaning the real server is starting. Defaults to
+    /// `["starting"]`.
+    pub starting_states: Vec<String>,
+
+    /// The states meaning the real server is stopping. Defaults to
+    /// `["stopping"]`. Any other state means it is offline.
+    pub stopping_states: Vec<String>,
+
+    /// How often (in seconds) to send the `status` request. Defaults to 5.
+    pub poll_interval: u64,
+
+    /// How many times a failed `start` or `stop` request is retried, waiting
+    /// twice as long each time. The `status` request isn't, as it's sent
+    /// again soon anyway. Defaults to 3.
+    pub retries: u32,
+
+    /// How long (in seconds) to wait for a response to each request.
+    /// Defaults to 10.
+    pub timeout: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            start: WebhookRequest::default(),
+            stop: None,
+            status: None,
+            state_path: "$".to_string(),
+            online_states: vec!["running".to_string(), "online".to_string()],
+            starting_states: vec!["starting".to_string()],
+            stopping_states: vec!["stopping".to_string()],
+            poll_interval: 5,
+            retries: 3,
+            timeout: 10,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookRequest {
+    /// The request's HTTP method. Defaults to "GET" for `status` requests,
+    /// and "POST" otherwise.
+    pub method: Option<String>,
+
+    pub url: String,
+
+    /// Headers to send with the request, e.g. `{ Authorization = "Bearer
+    /// ..." }`. Defaults to none.
+    pub headers: HashMap<String, String>,
+
+    /// The request's body. Defaults to none.
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WakeOnLanConfig {
+    /// The MAC address of the real server's network interface, e.g.
+    /// "aa:bb:cc:dd:ee:ff".
+    pub mac: String,
+
+    /// Where the magic packet is sent. A subnet's own broadcast address (e.g.
+    /// "192.168.1.255") may be needed if statik's machine has several
+    /// networks. Defaults to "255.255.255.255".
+    pub broadcast_address: String,
+
+    /// The port the magic packet is sent to. Defaults to 9.
+    pub port: u16,
+
+    /// How many magic packets are sent each time, in case some are lost.
+    /// Defaults to 3.
+    pub repeat: u32,
+
+    /// How long (in seconds) to keep sending magic packets for while the
+    /// real server doesn't answer at the backend's `address`. After that it's
+    /// treated as offline again, so the next player to join tries to wake it.
+    /// Defaults to 120.
+    pub wake_timeout: u64,
+}
+
+impl Default for WakeOnLanConfig {
+    fn default() -> Self {
+        Self {
+            mac: String::new(),
+            broadcast_address: "255.255.255.255".to_string(),
+            port: 9,
+            repeat: 3,
+            wake_timeout: 120,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkloadKind {
+    #[default]
+    Deployment,
+    StatefulSet,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// How many connections (mc and api) can be open at once - any more are
+    /// closed straight away. Defaults to 1024.
+    pub max_connections: usize,
+
+    /// How many new connections each address can open per second, once it
+    /// has used up its `connection_burst`. Behind a forwarding proxy, players
+    /// are limited by the address it forwards once they log in instead. 0
+    /// disables the limit. Defaults to 2.
+    pub connection_rate: f64,
+
+    /// How many connections each address can open in quick succession.
+    /// Defaults to 10.
+    pub connection_burst: u32,
+
+    /// How many status requests (server list pings) each address can send
+    /// per second, once it has used up its `status_burst`. Not applied behind
+    /// a forwarding proxy, as its requests are all from the same address. 0
+    /// disables the limit. Defaults to 1.
+    pub status_rate: f64,
+
+    /// How many status requests each address can send in quick succession.
+    /// Defaults to 5.
+    pub status_burst: u32,
+
+    /// How long (in seconds) clients have to send their handshake, and to
+    /// finish a status request after it. Defaults to 5.
+    pub handshake_timeout: u64,
+
+    /// How long (in seconds) clients have to finish logging in after their
+    /// handshake. Defaults to 30.
+    pub login_timeout: u64,
+
+    /// How long (in seconds) a connection can go without sending anything
+    /// before it is closed. Defaults to 30.
+    pub read_timeout: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            connection_rate: 2.0,
+            connection_burst: 10,
+            status_rate: 1.0,
+            status_burst: 5,
+            handshake_timeout: 5,
+            login_timeout: 30,
+            read_timeout: 30,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyProtocolConfig {
+    /// Whether connections from `trusted_sources` start with a [PROXY protocol](https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt)
+    /// (v1 or v2) header, as sent by HAProxy and most TCP load balancers, to
+    /// find out the real address of whoever connected. Applies to both the mc
+    /// and api listeners. Defaults to false.
+    pub enabled: bool,
+
+    /// The addresses (e.g. "10.0.0.1") or CIDR ranges (e.g. "10.0.0.0/8") of
+    /// the load balancers in front of statik. Connections from anywhere else
+    /// are treated as direct, so can't fake their address with a header of
+    /// their own. Defaults to none.
+    pub trusted_sources: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Whether players must prove they own the account they join with, like
+    /// `online-mode` in `server.properties`. Defaults to false.
+    ///
+    /// Note: ignored when player details are forwarded by a proxy, which
+    /// should authenticate players itself.
+    pub online_mode: bool,
+
+    /// The base URL of the session server players are authenticated with.
+    /// Defaults to "https://sessionserver.mojang.com".
+    pub session_server: String,
+
+    /// Whether the session server also checks players join from the address
+    /// they authenticated from, like `prevent-proxy-connections`. Defaults to
+    /// false.
+    pub prevent_proxy_connections: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            online_mode: false,
+            session_server: "https://sessionserver.mojang.com".to_string(),
+            prevent_proxy_connections: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryConfig {
+    /// Whether statik answers [Query](https://wiki.vg/Query) requests (like
+    /// `enable-query` in `server.properties`), used by server list websites
+    /// and monitoring tools. Queries don't include a hostname, so they get the
+    /// host profile matching the address they were sent to, if any. Defaults
+    /// to false.
+    pub enabled: bool,
+
+    /// The UDP port query requests are sent to. Defaults to 25565.
+    pub port: u16,
+
+    /// The map name reported to query clients. Defaults to "world".
+    pub map: String,
+
+    /// The plugins reported to query clients, in the format used by bukkit
+    /// servers: "Server name: plugin 1; plugin 2". Defaults to none.
+    pub plugins: String,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 25565,
+            map: "world".to_string(),
+            plugins: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BedrockConfig {
+    /// Whether statik answers pings from Bedrock Edition clients (e.g. players
+    /// joining through [Geyser](https://geysermc.org/)), so the server shows
+    /// as online in their server list. Defaults to false.
+    pub enabled: bool,
+
+    /// The UDP port Bedrock clients ping. Defaults to 19132.
+    pub port: u16,
+
+    /// The UDP port advertised for IPv6 connections. Defaults to 19133.
+    pub port_v6: u16,
+
+    /// The Bedrock protocol version advertised to clients. Defaults to 594
+    /// (1.20.10).
+    ///
+    /// Note: for continuity, this should probably match the version Geyser
+    /// (or your bedrock server) supports.
+    pub protocol_version: i32,
+
+    /// The Bedrock version name advertised to clients. Defaults to
+    /// "1.20.10".
+    pub version: String,
+
+    /// The game mode shown to clients. Defaults to "Survival".
+    pub game_mode: String,
+
+    /// The unique id of this server. Defaults to a random id, picked each
+    /// time statik starts.
+    pub server_guid: Option<u64>,
+}
+
+impl Default for BedrockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 19132,
+            port_v6: 19133,
+            protocol_version: 594,
+            version: "1.20.10".to_string(),
+            game_mode: "Survival".to_string(),
+            server_guid: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForgeConfig {
+    /// Whether statik tells modded clients it is a Forge (or NeoForge) server
+    /// in its status response, so they show it as compatible in the server
+    /// list. Defaults to false.
+    ///
+    /// Note: for continuity, the mods and channels should match those of
+    /// your actual minecraft server.
+    pub enabled: bool,
+
+    /// The version of Forge's networking protocol the server uses - 2 for
+    /// Forge 1.13 to 1.17.1, 3 for 1.18 and above. Defaults to 3.
+    pub fml_network_version: i32,
+
+    /// Whether the mods and channels are sent compressed, as Forge does from
+    /// 1.18.2 onwards. Defaults to true.
+    pub compressed: bool,
+
+    /// Whether to also send the mod list in the format used by Forge 1.12.2
+    /// and below. Defaults to false.
+    pub legacy_modinfo: bool,
+
+    /// The mods installed on the server. Defaults to none.
+    pub mods: Vec<ForgeModConfig>,
+
+    /// The network channels registered by the server's mods. Defaults to none.
+    pub channels: Vec<ForgeChannelConfig>,
+}
+
+impl Default for ForgeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fml_network_version: 3,
+            compressed: true,
+            legacy_modinfo: false,
+            mods: vec![],
+            channels: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForgeModConfig {
+    /// The mod's id, e.g. "examplemod".
+    pub id: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForgeChannelConfig {
+    /// The channel's name, e.g. "examplemod:main".
+    pub name: String,
+    pub version: String,
+    /// Whether clients must also have this channel to join. Defaults to true.
+    #[serde(default = "default_true")]
+    pub required: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
@@ -1,22 +1,49 @@
 use std::{
+    fmt,
     io::{self, Cursor, ErrorKind},
     net::SocketAddr,
     sync::Arc,
+    time::Duration,
 };
 
-use bytes::{Buf, BytesMut};
+use bytes::{Bytes, BytesMut};
 use statik_core::prelude::*;
 use statik_proto::{
-    c2s::C2SPacket,
-    s2c::status::response::{Players, StatusResponse},
+    c2s::{
+        handshake::FmlVersion, C2SHandshakePacket, C2SLoginPacket, C2SPlayPacket, C2SStatusPacket,
+    },
+    s2c::status::{
+        forge::{ForgeChannel, ForgeData, ForgeMod, ModInfo, ModInfoEntry},
+        response::{Players, StatusResponse, Version},
+    },
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
-    sync::RwLock,
+    sync::{watch, RwLock},
+    time::{self, Instant},
 };
+use tokio_util::codec::{Decoder, Encoder};
 
-use crate::config::ServerConfig;
+use crate::{
+    auth::{self, ServerKey},
+    backend::{self, BackendStatus, Backends},
+    capture::{Capture, Direction},
+    config::{ForwardingMode, ServerConfig},
+    forwarding::{self, VELOCITY_CHANNEL, VELOCITY_FORWARDING_VERSION},
+    limbo,
+    limbo::Limbo,
+    limits::Limits,
+    metrics::Metrics,
+    player::Player,
+    proxy,
+    status_cache::{StatusCache, StatusKey},
+    vhost::{self, Profile},
+};
+
+/// The message id of the login plugin request asking velocity for the
+/// player's details.
+const VELOCITY_MESSAGE_ID: i32 = 0;
 
 #[allow(unused)]
 /// Checks if a username COULD be a valid minecraft account's username.
@@ -32,11 +59,119 @@ fn is_valid_username(username: &str) -> bool {
             .all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
+/// Waits for the next limbo tick, or forever if the player isn't in limbo.
+async fn limbo_tick(limbo: &mut Option<Limbo>) {
+    match limbo {
+        Some(limbo) => limbo.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// What players logging in through a forwarding proxy are told when they're
+/// connecting too often.
+const RATE_LIMITED_MSG: &str = "You are connecting too often, please wait a moment.";
+
+/// What the client is told when it's disconnected for sending something
+/// invalid. The details are only logged, as they can include internal errors.
+const PROTOCOL_ERROR_MSG: &str = "Protocol error";
+
+/// Added as context to errors caused by something the client sent, rather
+/// than by the connection or the server itself: a packet that couldn't be
+/// decoded, or one that doesn't make sense in the current state.
+///
+/// Only these close the connection with [`Connection::protocol_error`].
+#[derive(Debug)]
+struct ProtocolError;
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("protocol error")
+    }
+}
+
+/// The details of a player who has been sent an encryption request.
+#[derive(Debug)]
+struct PendingEncryption {
+    username: String,
+    verify_token: [u8; 4],
+}
+
+/// Builds the status response sent to clients from the config, using the
+/// host profile at `host` in `config.hosts` if there is one.
+pub(crate) fn status_response(
+    config: &ServerConfig,
+    host: Option<usize>,
+) -> Result<StatusResponse> {
+    let profile = Profile::new(config, host);
+
+    let mut response = StatusResponse::new(
+        Players::new(profile.max_players(), 0, vec![]),
+        Chat::new(profile.motd().to_string()),
+        profile.icon().map(str::to_string),
+        false,
+    );
+
+    if let Some(version) = profile.version() {
+        response =
+            response.with_version(Version::new(version.to_string(), PROTOCOL_VERSION as i32));
+    }
+
+    if let Some(previews_chat) = config.mc.previews_chat {
+        response = response.with_previews_chat(previews_chat);
+    }
+
+    let forge = &config.forge;
+
+    if !forge.enabled {
+        return Ok(response);
+    }
+
+    let mods = forge
+        .mods
+        .iter()
+        .map(|m| ForgeMod {
+            mod_id: m.id.clone(),
+            modmarker: m.version.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    let channels = forge
+        .channels
+        .iter()
+        .map(|channel| ForgeChannel {
+            res: channel.name.clone(),
+            version: channel.version.clone(),
+            required: channel.required,
+        })
+        .collect::<Vec<_>>();
+
+    if forge.legacy_modinfo {
+        response = response.with_mod_info(ModInfo::new(
+            forge
+                .mods
+                .iter()
+                .map(|m| ModInfoEntry {
+                    modid: m.id.clone(),
+                    version: m.version.clone(),
+                })
+                .collect(),
+        ));
+    }
+
+    let forge_data = match forge.compressed {
+        true => ForgeData::compressed(forge.fml_network_version, mods, channels)?,
+        false => ForgeData::new(forge.fml_network_version, mods, channels),
+    };
+
+    Ok(response.with_forge_data(forge_data))
+}
+
 /// Send and receive `Frame` values from a minecraft client.
 ///
 /// When implementing networking protocols, a message on that protocol is
 /// often composed of several smaller messages known as frames. The purpose of
-/// `Connection` is to read and write frames on the underlying `TcpStream`.
+/// `Connection` is to read and write frames on the underlying `TcpStream` (or
+/// any other stream, e.g. an in-memory one in tests).
 ///
 /// To read frames, the `Connection` uses an internal buffer, which is filled
 /// up until there are enough bytes to create a full frame. Once this happens,
@@ -45,180 +180,510 @@ fn is_valid_username(username: &str) -> bool {
 /// When sending frames, the frame is first encoded into the write buffer.
 /// The contents of the write buffer are then written to the socket.
 #[derive(Debug)]
-pub struct Connection {
+pub struct Connection<S = TcpStream> {
     config: Arc<RwLock<ServerConfig>>,
 
-    // /// All the data accociated with the client after they have connected, including
-    // /// their username, UUID, (in the future) items, ect. Defaults to None, as this data
-    // /// isn't sent with a status request, only on login.
-    // pub player: Option<Player>,
+    /// Whether each of the real servers is up.
+    backends: Arc<Backends>,
+
+    /// Status responses which have already been built, shared by every
+    /// connection.
+    status_cache: Arc<StatusCache>,
+
+    /// Whether the real server for this connection's host profile is up, used
+    /// to move players out of limbo once it is.
+    backend_status: watch::Receiver<BackendStatus>,
+
+    /// The index in `config.hosts` of the profile picked by the hostname the
+    /// client connected with. `None` if no profile matched, in which case the
+    /// top level settings are used.
+    host: Option<usize>,
+
+    /// The keypair used to authenticate players. `None` unless online mode is
+    /// enabled.
+    server_key: Option<Arc<ServerKey>>,
+
+    /// Rate limits and timeouts shared by every connection, along with the
+    /// metrics they're reported in.
+    limits: Arc<Limits>,
+
+    /// When the client has to finish its handshake, status request or login
+    /// by. `None` once the player is in the play state.
+    stage_deadline: Option<Instant>,
+
+    /// All the data accociated with the client after they have connected,
+    /// including their username, UUID, (in the future) items, ect. Defaults
+    /// to None, as this data isn't sent with a status request, only on login.
+    pub player: Option<Player>,
 
-    // The `TcpStream`. It is decorated with a `BufWriter`, which provides write
-    // level buffering. The `BufWriter` implementation provided by Tokio is
-    // sufficient for our needs.
-    pub stream: BufWriter<TcpStream>,
+    /// The `TcpStream`. Writes are buffered in `write_buffer` instead of a
+    /// `BufWriter`, so packets are encoded straight into the buffer that is
+    /// sent.
+    pub stream: S,
 
-    /// The address that the connection comes from.
+    /// The address that the connection comes from - or if the player's
+    /// details were forwarded by a proxy, the address the player connected
+    /// to the proxy from.
     pub address: SocketAddr,
 
+    /// The address of the proxy the player connected through, if their
+    /// details were forwarded by one.
+    pub proxy_address: Option<SocketAddr>,
+
     /// The buffer for reading frames.
     pub buffer: BytesMut,
 
-    /// Buffer used for queuing and sending bytes.
-    queue: Vec<u8>,
+    /// Splits the bytes read into frames, and writes packets.
+    codec: PacketCodec,
 
-    ///staging
-    staging: Vec<u8>,
+    /// Buffer packets are encoded into before being sent. Packets written
+    /// while handling what the client sent are sent together once it has all
+    /// been handled.
+    write_buffer: BytesMut,
 
     /// Current state of the handler: should go from 0 (Handshake) to 1 (status)
     /// or to 2 (login, which then goes to 3 (play))
     pub state: State,
+
+    /// The protocol version the client sent in its handshake.
+    pub protocol_version: i32,
+
+    /// The version of Forge's networking the client supports, if it is a
+    /// modded client.
+    pub fml_version: Option<FmlVersion>,
+
+    /// Set while the player is being held in the limbo world, waiting for the
+    /// real server to start.
+    limbo: Option<Limbo>,
+
+    /// The id of the login plugin request sent to velocity, while waiting for
+    /// it to respond with the player's details.
+    velocity_message_id: Option<i32>,
+
+    /// Set after sending an encryption request, while waiting for the client
+    /// to respond.
+    pending_encryption: Option<PendingEncryption>,
+
+    /// Where every frame sent over the connection is recorded, if
+    /// `config.capture` is enabled.
+    capture: Option<Capture>,
+
+    /// When the client last sent a whole frame, which the read timeout counts
+    /// from.
+    last_received: Instant,
+
+    /// Set once the server has decided to close this connection, e.g. after
+    /// sending a disconnect packet.
+    closed: bool,
 }
 
-impl Connection {
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
     /// Create a new `Connection`, backed by `socket`. Read and write buffers
     /// are initialized.
     pub async fn new(
         config: Arc<RwLock<ServerConfig>>,
-        socket: TcpStream,
+        backends: Arc<Backends>,
+        status_cache: Arc<StatusCache>,
+        server_key: Option<Arc<ServerKey>>,
+        limits: Arc<Limits>,
+        socket: S,
         address: SocketAddr,
     ) -> Self {
-        let max_packet_size = config.read().await.mc.max_packet_size;
+        let config_guard = config.read().await;
+        let max_packet_size = config_guard.mc.max_packet_size;
+
+        let capture = match config_guard.capture.enabled {
+            true => match Capture::create(&config_guard.capture.directory, address).await {
+                Ok(capture) => {
+                    debug!(
+                        "Capturing the connection from {address} to \"{}\".",
+                        capture.path().display()
+                    );
+                    Some(capture)
+                }
+                Err(err) => {
+                    warn!("Not capturing the connection from {address}: {err:#}");
+                    None
+                }
+            },
+            false => None,
+        };
+
+        drop(config_guard);
 
         Self {
             config,
-            // player: None,
-            stream: BufWriter::new(socket),
+            backend_status: backends.status(None),
+            backends,
+            status_cache,
+            host: None,
+            server_key,
+            stage_deadline: Some(Instant::now() + limits.handshake_timeout),
+            limits,
+            player: None,
+            stream: socket,
             address,
+            proxy_address: None,
             buffer: BytesMut::with_capacity(max_packet_size),
-            queue: Vec::with_capacity(max_packet_size - 1),
-            staging: Vec::with_capacity(max_packet_size),
+            codec: PacketCodec::new(max_packet_size),
+            write_buffer: BytesMut::with_capacity(max_packet_size),
             state: State::Handshake,
+            protocol_version: 0,
+            fml_version: None,
+            limbo: None,
+            velocity_message_id: None,
+            pending_encryption: None,
+            capture,
+            last_received: Instant::now(),
+            closed: false,
         }
     }
 
-    /// Read a single `Packet` value from the underlying stream.
+    /// Reads and handles packets from the client until the connection ends.
     ///
-    /// The function waits until it has retrieved enough data to parse a packet.
-    /// Any data remaining in the read buffer after the packet has been parsed
-    /// is kept there for the next call to `read_packet`.
+    /// Bytes are read into the buffer until the codec has a whole frame,
+    /// which is then handled - any data left over after a frame is kept for
+    /// the next one.
     ///
     /// # Returns
     ///
-    /// On success, the received packet is returned. If the `TcpStream`
-    /// is closed in a way that doesn't break a packet in half, it returns
-    /// `None`. Otherwise, an error is returned.
+    /// Returns `Ok(())` once the server has closed the connection (e.g. after
+    /// disconnecting the client, or because it sent something invalid), or an
+    /// error if the client went away.
     pub async fn handle_connection(&mut self) -> Result<()> {
         loop {
             trace!("handling connection with {}", self.address);
 
-            if self.buffer.is_empty() {
-                let bytes_read = self.stream.read_buf(&mut self.buffer).await?;
+            if self.closed {
+                return self.flush().await;
+            }
+
+            let handled = match self.codec.decode(&mut self.buffer) {
+                Ok(Some(frame)) => {
+                    self.last_received = Instant::now();
+                    self.handle_frame(frame.freeze()).await.map(|_| true)
+                }
+                Ok(None) => Ok(false),
+                Err(e) => Err(e.context(ProtocolError)),
+            };
+
+            match handled {
+                Ok(true) => continue,
+                Ok(false) => (),
+                // Once the connection has been handed over to the real server,
+                // errors come from forwarding it rather than from the client.
+                Err(e) if !self.closed && e.is::<ProtocolError>() => {
+                    return self.protocol_error(e).await
+                }
+                Err(e) => return Err(e),
+            }
+
+            // Everything the client sent has been handled, so the responses can
+            // go out together before waiting for more.
+            self.flush().await?;
+
+            // Limbo ticks wake this loop up without the client sending
+            // anything, so this counts from what it last sent.
+            let read_deadline = self.last_received + self.limits.read_timeout;
+            let (deadline, timed_out) = match self.stage_deadline {
+                Some(stage_deadline) if stage_deadline < read_deadline => (
+                    stage_deadline,
+                    match self.state {
+                        State::Handshake => "didn't send a handshake in time",
+                        State::Status => "didn't finish its status request in time",
+                        _ => "didn't finish logging in in time",
+                    },
+                ),
+                _ => (read_deadline, "didn't send anything for too long"),
+            };
+
+            // Players in limbo need to be sent packets even when they haven't
+            // sent anything themselves.
+            let bytes_read = tokio::select! {
+                res = self.stream.read_buf(&mut self.buffer) => Some(res?),
+                _ = limbo_tick(&mut self.limbo) => None,
+                _ = time::sleep_until(deadline) => {
+                    Metrics::increment(&self.limits.metrics.connections_timed_out);
+                    warn!("Closing the connection from {}: it {timed_out}.", self.address);
 
-                if bytes_read == 0 {
-                    return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+                    return Ok(());
                 }
+            };
 
-                trace!("read {bytes_read} bytes from {}.", self.address);
+            match bytes_read {
+                Some(0) if self.buffer.is_empty() => {
+                    return Err(io::Error::from(ErrorKind::UnexpectedEof).into())
+                }
+                Some(0) => bail!(
+                    "connection closed partway through a packet ({} bytes left unread)",
+                    self.buffer.len()
+                ),
+                Some(bytes_read) => trace!("read {bytes_read} bytes from {}.", self.address),
+                None => self.tick_limbo().await?,
+            }
+        }
+    }
+
+    /// Decodes and handles a single frame (a packet id followed by the
+    /// packet's data), according to the current state.
+    async fn handle_frame(&mut self, frame: Bytes) -> Result<()> {
+        self.capture(Direction::C2S, &frame).await;
+
+        let mut buf = Cursor::new(&frame[..]);
+
+        match self.state {
+            State::Handshake => {
+                let packet = C2SHandshakePacket::decode(&mut buf).context(ProtocolError)?;
+                debug!("(↓) packet recieved: {:?}", &packet);
+
+                let C2SHandshakePacket::Handshake(handshake) = &packet;
+                self.choose_host(handshake.hostname()).await;
+
+                if let Some(address) = self.proxy_address().await {
+                    return self.forward(&address, &frame).await;
+                }
+
+                self.handle_handshake(packet).await?
             }
+            State::Status => {
+                let packet = C2SStatusPacket::decode(&mut buf).context(ProtocolError)?;
+                debug!("(↓) packet recieved: {:?}", &packet);
+                self.handle_status(packet).await?
+            }
+            State::Login => {
+                let packet = C2SLoginPacket::decode(&mut buf).context(ProtocolError)?;
+                debug!("(↓) packet recieved: {:?}", &packet);
+                self.handle_login(packet).await?
+            }
+            State::Play => {
+                // The client sends plenty of play packets (movement, settings,
+                // plugin channels...) that the limbo world has no use for.
+                let id = VarInt::decode(Cursor::new(&frame[..])).context(ProtocolError)?;
+
+                if !C2SPlayPacket::has_id(id.0) {
+                    trace!("(↓) ignoring play packet with id {:#04x}", id.0);
+                    return Ok(());
+                }
 
-            self.parse_packet().await?;
+                let packet = C2SPlayPacket::decode(&mut buf).context(ProtocolError)?;
+                debug!("(↓) packet recieved: {:?}", &packet);
+                self.handle_play(packet).await?
+            }
         }
+
+        Ok(())
     }
 
-    /// Tries to parse a frame from the buffer. If the buffer contains enough
-    /// data, the frame is returned and the data removed from the buffer. If not
-    /// enough data has been buffered yet, `Ok(None)` is returned. If the
-    /// buffered data does not represent a valid frame, `Err` is returned.
-    pub async fn parse_packet(&mut self) -> Result<()> {
-        let (offset, length) = {
-            let mut buf = Cursor::new(&self.buffer[..]);
-
-            let length = VarInt::decode(&mut buf)?;
-
-            if self.buffer.len() < length.0 as usize + buf.position() as usize {
-                bail!(
-                    "Packet wasn't long enough!
-                    Packet was {} bytes long while the packet stated it should be {} bytes long.",
-                    self.buffer.len(),
-                    length
+    /// Picks the host profile used for the rest of the connection, by the
+    /// `hostname` the client connected with.
+    async fn choose_host(&mut self, hostname: &str) {
+        let config = self.config.read().await;
+
+        if config.hosts.is_empty() {
+            return;
+        }
+
+        match vhost::find(&config.hosts, hostname) {
+            Some((host, pattern)) => {
+                info!(
+                    "{} connected with hostname \"{hostname}\", using the profile for \
+                     \"{pattern}\".",
+                    self.address
                 );
-            }
 
-            (buf.position() as usize, length.0 as usize)
-        };
+                self.host = Some(host);
+            }
+            None => info!(
+                "{} connected with hostname \"{hostname}\", which no profile matches - using the \
+                 default settings.",
+                self.address
+            ),
+        }
 
-        self.buffer.advance(offset);
+        drop(config);
 
-        // Cursor is used to track the "current" location in the
-        // buffer. Cursor also implements `Buf` from the `bytes` crate
-        // which provides a number of helpful utilities for working
-        // with bytes.
-        let mut buf = Cursor::new(&self.buffer[..length]);
+        self.backend_status = self.backends.status(self.host);
+    }
 
-        let packet = C2SPacket::decode(&mut buf)?;
-        debug!("(↓) packet recieved: {:?}", &packet);
+    /// The default message to disconnect the client with, from their host
+    /// profile.
+    pub async fn disconnect_msg(&self) -> String {
+        let config = self.config.read().await;
 
-        match self.state {
-            State::Handshake => {println!("hand"); self.handle_handshake(packet).await?},
+        Profile::new(&config, self.host)
+            .disconnect_msg()
+            .to_string()
+    }
 
-            State::Status => {println!("here");self.handle_status(packet).await?},
+    /// The address of the real server, if it is up and connections should be
+    /// forwarded to it rather than handled by statik.
+    async fn proxy_address(&self) -> Option<String> {
+        let config = self.config.read().await;
+        let backend = Profile::new(&config, self.host).backend();
 
-            State::Login => self.handle_login(packet).await?,
-            State::Play => unimplemented!(),
+        if !backend.proxy || *self.backend_status.borrow() != BackendStatus::Online {
+            return None;
         }
 
-        self.buffer.advance(length);
+        backend.address.clone()
+    }
+
+    /// Hands the connection over to the real server at `address`, replaying
+    /// the client's `handshake`.
+    async fn forward(&mut self, address: &str, handshake: &[u8]) -> Result<()> {
+        info!(
+            "Forwarding {} to the real server at {address}.",
+            self.address
+        );
+
+        self.closed = true;
+
+        let unread = self.buffer.split();
+
+        let (sent, received) =
+            proxy::forward(&mut self.stream, address, handshake, &unread).await?;
+
+        debug!(
+            "Stopped forwarding {} to the real server: sent {sent} bytes, and received {received}.",
+            self.address
+        );
 
         Ok(())
     }
 
-    pub async fn handle_handshake(&mut self, packet: C2SPacket) -> Result<()> {
+    pub async fn handle_handshake(&mut self, packet: C2SHandshakePacket) -> Result<()> {
         match packet {
-            C2SPacket::Handshake(handshake) => {
+            C2SHandshakePacket::Handshake(handshake) => {
+                // Switch states first, so that a client trying to log in with
+                // the wrong version is told why it was disconnected.
+                self.state = handshake.next_state.next_state();
+
                 if handshake.protocol_version.0 as usize != PROTOCOL_VERSION {
-                    return Err(anyhow!(
-                        "Protocol versions do not match! Client had protocol version: {}, while \
-                         the server's protocol version is {}.",
-                        handshake.protocol_version.0,
-                        PROTOCOL_VERSION
-                    ));
+                    debug!(
+                        "Closing the connection from {}: it has protocol version {}, while the \
+                         server's is {PROTOCOL_VERSION}.",
+                        self.address, handshake.protocol_version.0
+                    );
+
+                    return self
+                        .disconnect(Chat::new(format!(
+                            "Incompatible client! Please use {MINECRAFT_VERSION}."
+                        )))
+                        .await;
                 };
 
-                let next_state = handshake.next_state;
+                self.protocol_version = handshake.protocol_version.0;
+                self.stage_deadline = Some(
+                    Instant::now()
+                        + match self.state {
+                            State::Status => self.limits.handshake_timeout,
+                            _ => self.limits.login_timeout,
+                        },
+                );
+
+                // statik never transfers anyone itself (see `leave_limbo`),
+                // and a transferred player logs in like any other.
+                if handshake.next_state == HandshakeIntent::Transfer {
+                    debug!("{} was transferred here from another server.", self.address);
+                }
+
+                self.fml_version = handshake.fml_version();
+
+                if let Some(fml_version) = self.fml_version {
+                    debug!(
+                        "{} is a modded client ({}).",
+                        self.address,
+                        fml_version.marker()
+                    );
+                }
+
+                if self.config.read().await.forwarding.mode == ForwardingMode::BungeeCord {
+                    if let Some(forwarded) =
+                        forwarding::decode_bungeecord_address(&handshake.server_address)
+                            .context(ProtocolError)?
+                    {
+                        debug!(
+                            "{} ({}) connected to {} through bungeecord proxy {}.",
+                            forwarded.uuid, forwarded.address, forwarded.hostname, self.address
+                        );
 
-                self.state = next_state;
+                        self.proxy_address = Some(self.address);
+                        self.address = SocketAddr::new(forwarded.address, self.address.port());
+
+                        // The username isn't known until the login start packet.
+                        self.player = Some(Player {
+                            uuid: forwarded.uuid,
+                            properties: forwarded.properties,
+                            ..Default::default()
+                        });
+                    }
+                }
 
                 Ok(())
             }
-            _ => Err(anyhow!(
-                "Recieved a non handshake packet in the handshake stage!"
-            )),
         }
     }
 
-    pub async fn handle_status(&mut self, packet: C2SPacket) -> Result<()> {
+    pub async fn handle_status(&mut self, packet: C2SStatusPacket) -> Result<()> {
         use statik_proto::s2c::status::{S2CPong, S2CStatusResponse};
         match packet {
-            C2SPacket::StatusRequest(_status_request) => {
-                let config = self.config.read().await;
+            C2SStatusPacket::StatusRequest(_status_request) => {
+                // Status requests through a forwarding proxy (e.g. velocity's
+                // ping passthrough) don't say who they're for, so can't be
+                // limited per player.
+                let forwarded = self.config.read().await.forwarding.mode != ForwardingMode::None;
+
+                if !forwarded && !self.limits.check_status_rate(self.address.ip()) {
+                    Metrics::increment(&self.limits.metrics.status_rate_limited);
+                    debug!(
+                        "Ignoring a status request from {}: too many requests.",
+                        self.address
+                    );
+
+                    self.closed = true;
+
+                    return Ok(());
+                }
+
+                let key = StatusKey { host: self.host };
+
+                let response = match self.status_cache.get(&key) {
+                    Some(response) => response,
+                    None => {
+                        let config = self.config.read().await;
+
+                        let status_response = S2CStatusResponse {
+                            json_response: status_response(&config, self.host)?,
+                        };
+                        drop(config);
 
-                let status_response = S2CStatusResponse {
-                    json_response: StatusResponse::new(
-                        Players::new(config.mc.max_players, 0, vec![]),
-                        Chat::new(config.mc.motd.clone()),
-                        config.mc.icon.clone(),
-                        false,
-                    ),
+                        debug!("building the status response for {key:?}.");
+
+                        let mut frame = BytesMut::new();
+                        self.codec.encode(status_response, &mut frame)?;
+
+                        let frame = frame.freeze();
+                        self.status_cache.insert(key, frame.clone());
+
+                        frame
+                    }
                 };
 
-                drop(config);
+                trace!("(↑) sending status response for {key:?}.");
+
+                if self.capture.is_some() {
+                    let mut framed = Cursor::new(&response[..]);
+                    VarInt::decode(&mut framed)?;
+                    let frame = &response[framed.position() as usize..];
+
+                    self.capture(Direction::S2C, frame).await;
+                }
 
-                self.write_packet(status_response).await?;
+                self.write_buffer.extend_from_slice(&response);
 
                 Ok(())
             }
-            C2SPacket::Ping(ping) => {
+            C2SStatusPacket::Ping(ping) => {
                 let pong = S2CPong {
                     payload: ping.payload,
                 };
@@ -227,53 +692,460 @@ impl Connection {
 
                 Ok(())
             }
-            _ => Err(anyhow!("Recieved a non status packet in the status stage!")),
         }
     }
 
-    pub async fn handle_login(&mut self, packet: C2SPacket) -> Result<()> {
-        use statik_proto::s2c::login::S2CDisconnect;
+    pub async fn handle_login(&mut self, packet: C2SLoginPacket) -> Result<()> {
+        use statik_proto::s2c::login::{S2CEncryptionRequest, S2CLoginPluginRequest};
         match packet {
-            C2SPacket::LoginStart(login_start) => {
-                //later use tera templating?
-                let disconnect = S2CDisconnect {
-                    reason: Chat::new(
-                        /* self.config.read().await.mc.disconnect_msg.clone() */
-                        format!(
-                            "{}, the server is now starting. It will be up in around 30s-1m!",
-                            login_start.username
-                        ),
-                    ),
+            C2SLoginPacket::LoginStart(login_start) => {
+                if self.config.read().await.forwarding.mode == ForwardingMode::Velocity {
+                    // The player's real details come from velocity instead.
+                    let request = S2CLoginPluginRequest {
+                        message_id: VarInt(VELOCITY_MESSAGE_ID),
+                        channel: VELOCITY_CHANNEL.to_string(),
+                        data: RawBytes::new(vec![VELOCITY_FORWARDING_VERSION]),
+                    };
+
+                    self.velocity_message_id = Some(VELOCITY_MESSAGE_ID);
+                    self.write_packet(request).await?;
+
+                    return Ok(());
+                }
+
+                let forwarding_mode = self.config.read().await.forwarding.mode;
+
+                // Players forwarded by a proxy were already authenticated by it.
+                if forwarding_mode == ForwardingMode::None {
+                    if let Some(server_key) = &self.server_key {
+                        let verify_token = rand::random::<[u8; 4]>();
+
+                        let request = S2CEncryptionRequest {
+                            server_id: String::new(),
+                            public_key: server_key.public_key().to_vec(),
+                            verify_token: verify_token.to_vec(),
+                        };
+
+                        self.pending_encryption = Some(PendingEncryption {
+                            username: login_start.username,
+                            verify_token,
+                        });
+
+                        return self.write_packet(request).await;
+                    }
+                }
+
+                let player = match (forwarding_mode, self.player.take()) {
+                    (ForwardingMode::BungeeCord, Some(player)) => Player {
+                        username: login_start.username,
+                        ..player
+                    },
+                    (ForwardingMode::BungeeCord, None) => {
+                        warn!(
+                            "{} tried to join without connecting through bungeecord.",
+                            self.address
+                        );
+
+                        return self
+                            .disconnect(Chat::new(forwarding::BUNGEECORD_REQUIRED_MSG))
+                            .await;
+                    }
+                    (_, _) => match login_start.uuid {
+                        Some(uuid) => Player::new(uuid, login_start.username),
+                        None => Player::offline(login_start.username),
+                    },
                 };
 
-                self.write_packet(disconnect).await?;
+                self.login(player).await
+            }
+            C2SLoginPacket::LoginPluginResponse(response) => {
+                if self.velocity_message_id.take() != Some(response.message_id.0) {
+                    return Err(anyhow!(
+                        "recieved a response to a login plugin request that was never sent: {}",
+                        response.message_id
+                    )
+                    .context(ProtocolError));
+                }
+
+                // Clients that didn't come through velocity don't understand
+                // the request, so respond without any data.
+                let Some(data) = response.data else {
+                    warn!(
+                        "{} tried to join without connecting through velocity.",
+                        self.address
+                    );
 
-                Ok(())
+                    return self
+                        .disconnect(Chat::new(
+                            "This server requires you to connect with Velocity.",
+                        ))
+                        .await;
+                };
+
+                let secret = self.config.read().await.forwarding.secret.clone();
+
+                let forwarded =
+                    match forwarding::decode_velocity_response(secret.as_bytes(), &data.0) {
+                        Ok(forwarded) => forwarded,
+                        Err(e) => {
+                            warn!(
+                                "Could not verify player details forwarded by {}: {e:#}",
+                                self.address
+                            );
+
+                            return self
+                                .disconnect(Chat::new("Unable to verify player details."))
+                                .await;
+                        }
+                    };
+
+                info!(
+                    "{} ({}) connected through velocity proxy {}.",
+                    forwarded.player.username, forwarded.address, self.address
+                );
+
+                self.proxy_address = Some(self.address);
+                self.address = SocketAddr::new(forwarded.address, self.address.port());
+
+                self.login(forwarded.player).await
+            }
+            C2SLoginPacket::EncryptionResponse(response) => {
+                let (Some(pending), Some(server_key)) =
+                    (self.pending_encryption.take(), self.server_key.clone())
+                else {
+                    return Err(anyhow!(
+                        "recieved an encryption response without sending an encryption request"
+                    )
+                    .context(ProtocolError));
+                };
+
+                let verify_token = server_key
+                    .decrypt(&response.verify_token)
+                    .context(ProtocolError)?;
+
+                if verify_token != pending.verify_token {
+                    return Err(anyhow!("client responded with the wrong verify token")
+                        .context(ProtocolError));
+                }
+
+                let shared_secret = server_key
+                    .decrypt(&response.shared_secret)
+                    .context(ProtocolError)?;
+
+                // Everything after the encryption response is encrypted,
+                // including any disconnect message.
+                self.codec
+                    .enable_encryption(&shared_secret)
+                    .context(ProtocolError)?;
+
+                let server_hash = auth::server_hash("", &shared_secret, server_key.public_key());
+
+                let config = self.config.read().await;
+                let session_server = config.auth.session_server.clone();
+                let ip = config
+                    .auth
+                    .prevent_proxy_connections
+                    .then_some(self.address.ip());
+                drop(config);
+
+                match auth::has_joined(&session_server, &pending.username, &server_hash, ip).await {
+                    Ok(Some(profile)) => {
+                        info!(
+                            "{} ({}) authenticated from {}.",
+                            profile.name, profile.id, self.address
+                        );
+
+                        self.login(Player::from(profile)).await
+                    }
+                    Ok(None) => {
+                        warn!(
+                            "{} ({}) failed to authenticate.",
+                            pending.username, self.address
+                        );
+
+                        self.disconnect(Chat::new("Failed to verify username!"))
+                            .await
+                    }
+                    Err(e) => {
+                        error!("Could not authenticate {}: {e:#}", pending.username);
+
+                        self.disconnect(Chat::new(
+                            "Authentication servers are down. Please try again later, sorry!",
+                        ))
+                        .await
+                    }
+                }
             }
-            _ => unimplemented!(),
         }
     }
 
-    ///jank as. fix later.
+    /// Finishes logging in `player`, either holding them in limbo or
+    /// disconnecting them while the real server starts.
+    async fn login(&mut self, player: Player) -> Result<()> {
+        use statik_proto::s2c::login::{S2CLoginSuccess, S2CSetCompression};
+
+        // Players logging in through a forwarding proxy weren't limited when
+        // they connected, as they all share its address.
+        if let Some(proxy_address) = self.proxy_address {
+            if !self.limits.check_connection_rate(self.address.ip()) {
+                Metrics::increment(&self.limits.metrics.connections_rate_limited);
+                warn!(
+                    "Disconnecting {} ({}) through proxy {proxy_address}: they are connecting too \
+                     often.",
+                    player.username, self.address
+                );
+
+                return self.disconnect(Chat::new(RATE_LIMITED_MSG)).await;
+            }
+        }
+
+        let config = self.config.read().await;
+        let limbo_enabled = config.limbo.enabled;
+        let compression_threshold = config.mc.compression_threshold;
+        let starting_msg = Profile::new(&config, self.host)
+            .starting_msg()
+            .replace("{username}", &player.username);
+        drop(config);
+
+        // Starting again while it's already starting lets backends which
+        // can't tell whether their request arrived (Wake-on-LAN) try again.
+        if matches!(
+            *self.backend_status.borrow(),
+            BackendStatus::Offline | BackendStatus::Starting
+        ) {
+            self.backends.start(self.host);
+        }
+
+        if !limbo_enabled {
+            return self.disconnect(Chat::new(starting_msg)).await;
+        }
+
+        // Everything after set compression is sent (and received) compressed.
+        if compression_threshold >= 0 {
+            self.write_packet(S2CSetCompression {
+                threshold: VarInt(compression_threshold),
+            })
+            .await?;
+
+            self.codec
+                .set_compression(Some(compression_threshold as usize));
+        }
+
+        let login_success = S2CLoginSuccess::from(player.profile());
+
+        self.player = Some(player);
+
+        self.write_packet(login_success).await?;
+        self.state = State::Play;
+        self.stage_deadline = None;
+
+        self.enter_limbo().await
+    }
+
+    pub async fn handle_play(&mut self, packet: C2SPlayPacket) -> Result<()> {
+        match packet {
+            C2SPlayPacket::ConfirmTeleportation(_confirm_teleportation) => Ok(()),
+            C2SPlayPacket::KeepAlive(keep_alive) => match &mut self.limbo {
+                Some(limbo) => limbo
+                    .keep_alive_received(keep_alive.keep_alive_id)
+                    .context(ProtocolError),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Sends the packets needed for the client to join the (empty) limbo
+    /// world, and starts the countdown until the real server should be up.
+    async fn enter_limbo(&mut self) -> Result<()> {
+        use statik_proto::s2c::play::S2CSetCenterChunk;
+
+        let config = self.config.read().await;
+        let max_players = Profile::new(&config, self.host).max_players();
+        let wait_time = Duration::from_secs(config.limbo.wait_time);
+        drop(config);
+
+        self.write_packet(limbo::login_packet(max_players)).await?;
+        self.write_packet(limbo::spawn_position_packet()).await?;
+        self.write_packet(S2CSetCenterChunk {
+            chunk_x: VarInt(0),
+            chunk_z: VarInt(0),
+        })
+        .await?;
+        self.write_packet(limbo::empty_chunk(0, 0)?).await?;
+        self.write_packet(limbo::player_position_packet()).await?;
+
+        self.limbo = Some(Limbo::new(wait_time));
+
+        if let Some(player) = &self.player {
+            info!(
+                "{} ({}) is waiting in limbo for the server to start.",
+                player.username, self.address
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Runs once a second for players in limbo: updates their countdown, keeps
+    /// their connection alive, and disconnects them once the countdown is over.
+    async fn tick_limbo(&mut self) -> Result<()> {
+        use statik_proto::s2c::play::{S2CKeepAlive, S2CSetActionBarText};
+
+        let Some(limbo) = &mut self.limbo else {
+            return Ok(());
+        };
+
+        let seconds_left = limbo.seconds_left();
+
+        let keep_alive = match limbo.next_keep_alive() {
+            Ok(keep_alive) => keep_alive,
+            Err(e) => {
+                Metrics::increment(&self.limits.metrics.connections_timed_out);
+                warn!("Closing the connection from {}: {e:#}", self.address);
+
+                return self.disconnect(Chat::new("Timed out")).await;
+            }
+        };
+
+        if *self.backend_status.borrow() == BackendStatus::Online {
+            return self.leave_limbo().await;
+        }
+
+        let config = self.config.read().await;
+
+        // If the real server is being watched, wait for it to actually be up
+        // instead of guessing.
+        let backend = Profile::new(&config, self.host).backend();
+
+        if seconds_left == 0 && !backend::is_watched(backend) {
+            drop(config);
+
+            return self.leave_limbo().await;
+        }
+
+        let action_bar_text = Chat::new(
+            config
+                .limbo
+                .action_bar_msg
+                .replace("{seconds}", &seconds_left.to_string()),
+        );
+        drop(config);
+
+        if let Some(keep_alive_id) = keep_alive {
+            self.write_packet(S2CKeepAlive { keep_alive_id }).await?;
+        }
+
+        self.write_packet(S2CSetActionBarText { action_bar_text })
+            .await
+    }
+
+    /// Asks a player in limbo to reconnect, now that the real server is up.
+    ///
+    /// Ideally they'd be sent there with a transfer packet, but that was only
+    /// added in 1.20.5 and statik speaks 1.20.1, whose clients can't be sent
+    /// one - so they have to reconnect by hand.
+    async fn leave_limbo(&mut self) -> Result<()> {
+        let config = self.config.read().await;
+        let reconnect_msg = Profile::new(&config, self.host).reconnect_msg().to_string();
+        drop(config);
+
+        self.disconnect(Chat::new(reconnect_msg)).await
+    }
+
+    /// Closes the connection after the client sent something it shouldn't
+    /// have (a [`ProtocolError`]), disconnecting it if the current state
+    /// allows it.
+    ///
+    /// Anything can connect to the server, so invalid handshakes and status
+    /// requests (from scanners, or old clients' pings) are only logged at
+    /// debug level - once a client is logging in, it's worth a warning.
+    async fn protocol_error(&mut self, error: anyhow::Error) -> Result<()> {
+        match self.state {
+            State::Handshake | State::Status => debug!(
+                "Closing the connection from {} in state {:?}: {error:#}",
+                self.address, self.state
+            ),
+            State::Login | State::Play => warn!(
+                "Closing the connection from {} in state {:?}: {error:#}",
+                self.address, self.state
+            ),
+        }
+
+        self.disconnect(Chat::new(PROTOCOL_ERROR_MSG)).await?;
+
+        self.flush().await
+    }
+
+    /// Sends the client a disconnect packet (if the current state has one)
+    /// with the given reason, and marks the connection as closed.
+    pub async fn disconnect(&mut self, reason: Chat) -> Result<()> {
+        use statik_proto::s2c::{login::S2CDisconnect, play::S2CPlayDisconnect};
+
+        debug!("disconnecting {} with reason: {reason:?}", self.address);
+
+        match self.state {
+            State::Login => self.write_packet(S2CDisconnect { reason }).await?,
+            State::Play => self.write_packet(S2CPlayDisconnect { reason }).await?,
+            State::Handshake | State::Status => (),
+        }
+
+        self.closed = true;
+
+        Ok(())
+    }
+
+    /// Encodes `packet` into the write buffer, to be sent along with any
+    /// others written before the next [`flush`](Self::flush).
     pub async fn write_packet(&mut self, packet: impl Packet) -> Result<()> {
-        packet.encode(&mut self.queue)?;
+        trace!("(↑) sending packet: {packet:?}");
 
-        let packet_len = self.queue.len();
-        VarInt(packet_len as i32).encode(&mut self.staging)?;
+        if self.capture.is_some() {
+            let mut frame = vec![];
+            packet.encode(&mut frame)?;
 
-        self.staging.extend_from_slice(&self.queue);
+            self.capture(Direction::S2C, &frame).await;
+        }
 
-        trace!("writing packet to tcp stream.");
-        self.stream.write_all(&self.staging).await?;
+        self.codec.encode(packet, &mut self.write_buffer)
+    }
 
-        self.stream.flush().await?;
+    /// Records `frame` in the capture file, if the connection is being
+    /// captured. If it can't be written, capturing is stopped rather than
+    /// closing the connection.
+    async fn capture(&mut self, direction: Direction, frame: &[u8]) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
 
-        self.queue.clear();
+        if let Err(err) = capture.record(direction, self.state, frame).await {
+            warn!(
+                "Stopped capturing the connection from {}: {err:#}",
+                self.address
+            );
 
-        trace!("(↑) packet sent: {packet:?}");
+            self.capture = None;
+        }
+    }
+
+    /// Sends every packet written since the last flush to the client.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.write_buffer.is_empty() {
+            return Ok(());
+        }
 
-        self.staging.clear();
+        trace!(
+            "(↑) sending {} bytes to {}.",
+            self.write_buffer.len(),
+            self.address
+        );
+
+        self.stream.write_all(&self.write_buffer).await?;
+
+        self.write_buffer.clear();
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests;
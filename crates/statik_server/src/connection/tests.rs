@@ -0,0 +1,572 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+use statik_proto::{
+    c2s::{
+        handshake::C2SHandshake,
+        login::{C2SEncryptionResponse, C2SLoginStart},
+        play::C2SKeepAlive,
+    },
+    s2c::{login::S2CEncryptionRequest, S2CLoginPacket, S2CPlayPacket},
+};
+use tokio::{
+    io::{duplex, DuplexStream},
+    sync::broadcast,
+    task::JoinHandle,
+};
+use uuid::Uuid;
+
+use super::*;
+use crate::auth::tests::{session_server, PROFILE};
+
+/// How long to wait for the server to respond before failing a test.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The client end of an in-memory connection to a [`Connection`].
+struct TestClient {
+    stream: DuplexStream,
+    codec: PacketCodec,
+    buffer: BytesMut,
+
+    /// Handles the connection until it ends.
+    connection: JoinHandle<Result<()>>,
+
+    /// Kept so backends aren't told to shut down.
+    _notify_shutdown: broadcast::Sender<Option<String>>,
+}
+
+impl TestClient {
+    async fn connect(config: ServerConfig, server_key: Option<Arc<ServerKey>>) -> Self {
+        let limits = Arc::new(Limits::new(&config.limits));
+
+        Self::connect_with_limits(config, server_key, limits).await
+    }
+
+    /// Connects with `limits` shared with other clients, like the server's.
+    async fn connect_with_limits(
+        config: ServerConfig,
+        server_key: Option<Arc<ServerKey>>,
+        limits: Arc<Limits>,
+    ) -> Self {
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let backends = Arc::new(Backends::spawn(&config, &notify_shutdown).unwrap());
+        let (client, server) = duplex(64 * 1024);
+
+        let mut connection = Connection::new(
+            Arc::new(RwLock::new(config)),
+            backends,
+            Arc::new(StatusCache::default()),
+            server_key,
+            limits,
+            server,
+            "192.0.2.1:50000".parse().unwrap(),
+        )
+        .await;
+
+        Self {
+            stream: client,
+            codec: PacketCodec::default(),
+            buffer: BytesMut::new(),
+            connection: tokio::spawn(async move { connection.handle_connection().await }),
+            _notify_shutdown: notify_shutdown,
+        }
+    }
+
+    async fn send(&mut self, packet: impl Packet) {
+        let mut dst = BytesMut::new();
+        self.codec.encode(packet, &mut dst).unwrap();
+        self.stream.write_all(&dst).await.unwrap();
+    }
+
+    /// Sends a frame as is, whether or not it's a valid packet.
+    async fn send_frame(&mut self, frame: &[u8]) {
+        let mut dst = BytesMut::new();
+        self.codec.encode_frame(frame, &mut dst).unwrap();
+        self.stream.write_all(&dst).await.unwrap();
+    }
+
+    async fn handshake(&mut self, next_state: HandshakeIntent) {
+        self.handshake_with_version(PROTOCOL_VERSION as i32, next_state)
+            .await;
+    }
+
+    async fn handshake_with_version(&mut self, protocol_version: i32, next_state: HandshakeIntent) {
+        self.send(C2SHandshake {
+            protocol_version: VarInt(protocol_version),
+            server_address: "localhost".to_string(),
+            server_port: 25565,
+            next_state,
+        })
+        .await;
+    }
+
+    /// The next frame the server sent, or `None` once it has closed the
+    /// connection.
+    async fn recv(&mut self) -> Option<BytesMut> {
+        time::timeout(RESPONSE_TIMEOUT, async {
+            loop {
+                if let Some(frame) = self.codec.decode(&mut self.buffer).unwrap() {
+                    return Some(frame);
+                }
+
+                if self.stream.read_buf(&mut self.buffer).await.unwrap() == 0 {
+                    return None;
+                }
+            }
+        })
+        .await
+        .expect("the server didn't respond in time")
+    }
+
+    async fn recv_login(&mut self) -> S2CLoginPacket {
+        let frame = self.recv().await.expect("the server closed the connection");
+        let packet = S2CLoginPacket::decode(Cursor::new(&frame[..])).unwrap();
+
+        // Everything after set compression is compressed.
+        if let S2CLoginPacket::SetCompression(set_compression) = &packet {
+            self.codec
+                .set_compression(Some(set_compression.threshold.0 as usize));
+        }
+
+        packet
+    }
+
+    /// Skips past everything sent in limbo until the server disconnects the
+    /// player, returning the reason it gave.
+    async fn recv_play_disconnect(&mut self) -> String {
+        loop {
+            let frame = self.recv().await.expect("the server closed the connection");
+
+            if let S2CPlayPacket::Disconnect(disconnect) =
+                S2CPlayPacket::decode(Cursor::new(&frame[..])).unwrap()
+            {
+                return text(&disconnect.reason);
+            }
+        }
+    }
+
+    /// Checks the server has closed the connection, without erroring.
+    async fn assert_closed(mut self) {
+        assert!(self.recv().await.is_none());
+        self.connection.await.unwrap().unwrap();
+    }
+}
+
+/// The text of a disconnect reason.
+fn text(chat: &Chat) -> String {
+    serde_json::to_value(chat).unwrap()["text"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+fn online_config(session_server: String) -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.auth.online_mode = true;
+    config.auth.session_server = session_server;
+    config
+}
+
+/// Logs in as `username` in offline mode, which puts them in limbo.
+async fn offline_login(client: &mut TestClient, username: &str) {
+    client.handshake(HandshakeIntent::Login).await;
+    client
+        .send(C2SLoginStart {
+            username: username.to_string(),
+            uuid: None,
+        })
+        .await;
+
+    assert!(matches!(
+        client.recv_login().await,
+        S2CLoginPacket::SetCompression(_)
+    ));
+    assert!(matches!(
+        client.recv_login().await,
+        S2CLoginPacket::LoginSuccess(_)
+    ));
+}
+
+/// Logs in as far as the encryption request.
+async fn start_online_login(client: &mut TestClient) -> S2CEncryptionRequest {
+    client.handshake(HandshakeIntent::Login).await;
+    client
+        .send(C2SLoginStart {
+            username: "Notch".to_string(),
+            uuid: None,
+        })
+        .await;
+
+    match client.recv_login().await {
+        S2CLoginPacket::EncryptionRequest(request) => request,
+        packet => panic!("expected an encryption request, got {packet:?}"),
+    }
+}
+
+/// Responds to an encryption request with `shared_secret`, then encrypts
+/// everything after it.
+async fn respond_to_encryption(
+    client: &mut TestClient,
+    request: &S2CEncryptionRequest,
+    shared_secret: &[u8; 16],
+) {
+    let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+    let mut rng = rand::thread_rng();
+
+    client
+        .send(C2SEncryptionResponse {
+            shared_secret: public_key
+                .encrypt(&mut rng, Pkcs1v15Encrypt, shared_secret)
+                .unwrap(),
+            verify_token: public_key
+                .encrypt(&mut rng, Pkcs1v15Encrypt, &request.verify_token)
+                .unwrap(),
+        })
+        .await;
+
+    client.codec.enable_encryption(shared_secret).unwrap();
+}
+
+#[tokio::test]
+async fn online_mode_login() {
+    let server_key = Arc::new(ServerKey::generate().unwrap());
+
+    // The server hash is printed differently when it's negative.
+    for negative in [false, true] {
+        let (url, mut queries) = session_server(200, PROFILE).await;
+        let mut client = TestClient::connect(online_config(url), Some(server_key.clone())).await;
+
+        let request = start_online_login(&mut client).await;
+        assert_eq!(request.public_key, server_key.public_key());
+
+        let (shared_secret, hash) = loop {
+            let shared_secret = rand::random::<[u8; 16]>();
+            let hash = auth::server_hash("", &shared_secret, &request.public_key);
+
+            if hash.starts_with('-') == negative {
+                break (shared_secret, hash);
+            }
+        };
+
+        respond_to_encryption(&mut client, &request, &shared_secret).await;
+
+        let query = queries.recv().await.unwrap();
+        assert_eq!(query["username"], "Notch");
+        assert_eq!(query["serverId"], hash);
+
+        assert!(matches!(
+            client.recv_login().await,
+            S2CLoginPacket::SetCompression(_)
+        ));
+
+        match client.recv_login().await {
+            S2CLoginPacket::LoginSuccess(success) => {
+                assert_eq!(
+                    success.uuid,
+                    Uuid::parse_str("069a79f444e94726a5befca90e38aaf5").unwrap()
+                );
+                assert_eq!(success.username, "Notch");
+                assert_eq!(success.properties[0].name, "textures");
+            }
+            packet => panic!("expected login success, got {packet:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn online_mode_wrong_verify_token() {
+    let (url, mut queries) = session_server(200, PROFILE).await;
+    let server_key = Arc::new(ServerKey::generate().unwrap());
+    let mut client = TestClient::connect(online_config(url), Some(server_key)).await;
+
+    let request = start_online_login(&mut client).await;
+
+    let mut wrong_token = request.verify_token.clone();
+    wrong_token[0] ^= 1;
+
+    // The server disconnects the client before enabling encryption, so
+    // nothing is encrypted on the way back.
+    let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+    let mut rng = rand::thread_rng();
+
+    client
+        .send(C2SEncryptionResponse {
+            shared_secret: public_key
+                .encrypt(&mut rng, Pkcs1v15Encrypt, &[0; 16])
+                .unwrap(),
+            verify_token: public_key
+                .encrypt(&mut rng, Pkcs1v15Encrypt, &wrong_token)
+                .unwrap(),
+        })
+        .await;
+
+    assert!(matches!(
+        client.recv_login().await,
+        S2CLoginPacket::Disconnect(_)
+    ));
+    client.assert_closed().await;
+
+    assert!(queries.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn online_mode_not_joined() {
+    let (url, _) = session_server(204, "").await;
+    let server_key = Arc::new(ServerKey::generate().unwrap());
+    let mut client = TestClient::connect(online_config(url), Some(server_key)).await;
+
+    let request = start_online_login(&mut client).await;
+    respond_to_encryption(&mut client, &request, &rand::random()).await;
+
+    match client.recv_login().await {
+        S2CLoginPacket::Disconnect(disconnect) => {
+            assert_eq!(text(&disconnect.reason), "Failed to verify username!")
+        }
+        packet => panic!("expected a disconnect, got {packet:?}"),
+    }
+
+    client.assert_closed().await;
+}
+
+#[tokio::test]
+async fn read_timeout_in_limbo() {
+    let mut config = ServerConfig::default();
+    config.limits.read_timeout = 1;
+
+    let mut client = TestClient::connect(config, None).await;
+    offline_login(&mut client, "Notch").await;
+
+    // Limbo keeps sending packets, but the client never responds.
+    time::timeout(Duration::from_secs(3), async {
+        while client.recv().await.is_some() {}
+    })
+    .await
+    .expect("the connection wasn't closed after the read timeout");
+
+    client.assert_closed().await;
+}
+
+fn bungeecord_config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.forwarding.mode = ForwardingMode::BungeeCord;
+    config.limits.connection_rate = 0.001;
+    config.limits.connection_burst = 1;
+    config.limits.status_rate = 0.001;
+    config.limits.status_burst = 1;
+    config
+}
+
+/// Logs in through a bungeecord proxy (at the test client's address) as a
+/// player at `ip`, returning the first packet sent back.
+async fn bungeecord_login(limits: &Arc<Limits>, ip: &str) -> S2CLoginPacket {
+    let mut client =
+        TestClient::connect_with_limits(bungeecord_config(), None, limits.clone()).await;
+
+    client
+        .send(C2SHandshake {
+            protocol_version: VarInt(PROTOCOL_VERSION as i32),
+            server_address: format!("localhost\0{ip}\0{}", Uuid::from_u128(1).simple()),
+            server_port: 25565,
+            next_state: HandshakeIntent::Login,
+        })
+        .await;
+    client
+        .send(C2SLoginStart {
+            username: "Notch".to_string(),
+            uuid: None,
+        })
+        .await;
+
+    client.recv_login().await
+}
+
+#[tokio::test]
+async fn forwarded_players_are_rate_limited_by_their_own_address() {
+    let limits = Arc::new(Limits::new(&bungeecord_config().limits));
+
+    // Every player connects from the proxy's address, which is never limited.
+    for ip in ["198.51.100.1", "198.51.100.2", "203.0.113.1"] {
+        assert!(
+            matches!(
+                bungeecord_login(&limits, ip).await,
+                S2CLoginPacket::SetCompression(_)
+            ),
+            "{ip}"
+        );
+    }
+
+    match bungeecord_login(&limits, "198.51.100.2").await {
+        S2CLoginPacket::Disconnect(disconnect) => {
+            assert_eq!(text(&disconnect.reason), RATE_LIMITED_MSG)
+        }
+        packet => panic!("expected to be disconnected, got {packet:?}"),
+    }
+
+    assert_eq!(
+        limits
+            .metrics
+            .connections_rate_limited
+            .load(Ordering::Relaxed),
+        1
+    );
+}
+
+#[tokio::test]
+async fn status_requests_through_a_proxy_are_not_limited() {
+    use statik_proto::c2s::status::C2SStatusRequest;
+
+    let limits = Arc::new(Limits::new(&bungeecord_config().limits));
+
+    for _ in 0..3 {
+        let mut client =
+            TestClient::connect_with_limits(bungeecord_config(), None, limits.clone()).await;
+
+        client.handshake(HandshakeIntent::Status).await;
+        client.send(C2SStatusRequest {}).await;
+
+        assert!(client.recv().await.is_some());
+    }
+}
+
+/// Connects a new client, and sends it through the handshake into `state`.
+async fn connect_in(state: State) -> TestClient {
+    let mut client = TestClient::connect(ServerConfig::default(), None).await;
+
+    match state {
+        State::Handshake => (),
+        State::Status => client.handshake(HandshakeIntent::Status).await,
+        State::Login => client.handshake(HandshakeIntent::Login).await,
+        State::Play => offline_login(&mut client, "Notch").await,
+    }
+
+    client
+}
+
+#[tokio::test]
+async fn handshake_protocol_errors() {
+    for frame in [
+        // An unknown packet.
+        &[0x7f][..],
+        // A handshake cut off partway through its protocol version.
+        &[0x00, 0xff],
+    ] {
+        let mut client = connect_in(State::Handshake).await;
+        client.send_frame(frame).await;
+        client.assert_closed().await;
+    }
+}
+
+#[tokio::test]
+async fn incompatible_protocol_version() {
+    let mut client = TestClient::connect(ServerConfig::default(), None).await;
+    client
+        .handshake_with_version(PROTOCOL_VERSION as i32 - 1, HandshakeIntent::Login)
+        .await;
+
+    match client.recv_login().await {
+        S2CLoginPacket::Disconnect(disconnect) => {
+            assert!(text(&disconnect.reason).contains(MINECRAFT_VERSION))
+        }
+        packet => panic!("expected a disconnect, got {packet:?}"),
+    }
+
+    client.assert_closed().await;
+}
+
+#[tokio::test]
+async fn status_protocol_errors() {
+    for frame in [
+        // A login plugin response, which only makes sense in the login
+        // state.
+        &[0x02, 0x00, 0x00][..],
+        // A ping without all of its payload.
+        &[0x01, 0, 0, 0],
+    ] {
+        let mut client = connect_in(State::Status).await;
+        client.send_frame(frame).await;
+        client.assert_closed().await;
+    }
+}
+
+#[tokio::test]
+async fn login_protocol_errors() {
+    let mut encryption_response = vec![];
+    C2SEncryptionResponse {
+        shared_secret: vec![0; 128],
+        verify_token: vec![0; 128],
+    }
+    .encode(&mut encryption_response)
+    .unwrap();
+
+    for frame in [
+        // An unknown packet.
+        &[0x7f][..],
+        // A login start packet without a username.
+        &[0x00],
+        // An encryption response, without the server having sent an
+        // encryption request.
+        &encryption_response,
+    ] {
+        let mut client = connect_in(State::Login).await;
+        client.send_frame(frame).await;
+
+        match client.recv_login().await {
+            S2CLoginPacket::Disconnect(disconnect) => {
+                assert_eq!(text(&disconnect.reason), PROTOCOL_ERROR_MSG)
+            }
+            packet => panic!("expected a disconnect, got {packet:?}"),
+        }
+
+        client.assert_closed().await;
+    }
+}
+
+#[tokio::test]
+async fn play_protocol_errors() {
+    // A keep alive cut off partway through its id.
+    let mut client = connect_in(State::Play).await;
+    client.send_frame(&[0x12, 0, 0, 0]).await;
+
+    assert_eq!(client.recv_play_disconnect().await, PROTOCOL_ERROR_MSG);
+    client.assert_closed().await;
+
+    // Play packets statik doesn't know are ignored...
+    let mut client = connect_in(State::Play).await;
+    client.send_frame(&[0x7f, 1, 2, 3]).await;
+
+    // ...but keep alives have to match one that was sent.
+    client.send(C2SKeepAlive { keep_alive_id: 1 }).await;
+
+    assert_eq!(client.recv_play_disconnect().await, PROTOCOL_ERROR_MSG);
+    client.assert_closed().await;
+}
+
+#[tokio::test]
+async fn starting_msg_without_limbo() {
+    let mut config = ServerConfig::default();
+    config.limbo.enabled = false;
+    config.hosts.push(crate::config::HostConfig {
+        hostnames: vec!["localhost".to_string()],
+        starting_msg: Some("{username}, survival is starting!".to_string()),
+        ..Default::default()
+    });
+
+    let mut client = TestClient::connect(config, None).await;
+
+    client.handshake(HandshakeIntent::Login).await;
+    client
+        .send(C2SLoginStart {
+            username: "Notch".to_string(),
+            uuid: None,
+        })
+        .await;
+
+    match client.recv_login().await {
+        S2CLoginPacket::Disconnect(disconnect) => {
+            assert_eq!(text(&disconnect.reason), "Notch, survival is starting!")
+        }
+        packet => panic!("expected to be disconnected, got {packet:?}"),
+    }
+
+    client.assert_closed().await;
+}
@@ -0,0 +1,256 @@
+use std::{io::Cursor, net::IpAddr};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use statik_core::prelude::*;
+use statik_proto::{c2s::handshake::FmlVersion, s2c::login::Property};
+use uuid::Uuid;
+
+use crate::player::Player;
+
+/// The login plugin channel velocity forwards player info over.
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// The version of velocity's forwarding format statik asks for. Version 1
+/// (`MODERN_DEFAULT`) has no chat signing keys attached.
+pub const VELOCITY_FORWARDING_VERSION: u8 = 1;
+
+/// Length of the HMAC-SHA256 signature prefixing forwarded data.
+const SIGNATURE_LENGTH: usize = 32;
+
+/// The message BungeeCord servers disconnect players with when they connect
+/// without their details being forwarded.
+pub const BUNGEECORD_REQUIRED_MSG: &str =
+    "If you wish to use IP forwarding, please enable it in your BungeeCord config as well!";
+
+/// A player's details, as forwarded by a proxy.
+#[derive(Debug)]
+pub struct ForwardedPlayer {
+    /// The address the player connected to the proxy from.
+    pub address: IpAddr,
+    pub player: Player,
+}
+
+/// Checks the signature of the data sent in response to a
+/// [`VELOCITY_CHANNEL`] login plugin request, and decodes the player's details
+/// from it.
+pub fn decode_velocity_response(secret: &[u8], data: &[u8]) -> Result<ForwardedPlayer> {
+    ensure!(
+        data.len() > SIGNATURE_LENGTH,
+        "velocity forwarding data is too short to contain a signature"
+    );
+
+    let (signature, payload) = data.split_at(SIGNATURE_LENGTH);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)?;
+    mac.update(payload);
+    mac.verify_slice(signature)
+        .map_err(|_| anyhow!("velocity forwarding data has an invalid signature"))?;
+
+    let mut buf = Cursor::new(payload);
+
+    let version = VarInt::decode(&mut buf)?;
+    ensure!(
+        version.0 >= VELOCITY_FORWARDING_VERSION as i32,
+        "unsupported velocity forwarding version: {version}"
+    );
+
+    let address = String::decode(&mut buf)?;
+    let address = address
+        .parse()
+        .with_context(|| format!("velocity forwarded an invalid address: {address}"))?;
+
+    let uuid = Uuid::decode(&mut buf)?;
+    let username = String::decode(&mut buf)?;
+    let properties = Vec::<Property>::decode(&mut buf)?;
+
+    Ok(ForwardedPlayer {
+        address,
+        player: Player {
+            uuid,
+            username,
+            properties,
+        },
+    })
+}
+
+/// The handshake hostname of a player connecting through BungeeCord, which has
+/// their details appended to it.
+#[derive(Debug)]
+pub struct BungeeCordHandshake {
+    /// The hostname the player used to connect to the proxy.
+    pub hostname: String,
+    pub address: IpAddr,
+    pub uuid: Uuid,
+    pub properties: Vec<Property>,
+}
+
+/// Splits the details BungeeCord (and velocity in legacy mode) forward out of
+/// a handshake's server address, formatted as
+/// `hostname\0address\0uuid\0properties`, where the properties are json and
+/// optional.
+///
+/// Modded clients' markers (e.g. `\0FML3\0`) are passed along by the proxy
+/// too, either after the hostname or at the end, so are skipped over.
+///
+/// Returns `Ok(None)` if the server address doesn't contain forwarded
+/// details.
+pub fn decode_bungeecord_address(server_address: &str) -> Result<Option<BungeeCordHandshake>> {
+    let mut parts = server_address.split('\0');
+
+    let hostname = parts.next().unwrap_or_default().to_string();
+
+    let mut parts =
+        parts.filter(|part| !part.is_empty() && FmlVersion::from_marker(part).is_none());
+
+    let (Some(address), Some(uuid)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+
+    let address = address
+        .parse()
+        .with_context(|| format!("bungeecord forwarded an invalid address: {address}"))?;
+    let uuid = Uuid::parse_str(uuid)
+        .with_context(|| format!("bungeecord forwarded an invalid uuid: {uuid}"))?;
+
+    let properties = match parts.next() {
+        Some(json) => {
+            serde_json::from_str(json).context("bungeecord forwarded invalid profile properties")?
+        }
+        None => vec![],
+    };
+
+    Ok(Some(BungeeCordHandshake {
+        hostname,
+        address,
+        uuid,
+        properties,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"hunter2";
+    const IP: &str = "192.0.2.7";
+    const IPV6: &str = "2001:db8::1";
+    const UUID: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+
+    fn velocity_payload(version: i32) -> Vec<u8> {
+        let mut payload = vec![];
+
+        VarInt(version).encode(&mut payload).unwrap();
+        IP.to_string().encode(&mut payload).unwrap();
+        Uuid::from_u128(0x1234).encode(&mut payload).unwrap();
+        "Notch".to_string().encode(&mut payload).unwrap();
+        vec![Property {
+            name: "textures".to_string(),
+            value: "e30=".to_string(),
+            signature: Some("c2ln".to_string()),
+        }]
+        .encode(&mut payload)
+        .unwrap();
+
+        payload
+    }
+
+    fn sign(secret: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(payload);
+
+        let mut data = mac.finalize().into_bytes().to_vec();
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn velocity_good_signature() {
+        let data = sign(SECRET, &velocity_payload(1));
+        let forwarded = decode_velocity_response(SECRET, &data).unwrap();
+
+        assert_eq!(forwarded.address, IP.parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.player.uuid, Uuid::from_u128(0x1234));
+        assert_eq!(forwarded.player.username, "Notch");
+        assert_eq!(forwarded.player.properties.len(), 1);
+        assert_eq!(forwarded.player.properties[0].name, "textures");
+    }
+
+    #[test]
+    fn velocity_bad_signature() {
+        let data = sign(b"not the secret", &velocity_payload(1));
+        assert!(decode_velocity_response(SECRET, &data).is_err());
+
+        let mut data = sign(SECRET, &velocity_payload(1));
+        *data.last_mut().unwrap() ^= 1;
+        assert!(decode_velocity_response(SECRET, &data).is_err());
+
+        assert!(decode_velocity_response(SECRET, &[0; SIGNATURE_LENGTH]).is_err());
+    }
+
+    #[test]
+    fn velocity_version_mismatch() {
+        let data = sign(SECRET, &velocity_payload(0));
+        let error = decode_velocity_response(SECRET, &data).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("unsupported velocity forwarding version"));
+    }
+
+    #[test]
+    fn bungeecord_plain() {
+        assert!(decode_bungeecord_address("mc.example.com")
+            .unwrap()
+            .is_none());
+
+        let forwarded = decode_bungeecord_address(&format!("mc.example.com\0{IP}\0{UUID}"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(forwarded.hostname, "mc.example.com");
+        assert_eq!(forwarded.address, IP.parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.uuid, Uuid::parse_str(UUID).unwrap());
+        assert!(forwarded.properties.is_empty());
+
+        let forwarded = decode_bungeecord_address(&format!(
+            "mc.example.com\0{IPV6}\0{UUID}\0[{{\"name\":\"textures\",\"value\":\"e30=\"}}]"
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(forwarded.address, IPV6.parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.properties[0].name, "textures");
+        assert_eq!(forwarded.properties[0].signature, None);
+    }
+
+    #[test]
+    fn bungeecord_fml_marked() {
+        for address in [
+            format!("mc.example.com\0FML3\0\0{IP}\0{UUID}"),
+            format!("mc.example.com\0{IP}\0{UUID}\0FML2\0"),
+            format!("mc.example.com\0{IP}\0{UUID}\0[]\0FORGE"),
+            format!("mc.example.com\0FML\0{IP}\0{UUID}"),
+        ] {
+            let forwarded = decode_bungeecord_address(&address).unwrap().unwrap();
+
+            assert_eq!(forwarded.hostname, "mc.example.com", "{address:?}");
+            assert_eq!(forwarded.address, IP.parse::<IpAddr>().unwrap());
+            assert_eq!(forwarded.uuid, Uuid::parse_str(UUID).unwrap());
+        }
+
+        // Modded clients connecting directly aren't forwarded.
+        assert!(decode_bungeecord_address("mc.example.com\0FML3\0")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn bungeecord_malformed() {
+        assert!(decode_bungeecord_address(&format!("mc.example.com\0not an ip\0{UUID}")).is_err());
+        assert!(decode_bungeecord_address(&format!("mc.example.com\0{IP}\0not a uuid")).is_err());
+        assert!(
+            decode_bungeecord_address(&format!("mc.example.com\0{IP}\0{UUID}\0{{oops")).is_err()
+        );
+    }
+}
@@ -92,12 +92,18 @@ impl Handler {
                         return Err(anyhow!("connection ended with error: {e:#}"));
                     }
 
-                    warn!("shouldn't be possible to be here!");
+                    // The server closed the connection itself.
+                    return Ok(());
                 },
                 // If a shutdown signal is received, return from `run`.
                 // This will result in the task terminating.
                 reason = self.shutdown.recv() => {
 
+                    let reason = match reason {
+                        Some(reason) => reason,
+                        None => self.connection.disconnect_msg().await,
+                    };
+
                     // let template = reason;
 
                     // let context = if let Ok(context) = Context::from_serialize(/*self.player.clone().unwrap_or_default()*/ Player::default()) { context } else { Context::new() };
@@ -113,11 +119,8 @@ impl Handler {
                     // debug!("Client connection from {} disconnected by server with reason: \"{disconnect_msg}\"", &self.connection.address);
                     debug!("Client connection from {} disconnected by server with reason: \"{reason}\"", &self.connection.address);
 
-                    //write disconnect packet using disconnect_msg:
-
-                    // let disconnect_packet = S2C
-
-                    // self.connection.write_packet().await?;
+                    self.connection.disconnect(Chat::new(reason)).await?;
+                    self.connection.flush().await?;
 
                     // return Ok(());
                 }
@@ -1,6 +1,19 @@
+pub mod auth;
+pub mod backend;
+pub mod bedrock;
+pub mod capture;
 pub mod config;
 pub mod connection;
+pub mod forwarding;
 pub mod handler;
+pub mod limbo;
+pub mod limits;
+pub mod metrics;
 pub mod player;
+pub mod proxy;
+pub mod proxy_protocol;
+pub mod query;
 pub mod server;
 pub mod shutdown;
+pub mod status_cache;
+pub mod vhost;
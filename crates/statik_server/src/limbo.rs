@@ -0,0 +1,349 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use statik_core::prelude::*;
+use statik_proto::s2c::play::{
+    S2CChunkDataAndUpdateLight, S2CPlayLogin, S2CSetDefaultSpawnPosition,
+    S2CSynchronizePlayerPosition,
+};
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+
+/// How often players in limbo are sent a keep alive packet. The client will
+/// disconnect itself if it hasn't received one in 20 seconds.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The name of both the only dimension and dimension type in the limbo world.
+const DIMENSION: &str = "minecraft:overworld";
+
+/// How tall the limbo world is, in blocks. Must be a multiple of 16.
+const WORLD_HEIGHT: i32 = 256;
+
+/// Where players are placed in the limbo world.
+const SPAWN: Position = Position { x: 0, y: 64, z: 0 };
+
+/// Every damage type the notchian client expects to be in the registry - it
+/// won't join a world without them all.
+const DAMAGE_TYPES: &[&str] = &[
+    "arrow",
+    "bad_respawn_point",
+    "cactus",
+    "cramming",
+    "dragon_breath",
+    "drown",
+    "dry_out",
+    "explosion",
+    "fall",
+    "falling_anvil",
+    "falling_block",
+    "falling_stalactite",
+    "fireball",
+    "fireworks",
+    "fly_into_wall",
+    "freeze",
+    "generic",
+    "generic_kill",
+    "hot_floor",
+    "in_fire",
+    "in_wall",
+    "indirect_magic",
+    "lava",
+    "lightning_bolt",
+    "magic",
+    "mob_attack",
+    "mob_attack_no_aggro",
+    "mob_projectile",
+    "on_fire",
+    "out_of_world",
+    "outside_border",
+    "player_attack",
+    "player_explosion",
+    "sonic_boom",
+    "stalagmite",
+    "starve",
+    "sting",
+    "sweet_berry_bush",
+    "thorns",
+    "thrown",
+    "trident",
+    "unattributed_fireball",
+    "wither",
+    "wither_skull",
+];
+
+/// Tracks a player being held in the limbo world while the real server
+/// starts.
+#[derive(Debug)]
+pub struct Limbo {
+    /// Ticks once a second, to update the countdown and send keep alives.
+    ticker: Interval,
+
+    /// When the countdown shown to the player ends.
+    ready_at: Instant,
+
+    /// When the last keep alive packet was sent to the client.
+    last_keep_alive: Instant,
+
+    /// The id of the last keep alive packet sent, if the client hasn't
+    /// responded to it yet.
+    pending_keep_alive: Option<i64>,
+}
+
+impl Limbo {
+    pub fn new(wait_time: Duration) -> Self {
+        let mut ticker = time::interval(Duration::from_secs(1));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let now = Instant::now();
+
+        Self {
+            ticker,
+            ready_at: now + wait_time,
+            last_keep_alive: now,
+            pending_keep_alive: None,
+        }
+    }
+
+    /// Waits until the next second of the countdown.
+    pub async fn tick(&mut self) {
+        self.ticker.tick().await;
+    }
+
+    /// How many whole seconds are left on the countdown (rounded up).
+    pub fn seconds_left(&self) -> u64 {
+        let left = self.ready_at.saturating_duration_since(Instant::now());
+        left.as_secs() + (left.subsec_nanos() > 0) as u64
+    }
+
+    /// Returns the id of the keep alive packet that should be sent now, or
+    /// `None` if it isn't time to send one yet.
+    ///
+    /// Errors if the client never responded to the previous keep alive.
+    pub fn next_keep_alive(&mut self) -> Result<Option<i64>> {
+        if self.last_keep_alive.elapsed() < KEEP_ALIVE_INTERVAL {
+            return Ok(None);
+        }
+
+        ensure!(
+            self.pending_keep_alive.is_none(),
+            "client did not respond to a keep alive within {}s",
+            KEEP_ALIVE_INTERVAL.as_secs()
+        );
+
+        // Like the notchian server, use the current time as the id.
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as i64);
+
+        self.last_keep_alive = Instant::now();
+        self.pending_keep_alive = Some(id);
+
+        Ok(Some(id))
+    }
+
+    /// Checks a keep alive response from the client against the last one
+    /// sent.
+    pub fn keep_alive_received(&mut self, id: i64) -> Result<()> {
+        ensure!(
+            self.pending_keep_alive == Some(id),
+            "client responded with an unexpected keep alive id: {id}"
+        );
+
+        self.pending_keep_alive = None;
+
+        Ok(())
+    }
+}
+
+/// The packet which moves the client into the limbo world.
+pub fn login_packet(max_players: i32) -> S2CPlayLogin {
+    S2CPlayLogin {
+        entity_id: 0,
+        is_hardcore: false,
+        // Spectators float, so don't fall into the void, and don't wait for
+        // the chunk they're in to load before leaving the loading screen.
+        game_mode: 3,
+        previous_game_mode: -1,
+        dimension_names: vec![DIMENSION.to_string()],
+        registry_codec: registry_codec(),
+        dimension_type: DIMENSION.to_string(),
+        dimension_name: DIMENSION.to_string(),
+        hashed_seed: 0,
+        max_players: VarInt(max_players),
+        view_distance: VarInt(2),
+        simulation_distance: VarInt(2),
+        reduced_debug_info: false,
+        enable_respawn_screen: false,
+        is_debug: false,
+        is_flat: true,
+        death_location: None,
+        portal_cooldown: VarInt(0),
+    }
+}
+
+pub fn spawn_position_packet() -> S2CSetDefaultSpawnPosition {
+    S2CSetDefaultSpawnPosition {
+        location: SPAWN,
+        angle: 0.0,
+    }
+}
+
+pub fn player_position_packet() -> S2CSynchronizePlayerPosition {
+    S2CSynchronizePlayerPosition {
+        x: SPAWN.x as f64 + 0.5,
+        y: SPAWN.y as f64,
+        z: SPAWN.z as f64 + 0.5,
+        yaw: 0.0,
+        pitch: 0.0,
+        flags: 0,
+        teleport_id: VarInt(1),
+    }
+}
+
+/// A chunk column with nothing but air in it.
+pub fn empty_chunk(chunk_x: i32, chunk_z: i32) -> Result<S2CChunkDataAndUpdateLight> {
+    let mut data = Vec::new();
+
+    for _ in 0..WORLD_HEIGHT / 16 {
+        // Number of non-air blocks.
+        0i16.encode(&mut data)?;
+
+        // Block states, as a single valued paletted container of air (0).
+        0u8.encode(&mut data)?;
+        VarInt(0).encode(&mut data)?;
+        VarInt(0).encode(&mut data)?;
+
+        // Biomes, as a single valued paletted container of the first biome in
+        // the registry.
+        0u8.encode(&mut data)?;
+        VarInt(0).encode(&mut data)?;
+        VarInt(0).encode(&mut data)?;
+    }
+
+    Ok(S2CChunkDataAndUpdateLight {
+        chunk_x,
+        chunk_z,
+        heightmaps: Compound::new(),
+        data,
+        block_entities: vec![],
+        sky_light_mask: vec![],
+        block_light_mask: vec![],
+        empty_sky_light_mask: vec![],
+        empty_block_light_mask: vec![],
+        sky_light_arrays: vec![],
+        block_light_arrays: vec![],
+    })
+}
+
+/// The registries the client needs to be sent before it can join a world,
+/// containing as few entries as it will accept.
+pub fn registry_codec() -> Compound {
+    let dimension_type = Compound::new()
+        .with("piglin_safe", false)
+        .with("has_raids", false)
+        .with("monster_spawn_light_level", 0)
+        .with("monster_spawn_block_light_limit", 0)
+        .with("natural", true)
+        .with("ambient_light", 1.0f32)
+        .with("infiniburn", "#minecraft:infiniburn_overworld")
+        .with("respawn_anchor_works", false)
+        .with("has_skylight", true)
+        .with("bed_works", false)
+        .with("effects", "minecraft:overworld")
+        .with("min_y", 0)
+        .with("height", WORLD_HEIGHT)
+        .with("logical_height", WORLD_HEIGHT)
+        .with("coordinate_scale", 1.0f64)
+        .with("ultrawarm", false)
+        .with("has_ceiling", false);
+
+    let biome = Compound::new()
+        .with("has_precipitation", false)
+        .with("temperature", 0.8f32)
+        .with("downfall", 0.4f32)
+        .with(
+            "effects",
+            Compound::new()
+                .with("sky_color", 7907327)
+                .with("water_fog_color", 329011)
+                .with("fog_color", 12638463)
+                .with("water_color", 4159204),
+        );
+
+    let chat_type = Compound::new()
+        .with(
+            "chat",
+            Compound::new()
+                .with("translation_key", "chat.type.text")
+                .with("parameters", vec!["sender".into(), "content".into()]),
+        )
+        .with(
+            "narration",
+            Compound::new()
+                .with("translation_key", "chat.type.text.narrate")
+                .with("parameters", vec!["sender".into(), "content".into()]),
+        );
+
+    let damage_types = DAMAGE_TYPES
+        .iter()
+        .map(|name| {
+            (
+                format!("minecraft:{name}"),
+                Compound::new()
+                    .with("message_id", *name)
+                    .with("scaling", "when_caused_by_living_non_player")
+                    .with("exhaustion", 0.1f32),
+            )
+        })
+        .collect();
+
+    Compound::new()
+        .with(
+            "minecraft:dimension_type",
+            registry(
+                "minecraft:dimension_type",
+                vec![(DIMENSION.to_string(), dimension_type)],
+            ),
+        )
+        .with(
+            "minecraft:worldgen/biome",
+            registry(
+                "minecraft:worldgen/biome",
+                vec![("minecraft:plains".to_string(), biome)],
+            ),
+        )
+        .with(
+            "minecraft:chat_type",
+            registry(
+                "minecraft:chat_type",
+                vec![("minecraft:chat".to_string(), chat_type)],
+            ),
+        )
+        .with(
+            "minecraft:damage_type",
+            registry("minecraft:damage_type", damage_types),
+        )
+        .with(
+            "minecraft:trim_pattern",
+            registry("minecraft:trim_pattern", vec![]),
+        )
+        .with(
+            "minecraft:trim_material",
+            registry("minecraft:trim_material", vec![]),
+        )
+}
+
+/// A single registry, with ids given to each entry in order.
+fn registry(kind: &str, entries: Vec<(String, Compound)>) -> Compound {
+    let value = entries
+        .into_iter()
+        .enumerate()
+        .map(|(id, (name, element))| {
+            Compound::new()
+                .with("name", name)
+                .with("id", id as i32)
+                .with("element", element)
+                .into()
+        })
+        .collect::<Vec<Nbt>>();
+
+    Compound::new().with("type", kind).with("value", value)
+}
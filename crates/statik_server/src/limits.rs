@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{config::LimitsConfig, metrics::Metrics};
+
+/// The most addresses tracked at once. Past this, the one seen least recently
+/// is forgotten to make room.
+const MAX_TRACKED_ADDRESSES: usize = 4096;
+
+/// Limits on how many connections the server handles, and how often each
+/// address can connect.
+#[derive(Debug)]
+pub struct Limits {
+    /// One permit per connection that can be open at once.
+    connections: Arc<Semaphore>,
+
+    connection_rate: RateLimiter,
+    status_rate: RateLimiter,
+
+    /// How long clients have to send their handshake, and to finish a status
+    /// request after it.
+    pub handshake_timeout: Duration,
+
+    /// How long clients have to finish logging in after their handshake.
+    pub login_timeout: Duration,
+
+    /// How long a connection can go without sending anything.
+    pub read_timeout: Duration,
+
+    pub metrics: Metrics,
+}
+
+impl Limits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Self {
+            connections: Arc::new(Semaphore::new(config.max_connections)),
+            connection_rate: RateLimiter::new(config.connection_rate, config.connection_burst),
+            status_rate: RateLimiter::new(config.status_rate, config.status_burst),
+            handshake_timeout: Duration::from_secs(config.handshake_timeout),
+            login_timeout: Duration::from_secs(config.login_timeout),
+            read_timeout: Duration::from_secs(config.read_timeout),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Reserves a slot for a new connection, which is freed when the permit
+    /// is dropped. Returns `None` if the server is already full.
+    pub fn try_connect(&self) -> Option<OwnedSemaphorePermit> {
+        self.connections.clone().try_acquire_owned().ok()
+    }
+
+    /// Whether `ip` may open another connection.
+    pub fn check_connection_rate(&self, ip: IpAddr) -> bool {
+        self.connection_rate.check(ip)
+    }
+
+    /// Whether `ip` may request the server's status again.
+    pub fn check_status_rate(&self, ip: IpAddr) -> bool {
+        self.status_rate.check(ip)
+    }
+}
+
+/// A token bucket per address: each address can do something `burst` times
+/// in a row, then `rate` times a second after that.
+#[derive(Debug)]
+struct RateLimiter {
+    rate: f64,
+    burst: f64,
+
+    /// How long an empty bucket takes to fill back up, after which it's no
+    /// different to a new one.
+    refill_time: Duration,
+
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_address: HashMap<IpAddr, Bucket>,
+
+    /// Every tracked address by when it was last seen, oldest first.
+    by_age: BTreeSet<(Instant, IpAddr)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: f64, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+
+        let refill_time = match rate > 0.0 {
+            true => Duration::try_from_secs_f64(burst / rate).unwrap_or(Duration::MAX),
+            false => Duration::ZERO,
+        };
+
+        Self {
+            rate,
+            burst,
+            refill_time,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Takes a token from `ip`'s bucket, returning false if it's empty.
+    fn check(&self, ip: IpAddr) -> bool {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> bool {
+        // A rate of 0 (or less) disables the limit.
+        if self.rate <= 0.0 {
+            return true;
+        }
+
+        let ip = limited_address(ip);
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let Buckets { by_address, by_age } = &mut *buckets;
+
+        // Buckets that have had time to fill back up can be forgotten.
+        while let Some(&(last_seen, oldest)) = by_age.first() {
+            if now.saturating_duration_since(last_seen) < self.refill_time {
+                break;
+            }
+
+            by_age.pop_first();
+            by_address.remove(&oldest);
+        }
+
+        if !by_address.contains_key(&ip) {
+            if by_address.len() >= MAX_TRACKED_ADDRESSES {
+                if let Some((_, oldest)) = by_age.pop_first() {
+                    by_address.remove(&oldest);
+                }
+            }
+
+            by_address.insert(
+                ip,
+                Bucket {
+                    tokens: self.burst,
+                    last_refill: now,
+                },
+            );
+        }
+
+        let bucket = by_address.get_mut(&ip).unwrap();
+
+        by_age.remove(&(bucket.last_refill, ip));
+        let tokens = self.refill(bucket, now);
+        by_age.insert((bucket.last_refill, ip));
+
+        if tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens = tokens - 1.0;
+
+        true
+    }
+
+    /// Adds the tokens earned since the bucket was last refilled.
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        bucket.tokens
+    }
+}
+
+/// The address `ip` is rate limited as. IPv6 clients are usually given a
+/// whole /64 to themselves, so are limited by that rather than by each of
+/// its addresses.
+fn limited_address(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u64::MAX as u128))),
+        },
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn limits_after_burst() {
+        let limiter = RateLimiter::new(1.0, 3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(ip("192.0.2.1"), now));
+        }
+
+        assert!(!limiter.check_at(ip("192.0.2.1"), now));
+        assert!(limiter.check_at(ip("192.0.2.2"), now));
+
+        // One token a second.
+        assert!(limiter.check_at(ip("192.0.2.1"), now + Duration::from_secs(1)));
+        assert!(!limiter.check_at(ip("192.0.2.1"), now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn disabled_with_no_rate() {
+        let limiter = RateLimiter::new(0.0, 1);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(limiter.check_at(ip("192.0.2.1"), now));
+        }
+    }
+
+    #[test]
+    fn limits_ipv6_by_prefix() {
+        let limiter = RateLimiter::new(1.0, 1);
+        let now = Instant::now();
+
+        assert!(limiter.check_at(ip("2001:db8:0:1::1"), now));
+        assert!(!limiter.check_at(ip("2001:db8:0:1::2"), now));
+        assert!(!limiter.check_at(ip("2001:db8:0:1:ffff:ffff:ffff:ffff"), now));
+        assert!(limiter.check_at(ip("2001:db8:0:2::1"), now));
+
+        // IPv4 clients of a dual stack listener are the same as IPv4 ones.
+        assert!(limiter.check_at(ip("192.0.2.1"), now));
+        assert!(!limiter.check_at(ip("::ffff:192.0.2.1"), now));
+    }
+
+    #[test]
+    fn forgets_refilled_buckets() {
+        let limiter = RateLimiter::new(1.0, 2);
+        let now = Instant::now();
+
+        for i in 0..100 {
+            assert!(limiter.check_at(IpAddr::from([192, 0, 2, i]), now));
+        }
+
+        assert!(limiter.check_at(ip("198.51.100.1"), now + Duration::from_secs(2)));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_address.len(), 1);
+        assert_eq!(buckets.by_age.len(), 1);
+    }
+
+    #[test]
+    fn tracks_a_limited_number_of_addresses() {
+        let limiter = RateLimiter::new(1.0, 1);
+        let now = Instant::now();
+
+        for i in 0..MAX_TRACKED_ADDRESSES as u32 + 100 {
+            let now = now + Duration::from_micros(i as u64);
+            assert!(limiter.check_at(IpAddr::from(i.to_be_bytes()), now));
+        }
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_address.len(), MAX_TRACKED_ADDRESSES);
+        assert_eq!(buckets.by_age.len(), MAX_TRACKED_ADDRESSES);
+
+        // The oldest were the ones forgotten.
+        assert!(!buckets
+            .by_address
+            .contains_key(&IpAddr::from([0, 0, 0, 99])));
+        assert!(buckets
+            .by_address
+            .contains_key(&IpAddr::from([0, 0, 0, 100])));
+    }
+}
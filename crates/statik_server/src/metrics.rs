@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use statik_core::prelude::*;
+
+/// Counters for what the server has been up to, shared by every connection.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Connections accepted and handled.
+    pub connections_accepted: AtomicU64,
+
+    /// Connections currently open.
+    pub connections_active: AtomicU64,
+
+    /// Connections turned away because `limits.max_connections` were already
+    /// open.
+    pub connections_over_limit: AtomicU64,
+
+    /// Connections turned away because their address connected too often.
+    pub connections_rate_limited: AtomicU64,
+
+    /// Status requests ignored because their address pinged too often.
+    pub status_rate_limited: AtomicU64,
+
+    /// Connections closed for taking too long to handshake or log in, or
+    /// for going quiet.
+    pub connections_timed_out: AtomicU64,
+}
+
+impl Metrics {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decrement(counter: &AtomicU64) {
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn log_summary(&self) {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        info!(
+            "Connections: {} accepted, {} active, {} over the connection limit, {} rate limited, \
+             {} timed out. Status requests: {} rate limited.",
+            get(&self.connections_accepted),
+            get(&self.connections_active),
+            get(&self.connections_over_limit),
+            get(&self.connections_rate_limited),
+            get(&self.connections_timed_out),
+            get(&self.status_rate_limited),
+        );
+    }
+}
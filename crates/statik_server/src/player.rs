@@ -1,5 +1,7 @@
+use md5::{Digest, Md5};
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use statik_proto::s2c::login::{GameProfile, Property};
+use uuid::{Builder, Uuid};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Player {
@@ -13,6 +15,10 @@ pub struct Player {
     /// The username of an account as sent in the initial login packet.
     /// Defaults to "\<NULL\>". Set when (or if) login sequence starts.
     pub username: String,
+
+    /// The properties of the player's profile (such as their skin), if it
+    /// has been resolved. Empty by default.
+    pub properties: Vec<Property>,
 }
 
 impl Default for Player {
@@ -20,12 +26,47 @@ impl Default for Player {
         Self {
             uuid: Default::default(),
             username: String::from("<NULL>"),
+            properties: vec![],
         }
     }
 }
 
 impl Player {
     pub fn new(uuid: Uuid, username: String) -> Self {
-        Self { uuid, username }
+        Self {
+            uuid,
+            username,
+            properties: vec![],
+        }
+    }
+
+    /// Creates a player with the same UUID an offline mode (not
+    /// authenticated) notchian server would give them: a version 3 UUID of
+    /// "OfflinePlayer:<username>".
+    pub fn offline(username: String) -> Self {
+        let hash = Md5::digest(format!("OfflinePlayer:{username}"));
+        let uuid = Builder::from_md5_bytes(hash.into()).into_uuid();
+
+        Self::new(uuid, username)
+    }
+
+    /// The player's profile, as sent to the client in
+    /// [`S2CLoginSuccess`](statik_proto::s2c::login::S2CLoginSuccess).
+    pub fn profile(&self) -> GameProfile {
+        GameProfile {
+            id: self.uuid,
+            name: self.username.clone(),
+            properties: self.properties.clone(),
+        }
+    }
+}
+
+impl From<GameProfile> for Player {
+    fn from(profile: GameProfile) -> Self {
+        Self {
+            uuid: profile.id,
+            username: profile.name,
+            properties: profile.properties,
+        }
     }
 }
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use statik_core::prelude::*;
+use tokio::{
+    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+
+/// How long to wait for the real server to accept a connection before giving
+/// up on forwarding the client to it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connects `client` to the real server at `address`, replaying the frame of
+/// the handshake they already sent statik (and anything sent after it), then
+/// copies data between the two until either side closes their connection.
+///
+/// # Returns
+///
+/// Returns how many bytes were sent from the client to the real server, and
+/// how many were sent back.
+pub async fn forward(
+    client: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    address: &str,
+    handshake: &[u8],
+    unread: &[u8],
+) -> Result<(u64, u64)> {
+    let mut backend = time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|_| anyhow!("timed out connecting to the real server at {address}"))?
+        .with_context(|| format!("failed to connect to the real server at {address}"))?;
+
+    backend.set_nodelay(true)?;
+
+    // The handshake was read without its length prefix, so it has to be
+    // framed again.
+    let mut replayed = Vec::with_capacity(handshake.len() + unread.len() + 3);
+    VarInt::from(handshake.len()).encode(&mut replayed)?;
+    replayed.extend_from_slice(handshake);
+    replayed.extend_from_slice(unread);
+
+    backend.write_all(&replayed).await?;
+
+    Ok(io::copy_bidirectional(client, &mut backend).await?)
+}
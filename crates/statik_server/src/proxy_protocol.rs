@@ -0,0 +1,637 @@
+//! Reads the [PROXY protocol](https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt)
+//! header load balancers (e.g. HAProxy, or most cloud TCP load balancers)
+//! send at the start of a connection, to find out who actually connected.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use statik_core::prelude::*;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    time,
+};
+
+/// Every version 1 (text) header starts with this.
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+
+/// The longest a version 1 header can be, including the trailing CRLF.
+const V1_MAX_LENGTH: usize = 107;
+
+/// Every version 2 (binary) header starts with this.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of the fixed part of a version 2 header.
+const V2_HEADER_LENGTH: usize = 16;
+
+/// How long a trusted source has to send its header.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A CRC-32c checksum of the whole header.
+pub const PP2_TYPE_CRC32C: u8 = 0x03;
+
+/// The host name the client connected to (e.g. from TLS SNI).
+pub const PP2_TYPE_AUTHORITY: u8 = 0x02;
+
+/// A single type-length-value field from a version 2 header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+/// The connection details sent by the load balancer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// The address the client connected from. `None` for health checks sent
+    /// by the load balancer itself, or connections it couldn't describe.
+    pub source: Option<SocketAddr>,
+
+    /// The address the client connected to.
+    pub destination: Option<SocketAddr>,
+
+    /// Any extra fields (only sent in version 2 headers).
+    pub tlvs: Vec<Tlv>,
+}
+
+impl ProxyHeader {
+    /// The host name the client connected to, if the load balancer sent it.
+    pub fn authority(&self) -> Option<&str> {
+        self.tlvs
+            .iter()
+            .find(|tlv| tlv.kind == PP2_TYPE_AUTHORITY)
+            .and_then(|tlv| std::str::from_utf8(&tlv.value).ok())
+    }
+}
+
+/// An address (or range of addresses, in CIDR notation) that connections
+/// are trusted to send a PROXY protocol header from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedSource {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedSource {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual stack listener show up as mapped addresses.
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+
+        match (self.address, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+
+        let address = address
+            .parse::<IpAddr>()
+            .with_context(|| format!("invalid trusted source address \"{s}\""))?;
+
+        let max_prefix = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .with_context(|| format!("invalid trusted source prefix length in \"{s}\""))?,
+            None => max_prefix,
+        };
+
+        Ok(Self { address, prefix })
+    }
+}
+
+/// Whether the first `prefix` bits of `network` and `ip` are the same.
+fn prefix_matches(network: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let whole_bytes = prefix as usize / 8;
+    let remaining_bits = prefix % 8;
+
+    if network[..whole_bytes] != ip[..whole_bytes] {
+        return false;
+    }
+
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = 0xff << (8 - remaining_bits);
+
+    network[whole_bytes] & mask == ip[whole_bytes] & mask
+}
+
+/// Finds out the real address of a connection from `peer`. If `peer` is
+/// trusted, its header is read from `stream` - otherwise it's taken to have
+/// connected directly.
+///
+/// Also returns anything read from `stream` after the header, which should be
+/// handled as if it had just been received.
+pub async fn resolve_address(
+    stream: &mut (impl AsyncRead + Unpin),
+    peer: SocketAddr,
+    trusted_sources: &[TrustedSource],
+) -> Result<(SocketAddr, BytesMut)> {
+    if !trusted_sources
+        .iter()
+        .any(|source| source.contains(peer.ip()))
+    {
+        return Ok((peer, BytesMut::new()));
+    }
+
+    let (header, unread) = time::timeout(HEADER_TIMEOUT, read_header(stream))
+        .await
+        .with_context(|| format!("{peer} didn't send a PROXY protocol header in time"))?
+        .with_context(|| format!("invalid PROXY protocol header from {peer}"))?;
+
+    trace!("PROXY protocol header from {peer}: {header:?}");
+
+    match header.source {
+        Some(source) => {
+            debug!("{source} connected through load balancer {peer}.");
+            Ok((source, unread))
+        }
+        // e.g. a health check from the load balancer itself.
+        None => Ok((peer, unread)),
+    }
+}
+
+/// Reads a version 1 or 2 header from the start of `stream`, along with
+/// anything that was read after it.
+pub async fn read_header(stream: &mut (impl AsyncRead + Unpin)) -> Result<(ProxyHeader, BytesMut)> {
+    // The shortest possible header ("PROXY UNKNOWN\r\n") is longer than the
+    // version 2 signature, so this never reads too much.
+    let mut start = [0; V2_SIGNATURE.len()];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        // Version 2 headers say how long they are, so are read exactly.
+        return Ok((read_v2(stream).await?, BytesMut::new()));
+    }
+
+    ensure!(
+        start.starts_with(V1_SIGNATURE),
+        "connection didn't start with a PROXY protocol header"
+    );
+
+    // Version 1 headers don't, so are read in whatever chunks they arrive in
+    // until the CRLF turns up.
+    let mut buf = BytesMut::with_capacity(V1_MAX_LENGTH);
+    buf.extend_from_slice(&start);
+
+    loop {
+        let searched = &buf[..buf.len().min(V1_MAX_LENGTH)];
+
+        if let Some(end) = searched.windows(2).position(|window| window == b"\r\n") {
+            let unread = buf.split_off(end + 2);
+            let header = parse_v1(std::str::from_utf8(&buf[..end])?)?;
+
+            return Ok((header, unread));
+        }
+
+        ensure!(
+            buf.len() < V1_MAX_LENGTH,
+            "PROXY protocol v1 header is longer than {V1_MAX_LENGTH} bytes"
+        );
+
+        ensure!(
+            stream.read_buf(&mut buf).await? != 0,
+            "connection closed partway through its PROXY protocol header"
+        );
+    }
+}
+
+/// Parses a version 1 header line, e.g. `PROXY TCP4 1.2.3.4 5.6.7.8 1234
+/// 25565`.
+fn parse_v1(line: &str) -> Result<ProxyHeader> {
+    let fields = line.split(' ').collect::<Vec<_>>();
+
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(ProxyHeader::default()),
+        ["PROXY", protocol @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] =>
+        {
+            let parse_ip = |ip: &str| -> Result<IpAddr> {
+                let ip = ip
+                    .parse::<IpAddr>()
+                    .with_context(|| format!("invalid address in PROXY protocol header: {ip:?}"))?;
+
+                ensure!(
+                    ip.is_ipv4() == (protocol == "TCP4"),
+                    "{ip} is the wrong kind of address for {protocol}"
+                );
+
+                Ok(ip)
+            };
+
+            let parse_port = |port: &str| -> Result<u16> {
+                port.parse()
+                    .with_context(|| format!("invalid port in PROXY protocol header: {port:?}"))
+            };
+
+            Ok(ProxyHeader {
+                source: Some(SocketAddr::new(parse_ip(source)?, parse_port(source_port)?)),
+                destination: Some(SocketAddr::new(
+                    parse_ip(destination)?,
+                    parse_port(destination_port)?,
+                )),
+                tlvs: vec![],
+            })
+        }
+        _ => bail!("invalid PROXY protocol v1 header: {line:?}"),
+    }
+}
+
+/// Reads the rest of a version 2 header, after its signature.
+async fn read_v2(stream: &mut (impl AsyncRead + Unpin)) -> Result<ProxyHeader> {
+    let mut fixed = [0; V2_HEADER_LENGTH - V2_SIGNATURE.len()];
+    stream.read_exact(&mut fixed).await?;
+
+    let [_version_command, _family, length @ ..] = fixed;
+
+    let mut body = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut body).await?;
+
+    let mut header = V2_SIGNATURE.to_vec();
+    header.extend_from_slice(&fixed);
+    header.extend_from_slice(&body);
+
+    parse_v2(&header)
+}
+
+/// Parses a whole version 2 header.
+fn parse_v2(header: &[u8]) -> Result<ProxyHeader> {
+    let version_command = header[12];
+    let family = header[13];
+    let body = &header[V2_HEADER_LENGTH..];
+
+    ensure!(
+        version_command >> 4 == 2,
+        "unsupported PROXY protocol version {}",
+        version_command >> 4
+    );
+
+    // LOCAL connections are made by the load balancer itself (e.g. health
+    // checks), so any addresses should be ignored.
+    let local = match version_command & 0x0f {
+        0x0 => true,
+        0x1 => false,
+        command => bail!("unsupported PROXY protocol v2 command {command:#x}"),
+    };
+
+    let (source, destination, address_length) = match family >> 4 {
+        // AF_INET
+        0x1 => {
+            ensure!(body.len() >= 12, "PROXY protocol v2 header is too short");
+
+            let ip = |at: usize| Ipv4Addr::from(<[u8; 4]>::try_from(&body[at..at + 4]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+
+            (
+                Some(SocketAddr::new(ip(0).into(), port(8))),
+                Some(SocketAddr::new(ip(4).into(), port(10))),
+                12,
+            )
+        }
+        // AF_INET6
+        0x2 => {
+            ensure!(body.len() >= 36, "PROXY protocol v2 header is too short");
+
+            let ip = |at: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&body[at..at + 16]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+
+            (
+                Some(SocketAddr::new(ip(0).into(), port(32))),
+                Some(SocketAddr::new(ip(16).into(), port(34))),
+                36,
+            )
+        }
+        // AF_UNIX addresses can't be represented, so are skipped.
+        0x3 => {
+            ensure!(body.len() >= 216, "PROXY protocol v2 header is too short");
+            (None, None, 216)
+        }
+        // AF_UNSPEC
+        _ => (None, None, 0),
+    };
+
+    let tlvs_at = V2_HEADER_LENGTH + address_length;
+    let mut tlvs = Vec::new();
+
+    for (offset, tlv) in parse_tlvs(&header[tlvs_at..])? {
+        if tlv.kind == PP2_TYPE_CRC32C {
+            verify_crc32c(header, tlvs_at + offset, &tlv)?;
+        }
+
+        tlvs.push(tlv);
+    }
+
+    if local {
+        return Ok(ProxyHeader {
+            tlvs,
+            ..Default::default()
+        });
+    }
+
+    Ok(ProxyHeader {
+        source,
+        destination,
+        tlvs,
+    })
+}
+
+/// Parses TLVs, along with the offset of each in `data`.
+fn parse_tlvs(data: &[u8]) -> Result<Vec<(usize, Tlv)>> {
+    let mut tlvs = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        ensure!(data.len() - offset >= 3, "truncated PROXY protocol TLV");
+
+        let kind = data[offset];
+        let length = u16::from_be_bytes([data[offset + 1], data[offset + 2]]) as usize;
+        let value_at = offset + 3;
+
+        ensure!(
+            data.len() >= value_at + length,
+            "PROXY protocol TLV {kind:#04x} is longer than the header"
+        );
+
+        tlvs.push((
+            offset,
+            Tlv {
+                kind,
+                value: data[value_at..value_at + length].to_vec(),
+            },
+        ));
+
+        offset = value_at + length;
+    }
+
+    Ok(tlvs)
+}
+
+/// Checks the header's CRC-32c checksum (in the TLV at `tlv_at`), which is
+/// calculated with the checksum itself set to zero.
+fn verify_crc32c(header: &[u8], tlv_at: usize, crc: &Tlv) -> Result<()> {
+    ensure!(
+        crc.value.len() == 4,
+        "invalid PROXY protocol CRC-32c length"
+    );
+
+    let expected = u32::from_be_bytes(crc.value[..].try_into()?);
+    let value_at = tlv_at + 3;
+
+    let mut zeroed = header.to_vec();
+    zeroed[value_at..value_at + 4].fill(0);
+
+    ensure!(
+        crc32c(&zeroed) == expected,
+        "PROXY protocol header failed its CRC-32c check"
+    );
+
+    Ok(())
+}
+
+/// CRC-32c (Castagnoli), bit by bit - headers are small, so a lookup table
+/// isn't worth it.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0x82f63b78 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_TCP4: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25565\r\n";
+    const V1_TCP6: &[u8] = b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 25565\r\n";
+    const V1_UNKNOWN: &[u8] = b"PROXY UNKNOWN\r\n";
+
+    /// A version 2 header with the given version/command and family bytes.
+    fn v2(version_command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[version_command, family]);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    fn v2_ipv4_body() -> Vec<u8> {
+        let mut body = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&25565u16.to_be_bytes());
+        body
+    }
+
+    /// A version 2 IPv4 header with a CRC-32c TLV, optionally with a wrong
+    /// checksum.
+    fn v2_with_crc(correct: bool) -> Vec<u8> {
+        let mut body = v2_ipv4_body();
+        body.extend_from_slice(&[PP2_TYPE_CRC32C, 0, 4, 0, 0, 0, 0]);
+
+        let mut header = v2(0x21, 0x11, &body);
+        let mut crc = crc32c(&header);
+
+        if !correct {
+            crc ^= 1;
+        }
+
+        let crc_at = header.len() - 4;
+        header[crc_at..].copy_from_slice(&crc.to_be_bytes());
+        header
+    }
+
+    async fn read(mut data: &[u8]) -> Result<(ProxyHeader, BytesMut)> {
+        read_header(&mut data).await
+    }
+
+    fn addr(addr: &str) -> Option<SocketAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4() {
+        let (header, unread) = read(V1_TCP4).await.unwrap();
+
+        assert_eq!(header.source, addr("192.0.2.1:56324"));
+        assert_eq!(header.destination, addr("198.51.100.1:25565"));
+        assert!(unread.is_empty());
+    }
+
+    #[tokio::test]
+    async fn v1_tcp6() {
+        let (header, _) = read(V1_TCP6).await.unwrap();
+
+        assert_eq!(header.source, addr("[2001:db8::1]:56324"));
+        assert_eq!(header.destination, addr("[2001:db8::2]:25565"));
+    }
+
+    #[tokio::test]
+    async fn v1_unknown() {
+        let (header, _) = read(V1_UNKNOWN).await.unwrap();
+        assert_eq!(header, ProxyHeader::default());
+    }
+
+    #[tokio::test]
+    async fn v1_keeps_data_after_header() {
+        let data = [V1_TCP4, b"\x10\x00minecraft"].concat();
+        let (_, unread) = read(&data).await.unwrap();
+
+        assert_eq!(&unread[..], b"\x10\x00minecraft");
+    }
+
+    #[tokio::test]
+    async fn v1_rejects_bad_headers() {
+        // Mismatched address family.
+        assert!(read(b"PROXY TCP4 2001:db8::1 2001:db8::2 1 2\r\n")
+            .await
+            .is_err());
+
+        // No CRLF within the maximum length, even with more data after it.
+        let long = [&b"PROXY TCP4 "[..], &[b'1'; V1_MAX_LENGTH], b"\r\n"].concat();
+        assert!(read(&long).await.is_err());
+
+        // Closed partway through.
+        assert!(read(&V1_TCP4[..30]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_ipv4() {
+        let (header, unread) = read(&v2(0x21, 0x11, &v2_ipv4_body())).await.unwrap();
+
+        assert_eq!(header.source, addr("192.0.2.1:56324"));
+        assert_eq!(header.destination, addr("198.51.100.1:25565"));
+        assert!(unread.is_empty());
+    }
+
+    #[tokio::test]
+    async fn v2_ipv6() {
+        let source: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let destination: Ipv6Addr = "2001:db8::2".parse().unwrap();
+
+        let mut body = [source.octets(), destination.octets()].concat();
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&25565u16.to_be_bytes());
+
+        let (header, _) = read(&v2(0x21, 0x21, &body)).await.unwrap();
+
+        assert_eq!(header.source, addr("[2001:db8::1]:56324"));
+        assert_eq!(header.destination, addr("[2001:db8::2]:25565"));
+    }
+
+    #[tokio::test]
+    async fn v2_local() {
+        // Addresses sent with LOCAL are ignored.
+        let (header, _) = read(&v2(0x20, 0x11, &v2_ipv4_body())).await.unwrap();
+        assert_eq!(header, ProxyHeader::default());
+
+        let (header, _) = read(&v2(0x20, 0x00, &[])).await.unwrap();
+        assert_eq!(header, ProxyHeader::default());
+    }
+
+    #[tokio::test]
+    async fn v2_authority() {
+        let mut body = v2_ipv4_body();
+        body.extend_from_slice(&[PP2_TYPE_AUTHORITY, 0, 11]);
+        body.extend_from_slice(b"example.com");
+
+        let (header, _) = read(&v2(0x21, 0x11, &body)).await.unwrap();
+        assert_eq!(header.authority(), Some("example.com"));
+    }
+
+    #[tokio::test]
+    async fn v2_rejects_unknown_commands_and_versions() {
+        assert!(read(&v2(0x22, 0x11, &v2_ipv4_body())).await.is_err());
+        assert!(read(&v2(0x2f, 0x11, &v2_ipv4_body())).await.is_err());
+        assert!(read(&v2(0x11, 0x11, &v2_ipv4_body())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_crc() {
+        assert!(read(&v2_with_crc(true)).await.is_ok());
+        assert!(read(&v2_with_crc(false)).await.is_err());
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xe3069283);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signature() {
+        assert!(read(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .is_err());
+
+        // The version 2 signature, one byte off.
+        let mut header = v2(0x21, 0x11, &v2_ipv4_body());
+        header[11] ^= 1;
+        assert!(read(&header).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn untrusted_source() {
+        let trusted = ["10.0.0.0/8".parse::<TrustedSource>().unwrap()];
+        let peer = "192.0.2.100:40000".parse().unwrap();
+
+        // Someone connecting directly can't claim to be anyone else, and
+        // nothing is read from them.
+        let mut stream = V1_TCP4;
+        let (address, unread) = resolve_address(&mut stream, peer, &trusted).await.unwrap();
+
+        assert_eq!(address, peer);
+        assert!(unread.is_empty());
+        assert_eq!(stream, V1_TCP4);
+    }
+
+    #[tokio::test]
+    async fn trusted_source() {
+        let trusted = ["10.0.0.0/8".parse::<TrustedSource>().unwrap()];
+        let peer = "10.1.2.3:40000".parse().unwrap();
+
+        let mut stream = V1_TCP4;
+        let (address, _) = resolve_address(&mut stream, peer, &trusted).await.unwrap();
+        assert_eq!(Some(address), addr("192.0.2.1:56324"));
+
+        // Health checks are from the load balancer itself.
+        let mut stream = V1_UNKNOWN;
+        let (address, _) = resolve_address(&mut stream, peer, &trusted).await.unwrap();
+        assert_eq!(address, peer);
+
+        // Trusted sources have to send a header.
+        let mut stream = &b"\x10\x00minecraft"[..];
+        assert!(resolve_address(&mut stream, peer, &trusted).await.is_err());
+    }
+}
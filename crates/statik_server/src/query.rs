@@ -0,0 +1,468 @@
+//! Answers [Query](https://wiki.vg/Query) requests - the GameSpy4 based UDP
+//! protocol enabled by `enable-query` on a notchian server.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use statik_core::prelude::*;
+use tokio::{
+    net::UdpSocket,
+    sync::RwLock,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::{config::ServerConfig, connection, shutdown::Shutdown, vhost};
+
+/// Every query request starts with these bytes.
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+
+/// Like the notchian server, challenge tokens are only valid for 30 seconds.
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(30);
+
+/// The constant padding sent at the start of a full stat response.
+const FULL_STAT_PADDING: &[u8] = b"splitnum\0\x80\0";
+
+/// The constant padding sent before the player list of a full stat response.
+const PLAYERS_PADDING: &[u8] = b"\x01player_\0\0";
+
+/// The challenge tokens handed out to clients, which they must send back with
+/// their stat requests - so a spoofed address can't be used to reflect
+/// (larger) stat responses at someone else.
+#[derive(Debug, Default)]
+struct Challenges {
+    tokens: HashMap<SocketAddr, (i32, Instant)>,
+}
+
+impl Challenges {
+    /// Hands out a new challenge token to `address`.
+    fn issue(&mut self, address: SocketAddr) -> i32 {
+        let token = rand::random::<i32>() & 0x7fffffff;
+
+        self.tokens.insert(address, (token, Instant::now()));
+
+        token
+    }
+
+    /// Whether `token` is the current token for `address`.
+    fn check(&self, address: SocketAddr, token: i32) -> bool {
+        matches!(
+            self.tokens.get(&address),
+            Some((expected, issued)) if *expected == token && issued.elapsed() < CHALLENGE_LIFETIME
+        )
+    }
+
+    /// Forgets any expired tokens.
+    fn rotate(&mut self) {
+        self.tokens
+            .retain(|_, (_, issued)| issued.elapsed() < CHALLENGE_LIFETIME);
+    }
+}
+
+/// A request from a query client.
+#[derive(Debug)]
+enum Request {
+    Handshake {
+        session_id: i32,
+    },
+    Stat {
+        session_id: i32,
+        token: i32,
+        /// Full stat requests are padded with 4 extra bytes.
+        full: bool,
+    },
+}
+
+impl Request {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 7 || packet[..2] != MAGIC {
+            return None;
+        }
+
+        // Only the lower 4 bits of each byte are used by the notchian server.
+        let session_id = i32::from_be_bytes(packet[3..7].try_into().ok()?) & 0x0f0f0f0f;
+
+        match packet[2] {
+            HANDSHAKE => Some(Self::Handshake { session_id }),
+            STAT if packet.len() >= 11 => Some(Self::Stat {
+                session_id,
+                token: i32::from_be_bytes(packet[7..11].try_into().ok()?),
+                full: packet.len() >= 15,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Answers query requests on `socket` until `shutdown` is received.
+pub async fn listen(socket: UdpSocket, config: Arc<RwLock<ServerConfig>>, mut shutdown: Shutdown) {
+    let mut challenges = Challenges::default();
+
+    let mut rotate = time::interval(CHALLENGE_LIFETIME);
+    rotate.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut buf = [0; 1500];
+
+    while !shutdown.is_shutdown() {
+        tokio::select! {
+            res = socket.recv_from(&mut buf) => {
+                let (length, address) = match res {
+                    Ok(res) => res,
+                    Err(e) => {
+                        error!("Failed to receive query packet: {e}");
+                        continue;
+                    }
+                };
+
+                let Some(request) = Request::parse(&buf[..length]) else {
+                    trace!("ignoring {length} byte query packet from {address}.");
+                    continue;
+                };
+
+                debug!("(↓) query request from {address}: {request:?}");
+
+                let response = match request {
+                    Request::Handshake { session_id } => {
+                        Ok(handshake_response(session_id, challenges.issue(address)))
+                    }
+                    Request::Stat { session_id, token, full } => {
+                        if !challenges.check(address, token) {
+                            debug!("{address} sent a query with an invalid challenge token.");
+                            continue;
+                        }
+
+                        let config = config.read().await;
+                        let host_ip = host_ip(&config.general.host, address).await;
+
+                        stat_response(session_id, full, &config, &host_ip)
+                    }
+                };
+
+                let res = match response {
+                    Ok(response) => socket.send_to(&response, address).await.map_err(Error::from),
+                    Err(e) => Err(e),
+                };
+
+                if let Err(e) = res {
+                    warn!("Failed to respond to query from {address}: {e:#}");
+                }
+            }
+            _ = rotate.tick() => challenges.rotate(),
+            _ = shutdown.recv() => {}
+        }
+    }
+}
+
+fn handshake_response(session_id: i32, token: i32) -> Vec<u8> {
+    let mut response = vec![HANDSHAKE];
+
+    response.extend_from_slice(&session_id.to_be_bytes());
+    // The token is sent as a null terminated decimal string.
+    response.extend_from_slice(token.to_string().as_bytes());
+    response.push(0);
+
+    response
+}
+
+/// The address query clients are told to connect to: `host` (statik's
+/// `general.host`), or if statik is listening on every address, the one it
+/// sends packets to `peer` from.
+async fn host_ip(host: &str, peer: SocketAddr) -> String {
+    let Ok(ip) = host.parse::<IpAddr>() else {
+        return host.to_string();
+    };
+
+    if !ip.is_unspecified() {
+        return ip.to_string();
+    }
+
+    let unspecified = match peer {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+
+    // Connecting a UDP socket doesn't send anything, but does pick the local
+    // address for the route to `peer`.
+    let local_ip = async {
+        let socket = UdpSocket::bind((unspecified, 0)).await?;
+        socket.connect(peer).await?;
+
+        socket.local_addr().map(|address| address.ip())
+    };
+
+    match local_ip.await {
+        Ok(ip) => ip.to_string(),
+        Err(e) => {
+            debug!("Couldn't find the local address for {peer}: {e}");
+            host.to_string()
+        }
+    }
+}
+
+/// Builds a basic or full stat response, from the same data as the status
+/// response sent to minecraft clients.
+///
+/// Queries don't say which hostname they were sent to, so the host profile
+/// used is the one matching `host_ip` - as if a player had connected to it.
+fn stat_response(
+    session_id: i32,
+    full: bool,
+    config: &ServerConfig,
+    host_ip: &str,
+) -> Result<Vec<u8>> {
+    let profile = vhost::find(&config.hosts, host_ip).map(|(index, _)| index);
+    let status = connection::status_response(config, profile)?;
+
+    let motd = status.description().text().to_string();
+    let online = status.players().online().to_string();
+    let max = status.players().max().to_string();
+
+    let mut response = vec![STAT];
+    response.extend_from_slice(&session_id.to_be_bytes());
+
+    if !full {
+        for field in [motd.as_str(), "SMP", &config.query.map, &online, &max] {
+            push_string(&mut response, field);
+        }
+
+        // Unlike everything else, the port is little endian.
+        response.extend_from_slice(&config.mc.port.to_le_bytes());
+        push_string(&mut response, host_ip);
+
+        return Ok(response);
+    }
+
+    response.extend_from_slice(FULL_STAT_PADDING);
+
+    let port = config.mc.port.to_string();
+
+    for (key, value) in [
+        ("hostname", motd.as_str()),
+        ("gametype", "SMP"),
+        ("game_id", "MINECRAFT"),
+        ("version", status.version().name()),
+        ("plugins", &config.query.plugins),
+        ("map", &config.query.map),
+        ("numplayers", &online),
+        ("maxplayers", &max),
+        ("hostport", &port),
+        ("hostip", host_ip),
+    ] {
+        push_string(&mut response, key);
+        push_string(&mut response, value);
+    }
+
+    response.push(0);
+    response.extend_from_slice(PLAYERS_PADDING);
+
+    for player in status.players().sample() {
+        push_string(&mut response, player.name());
+    }
+
+    response.push(0);
+
+    Ok(response)
+}
+
+/// Writes a null terminated string. Query strings are ISO-8859-1, so anything
+/// outside of it is replaced with a question mark.
+fn push_string(buf: &mut Vec<u8>, string: &str) {
+    buf.extend(
+        string
+            .chars()
+            .map(|c| u8::try_from(c).unwrap_or(b'?'))
+            .filter(|c| *c != 0),
+    );
+    buf.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HostConfig;
+
+    const SESSION_ID: i32 = 0x01020304;
+
+    const TOKEN: i32 = 9513307;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert!(matches!(
+            Request::parse(&[0xfe, 0xfd, 0x09, 0x01, 0x02, 0x03, 0x04]),
+            Some(Request::Handshake {
+                session_id: SESSION_ID
+            })
+        ));
+
+        // Only the lower 4 bits of each byte of the session id are kept.
+        assert!(matches!(
+            Request::parse(&[0xfe, 0xfd, 0x09, 0xff, 0xff, 0xff, 0xff]),
+            Some(Request::Handshake {
+                session_id: 0x0f0f0f0f
+            })
+        ));
+
+        let basic = [
+            0xfe, 0xfd, 0x00, 0x01, 0x02, 0x03, 0x04, 0x00, 0x91, 0x29, 0x5b,
+        ];
+        assert!(matches!(
+            Request::parse(&basic),
+            Some(Request::Stat {
+                session_id: SESSION_ID,
+                token: TOKEN,
+                full: false
+            })
+        ));
+
+        let full = [basic.as_slice(), &[0; 4]].concat();
+        assert!(matches!(
+            Request::parse(&full),
+            Some(Request::Stat {
+                session_id: SESSION_ID,
+                token: TOKEN,
+                full: true
+            })
+        ));
+    }
+
+    #[test]
+    fn ignores_other_packets() {
+        for packet in [
+            &[][..],
+            &[0xfe, 0xfd, 0x09, 0x01, 0x02, 0x03],
+            &[0xfd, 0xfe, 0x09, 0x01, 0x02, 0x03, 0x04],
+            &[0xfe, 0xfd, 0x05, 0x01, 0x02, 0x03, 0x04],
+            // A stat request without its challenge token.
+            &[0xfe, 0xfd, 0x00, 0x01, 0x02, 0x03, 0x04, 0x00, 0x91, 0x29],
+        ] {
+            assert!(Request::parse(packet).is_none(), "{packet:x?}");
+        }
+    }
+
+    #[test]
+    fn challenges() {
+        let mut challenges = Challenges::default();
+
+        let token = challenges.issue(address(1));
+
+        assert!(token >= 0);
+        assert!(challenges.check(address(1), token));
+        assert!(!challenges.check(address(1), token ^ 1));
+        assert!(!challenges.check(address(2), token));
+
+        // A new token replaces the old one.
+        let new_token = challenges.issue(address(1));
+        assert!(challenges.check(address(1), new_token));
+        assert_eq!(challenges.check(address(1), token), token == new_token);
+    }
+
+    #[test]
+    fn challenges_expire() {
+        let mut challenges = Challenges::default();
+
+        let token = challenges.issue(address(1));
+        challenges.issue(address(2));
+
+        challenges
+            .tokens
+            .insert(address(1), (token, Instant::now() - CHALLENGE_LIFETIME));
+
+        assert!(!challenges.check(address(1), token));
+
+        challenges.rotate();
+
+        assert!(!challenges.tokens.contains_key(&address(1)));
+        assert!(challenges.tokens.contains_key(&address(2)));
+    }
+
+    #[test]
+    fn handshake_encoding() {
+        assert_eq!(
+            handshake_response(SESSION_ID, TOKEN),
+            b"\x09\x01\x02\x03\x049513307\0"
+        );
+    }
+
+    #[test]
+    fn basic_stat_encoding() {
+        let response = stat_response(SESSION_ID, false, &ServerConfig::default(), "192.0.2.1");
+
+        assert_eq!(
+            response.unwrap(),
+            [
+                &b"\x00\x01\x02\x03\x04"[..],
+                b"A Statik server!\0SMP\0world\x000\x0020\0",
+                &25565u16.to_le_bytes(),
+                b"192.0.2.1\0",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn full_stat_encoding() {
+        let mut config = ServerConfig::default();
+        config.query.plugins = "Statik: Query".to_string();
+
+        let response = stat_response(SESSION_ID, true, &config, "192.0.2.1");
+
+        assert_eq!(
+            response.unwrap(),
+            [
+                &b"\x00\x01\x02\x03\x04"[..],
+                FULL_STAT_PADDING,
+                b"hostname\0A Statik server!\0",
+                b"gametype\0SMP\0",
+                b"game_id\0MINECRAFT\0",
+                format!("version\0{MINECRAFT_VERSION}\0").as_bytes(),
+                b"plugins\0Statik: Query\0",
+                b"map\0world\0",
+                b"numplayers\x000\0",
+                b"maxplayers\x0020\0",
+                b"hostport\x0025565\0",
+                b"hostip\x00192.0.2.1\0",
+                b"\0",
+                PLAYERS_PADDING,
+                b"\0",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn stat_uses_host_profile() {
+        let mut config = ServerConfig::default();
+        config.hosts.push(HostConfig {
+            hostnames: vec!["192.0.2.1".to_string()],
+            motd: Some("A Statik profile!".to_string()),
+            max_players: Some(5),
+            ..Default::default()
+        });
+
+        let response = stat_response(SESSION_ID, false, &config, "192.0.2.1").unwrap();
+        assert!(response[5..].starts_with(b"A Statik profile!\0SMP\0world\x000\x005\0"));
+
+        let response = stat_response(SESSION_ID, false, &config, "192.0.2.2").unwrap();
+        assert!(response[5..].starts_with(b"A Statik server!\0"));
+    }
+
+    #[tokio::test]
+    async fn reports_a_usable_host_ip() {
+        assert_eq!(host_ip("0.0.0.0", address(25565)).await, "127.0.0.1");
+        assert_eq!(host_ip("192.0.2.1", address(25565)).await, "192.0.2.1");
+        assert_eq!(
+            host_ip("mc.example.com", address(25565)).await,
+            "mc.example.com"
+        );
+    }
+}
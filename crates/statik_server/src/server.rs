@@ -1,19 +1,52 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use base64::prelude::{Engine as _, BASE64_STANDARD};
 use statik_core::prelude::*;
 use tokio::{
-    net::TcpListener,
+    net::{TcpListener, UdpSocket},
     select,
-    sync::{broadcast, mpsc, RwLock},
+    sync::{broadcast, mpsc, OwnedSemaphorePermit, RwLock},
 };
 
-use crate::{config::ServerConfig, connection::Connection, handler::Handler, shutdown::Shutdown};
+use crate::{
+    auth::ServerKey,
+    backend::Backends,
+    bedrock,
+    config::{ForwardingMode, ServerConfig},
+    connection::Connection,
+    handler::Handler,
+    limits::Limits,
+    metrics::Metrics,
+    proxy_protocol::{self, TrustedSource},
+    query,
+    shutdown::Shutdown,
+    status_cache::StatusCache,
+};
 
 pub struct Server {
-    /// Configuration for how the server should be run.
+    /// Configuration for how the server should be run. It's only read once
+    /// the server has started - nothing changes it while it's running.
     pub config: Arc<RwLock<ServerConfig>>,
 
+    /// Whether the real servers are up, as checked by [`backend::monitor`]
+    /// for `config.backend.address` and any host profile's backend address.
+    pub backends: Arc<Backends>,
+
+    /// Status responses which have already been built, one per host profile.
+    pub status_cache: Arc<StatusCache>,
+
+    /// The keypair used to authenticate players, if `config.auth.online_mode`
+    /// is enabled.
+    pub server_key: Option<Arc<ServerKey>>,
+
+    /// Load balancers whose connections start with a PROXY protocol header.
+    /// Empty unless `config.proxy_protocol.enabled` is set.
+    pub trusted_sources: Arc<[TrustedSource]>,
+
+    /// Connection limits and timeouts, set by `config.limits`, and the metrics
+    /// counting how often they were hit.
+    pub limits: Arc<Limits>,
+
     /// Minecraft TCP listener that the server will bind and accept minecraft
     /// client connections from. Set by the `config.general.host` and
     /// `config.mc.port` fields.
@@ -29,10 +62,11 @@ pub struct Server {
     /// function: the server is then responsible for gracefully shutting down
     /// active connections. When a connection task is spawned, it is passed
     /// a handle to the broadcast receiver. When a graceful shutdown is
-    /// initiated, a `String` value is sent via the broadcast::Sender. Each
-    /// active connection receives it, parses the template, reaches a safe
-    /// termination state, and disconnects the client, completing the tast.
-    pub notify_shutdown: broadcast::Sender<String>,
+    /// initiated, an `Option<String>` value is sent via the broadcast::Sender.
+    /// Each active connection receives it, parses the template (or uses its
+    /// profile's default if there isn't one), reaches a safe termination
+    /// state, and disconnects the client, completing the tast.
+    pub notify_shutdown: broadcast::Sender<Option<String>>,
 
     /// Used as part of the graceful shutdown process to wait for client
     /// connections to complete processing.
@@ -52,7 +86,7 @@ pub struct Server {
 impl Server {
     pub async fn new(
         mut config: ServerConfig,
-        notify_shutdown: broadcast::Sender<String>,
+        notify_shutdown: broadcast::Sender<Option<String>>,
         shutdown_complete_tx: mpsc::Sender<String>,
     ) -> Result<Self> {
         let mc_address = format!("{}:{}", config.general.host, config.mc.port);
@@ -61,20 +95,116 @@ impl Server {
         let mc_listener = TcpListener::bind(&mc_address).await?;
         let api_listener = TcpListener::bind(&api_address).await?;
 
-        config.mc.icon = if let Some(s) = config.mc.icon {
-            match tokio::fs::read(&s).await {
-                Ok(s) => Some(BASE64_STANDARD.encode(s)),
-                Err(e) => {
-                    warn!("could not read icon file \"{s}\", defaulting to no icon: {e}");
-                    None
+        config.mc.icon = match config.mc.icon {
+            Some(path) => load_icon(&path, "no icon").await,
+            None => None,
+        };
+
+        for host in &mut config.hosts {
+            host.icon = match host.icon.take() {
+                Some(path) => load_icon(&path, "the default icon").await,
+                None => None,
+            };
+        }
+
+        for host in &config.hosts {
+            if host.hostnames.is_empty() {
+                warn!("a host profile has no hostnames, so will never be used.");
+            }
+        }
+
+        ensure!(
+            config.forwarding.mode != ForwardingMode::Velocity
+                || !config.forwarding.secret.is_empty(),
+            "velocity forwarding is enabled without a forwarding secret, which would let anyone \
+             forge player details"
+        );
+
+        let server_key = match config.auth.online_mode {
+            true => {
+                if config.forwarding.mode != ForwardingMode::None {
+                    warn!(
+                        "online mode is ignored while player details are forwarded by a proxy - \
+                         the proxy should authenticate players instead."
+                    );
                 }
+
+                debug!("Generating the server's keypair for online mode.");
+
+                Some(Arc::new(ServerKey::generate()?))
             }
-        } else {
-            None
+            false => None,
+        };
+
+        let trusted_sources = match config.proxy_protocol.enabled {
+            true => {
+                let sources = config
+                    .proxy_protocol
+                    .trusted_sources
+                    .iter()
+                    .map(|source| source.parse())
+                    .collect::<Result<Vec<TrustedSource>>>()?;
+
+                if sources.is_empty() {
+                    warn!(
+                        "the PROXY protocol is enabled without any trusted sources, so will never \
+                         be used."
+                    );
+                }
+
+                sources
+            }
+            false => vec![],
         };
 
+        let backends = Arc::new(Backends::spawn(&config, &notify_shutdown)?);
+
+        let status_cache = Arc::new(StatusCache::default());
+
+        let bedrock_socket = match config.bedrock.enabled {
+            true => {
+                let address = format!("{}:{}", config.general.host, config.bedrock.port);
+                let socket = UdpSocket::bind(&address).await?;
+
+                info!("Answering bedrock pings on {address}.");
+
+                Some(socket)
+            }
+            false => None,
+        };
+
+        let query_socket = match config.query.enabled {
+            true => {
+                let address = format!("{}:{}", config.general.host, config.query.port);
+                let socket = UdpSocket::bind(&address).await?;
+
+                info!("Answering queries on {address}.");
+
+                Some(socket)
+            }
+            false => None,
+        };
+
+        let limits = Arc::new(Limits::new(&config.limits));
+
         let config = Arc::new(RwLock::new(config));
 
+        if let Some(socket) = query_socket {
+            tokio::spawn(query::listen(
+                socket,
+                config.clone(),
+                Shutdown::new(notify_shutdown.subscribe()),
+            ));
+        }
+
+        if let Some(socket) = bedrock_socket {
+            tokio::spawn(bedrock::listen(
+                socket,
+                config.clone(),
+                Shutdown::new(notify_shutdown.subscribe()),
+            ));
+        }
+
         info!(
             "Statik server is up! Broadcasting the mc server on {mc_address}, and the api server \
              on {api_address}."
@@ -82,6 +212,11 @@ impl Server {
 
         Ok(Self {
             config,
+            backends,
+            status_cache,
+            server_key,
+            trusted_sources: trusted_sources.into(),
+            limits,
             mc_listener,
             api_listener,
             notify_shutdown,
@@ -95,8 +230,10 @@ impl Server {
 
                 res = self.mc_listener.accept() => {
                     match res {
-                        Ok((stream, address)) => {
-                            info!("New mc connection from {}.", address);
+                        Ok((mut stream, peer)) => {
+                            let Some(permit) = self.connection_permit(peer) else {
+                                continue;
+                            };
 
                             let shutdown_complete_tx = self.shutdown_complete_tx.clone();
                             let shutdown = Shutdown::new(self.notify_shutdown.subscribe());
@@ -104,15 +241,55 @@ impl Server {
                             //replace this with shared config struct later
                             let config = self.config.clone();
                             let config2 = self.config.clone();
+                            let backends = self.backends.clone();
+                            let status_cache = self.status_cache.clone();
+                            let server_key = self.server_key.clone();
+                            let trusted_sources = self.trusted_sources.clone();
+                            let limits = self.limits.clone();
 
                             tokio::spawn(async move {
 
-                                if let Err(err) = Handler::new(config, Connection::new(config2, stream, address).await, shutdown, shutdown_complete_tx).await.run().await {
+                                let (address, unread) = match proxy_protocol::resolve_address(&mut stream, peer, &trusted_sources).await {
+                                    Ok(resolved) => resolved,
+                                    Err(err) => {
+                                        warn!("Dropping mc connection: {err:#}");
+                                        return;
+                                    }
+                                };
+
+                                // Behind a forwarding proxy every player shares its address, so
+                                // they're only limited once it has forwarded their own.
+                                let forwarded = config.read().await.forwarding.mode != ForwardingMode::None;
+
+                                if !forwarded && !check_connection_rate(&limits, address) {
+                                    return;
+                                }
+
+                                info!("New mc connection from {}.", address);
+
+                                // Writes are already batched, so there's nothing to gain from the OS
+                                // holding them back too.
+                                if let Err(err) = stream.set_nodelay(true) {
+                                    debug!("couldn't disable nagle's algorithm for {address}: {err}");
+                                }
+
+                                Metrics::increment(&limits.metrics.connections_active);
+
+                                let mut connection = Connection::new(config2, backends, status_cache, server_key, limits.clone(), stream, address).await;
+
+                                // Whatever the load balancer sent straight after its header.
+                                connection.buffer.extend_from_slice(&unread);
+
+                                if let Err(err) = Handler::new(config, connection, shutdown, shutdown_complete_tx).await.run().await {
                                     error!("Connection error: {err:#}");
                                 }
 
+                                Metrics::decrement(&limits.metrics.connections_active);
+
                                 info!("Connection with mc client {} ended.", address);
 
+                                drop(permit);
+
                             });
                         },
                         Err(err) => error!("Failed to accept mc connection: {:#}", anyhow!(err)),
@@ -120,22 +297,36 @@ impl Server {
                 }
                 res = self.api_listener.accept() => {
                     match res {
-                        Ok((_stream, address)) => {
-                            info!("New api connection from {}.", address);
+                        Ok((mut stream, peer)) => {
+                            let Some(permit) = self.connection_permit(peer) else {
+                                continue;
+                            };
 
-                            let _shutdown_complete_tx = self.shutdown_complete_tx.clone();
-                            let _shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+                            let trusted_sources = self.trusted_sources.clone();
+                            let limits = self.limits.clone();
 
-                            let _config = self.config.clone();
+                            tokio::spawn(async move {
 
-                            todo!()
+                                let (address, _unread) = match proxy_protocol::resolve_address(&mut stream, peer, &trusted_sources).await {
+                                    Ok(resolved) => resolved,
+                                    Err(err) => {
+                                        warn!("Dropping api connection: {err:#}");
+                                        return;
+                                    }
+                                };
 
-                            // tokio::spawn(async move {
+                                if !check_connection_rate(&limits, address) {
+                                    return;
+                                }
+
+                                info!("New api connection from {}.", address);
 
-                            //     //handler
-                            //     Handler::new(config, stream, shutdown, shutdown_complete_tx).await.run().await;
+                                //handler
+                                warn!("The api server isn't implemented yet, closing the connection from {address}.");
 
-                            // });
+                                drop(permit);
+
+                            });
                         },
                         Err(err) => error!("Failed to accept api connection: {:#}", anyhow!(err)),
                     }
@@ -144,25 +335,74 @@ impl Server {
         }
     }
 
+    /// Reserves one of the `config.limits.max_connections` slots for a newly
+    /// accepted connection from `peer`, which should be closed if there are
+    /// none left.
+    fn connection_permit(&self, peer: SocketAddr) -> Option<OwnedSemaphorePermit> {
+        let permit = self.limits.try_connect();
+
+        match permit {
+            Some(_) => Metrics::increment(&self.limits.metrics.connections_accepted),
+            None => {
+                Metrics::increment(&self.limits.metrics.connections_over_limit);
+                warn!("Closing the connection from {peer}: too many connections are open.");
+            }
+        }
+
+        permit
+    }
+
     /// Gracefully sends shutdown signals to all clients connected to the
-    /// server. Supply `None` to use the default disconnect message, or
-    /// `Some(my_disconnecting_reason)` to send clients a custom disconnect
-    /// message. the message will be parsed using the [`Tera`] templater.
+    /// server. Supply `None` to use the default disconnect message (of the
+    /// profile each client connected with), or `Some(my_disconnecting_reason)`
+    /// to send clients a custom disconnect message. the message will be parsed
+    /// using the [`Tera`] templater.
     pub async fn shutdown(&self, reason: Option<String>) -> Result<()> {
         info!("Shutting down the server...");
 
-        let template = match reason {
-            Some(template) => template,
-            None => self.config.read().await.mc.disconnect_msg.clone(),
-        };
+        match &reason {
+            Some(template) => debug!(
+                "sending shutdown notice to connected clients, using disconnect message template: \
+                 \"{template}\""
+            ),
+            None => debug!(
+                "sending shutdown notice to connected clients, using their default disconnect \
+                 messages."
+            ),
+        }
 
-        debug!(
-            "sending shutdown notice to connected clients, using disconnect message template: \
-             \"{template}\""
-        );
+        self.limits.metrics.log_summary();
 
-        self.notify_shutdown.send(template)?;
+        self.notify_shutdown.send(reason)?;
+
+        self.backends.shutdown().await;
 
         Ok(())
     }
 }
+
+/// Reads the icon at `path`, base64 encoded to be sent in status responses.
+/// If it can't be read, `fallback` (describing what is used instead) is
+/// logged.
+async fn load_icon(path: &str, fallback: &str) -> Option<String> {
+    match tokio::fs::read(path).await {
+        Ok(icon) => Some(BASE64_STANDARD.encode(icon)),
+        Err(e) => {
+            warn!("could not read icon file \"{path}\", defaulting to {fallback}: {e}");
+            None
+        }
+    }
+}
+
+/// Checks whether `address` is allowed to open another connection, logging it
+/// if not.
+fn check_connection_rate(limits: &Limits, address: SocketAddr) -> bool {
+    if limits.check_connection_rate(address.ip()) {
+        return true;
+    }
+
+    Metrics::increment(&limits.metrics.connections_rate_limited);
+    warn!("Closing the connection from {address}: it is connecting too often.");
+
+    false
+}
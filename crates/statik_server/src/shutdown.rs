@@ -16,12 +16,12 @@ pub struct Shutdown {
     is_shutdown: bool,
 
     /// The receive half of the channel used to listen for shutdown.
-    recv: broadcast::Receiver<String>,
+    recv: broadcast::Receiver<Option<String>>,
 }
 
 impl Shutdown {
     /// Create a new `Shutdown` backed by the given `broadcast::Receiver`.
-    pub(crate) fn new(recv: broadcast::Receiver<String>) -> Shutdown {
+    pub(crate) fn new(recv: broadcast::Receiver<Option<String>>) -> Shutdown {
         Shutdown {
             is_shutdown: false,
             recv,
@@ -33,10 +33,14 @@ impl Shutdown {
         self.is_shutdown
     }
 
-    /// Receive the shutdown notice, waiting if necessary.
-    pub(crate) async fn recv(&mut self) -> String {
-        // Cannot receive a "lag error" as only one value is ever sent.
-        let Ok(reason) = self.recv.recv().await else { unreachable!("theoretically this is unreachable.") };
+    /// Receive the shutdown notice, waiting if necessary. Returns the reason
+    /// clients should be disconnected with, or `None` if they should get their
+    /// profile's default disconnect message.
+    pub(crate) async fn recv(&mut self) -> Option<String> {
+        // Cannot receive a "lag error" as only one value is ever sent - but if
+        // the sender was dropped without sending anything, the server is gone
+        // anyway, so that's treated as a shutdown too.
+        let reason = self.recv.recv().await.unwrap_or_default();
 
         // Remember that the signal has been received.
         self.is_shutdown = true;
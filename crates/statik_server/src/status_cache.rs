@@ -0,0 +1,41 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use bytes::Bytes;
+
+/// What a status response depends on, besides the config (which doesn't
+/// change while the server is running).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StatusKey {
+    /// The index of the host profile the client connected with, if any.
+    pub host: Option<usize>,
+}
+
+/// Status responses which have already been built, serialized and framed, so
+/// answering a status request is just a matter of copying the bytes into the
+/// write buffer.
+///
+/// Status responses are never compressed or encrypted, so the same frame can
+/// be sent to every client with the same [`StatusKey`]. There's at most one
+/// per host profile, and as nothing else goes into them, they're never
+/// invalidated.
+#[derive(Debug, Default)]
+pub struct StatusCache {
+    responses: RwLock<HashMap<StatusKey, Bytes>>,
+}
+
+impl StatusCache {
+    pub fn get(&self, key: &StatusKey) -> Option<Bytes> {
+        self.responses
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .cloned()
+    }
+
+    pub fn insert(&self, key: StatusKey, response: Bytes) {
+        self.responses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, response);
+    }
+}
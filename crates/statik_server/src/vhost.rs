@@ -0,0 +1,114 @@
+use crate::config::{BackendConfig, HostConfig, ServerConfig};
+
+/// Finds the profile in `hosts` for clients connecting with `hostname`,
+/// returning its index and the pattern it matched.
+///
+/// Exact matches are preferred, then the most specific wildcard (so
+/// "*.play.example.com" beats "*.example.com"), then a catch-all "*".
+pub fn find<'a>(hosts: &'a [HostConfig], hostname: &str) -> Option<(usize, &'a str)> {
+    let hostname = normalize(hostname);
+
+    hosts
+        .iter()
+        .enumerate()
+        .flat_map(|(index, host)| host.hostnames.iter().map(move |pattern| (index, pattern)))
+        .filter_map(|(index, pattern)| {
+            specificity(pattern, &hostname).map(|rank| (rank, index, pattern.as_str()))
+        })
+        // The first profile listed wins ties.
+        .max_by_key(|&(rank, index, _)| (rank, std::cmp::Reverse(index)))
+        .map(|(_, index, pattern)| (index, pattern))
+}
+
+/// Hostnames are case insensitive, and may be sent fully qualified (with a
+/// trailing dot), e.g. when found through an SRV record.
+fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// How closely `pattern` matches `hostname` (higher is closer), or `None` if it
+/// doesn't match at all.
+fn specificity(pattern: &str, hostname: &str) -> Option<usize> {
+    let pattern = normalize(pattern);
+
+    if pattern == "*" {
+        return Some(0);
+    }
+
+    if let Some(suffix) = pattern.strip_prefix("*.") {
+        let subdomain = hostname.strip_suffix(suffix)?;
+
+        // Only whole labels match, and the wildcard has to match something.
+        return (subdomain.len() > 1 && subdomain.ends_with('.')).then_some(suffix.len() + 1);
+    }
+
+    (pattern == hostname).then_some(usize::MAX)
+}
+
+/// The settings used for a connection: those of the profile its hostname
+/// matched, falling back to the top level settings for anything the profile
+/// doesn't set (or if there was no profile).
+#[derive(Debug, Clone, Copy)]
+pub struct Profile<'a> {
+    config: &'a ServerConfig,
+    host: Option<&'a HostConfig>,
+}
+
+impl<'a> Profile<'a> {
+    /// The profile at `index` in `config.hosts`, or the top level settings if
+    /// `index` is `None`.
+    pub fn new(config: &'a ServerConfig, index: Option<usize>) -> Self {
+        Self {
+            config,
+            host: index.and_then(|index| config.hosts.get(index)),
+        }
+    }
+
+    pub fn motd(&self) -> &'a str {
+        self.host
+            .and_then(|host| host.motd.as_deref())
+            .unwrap_or(&self.config.mc.motd)
+    }
+
+    pub fn icon(&self) -> Option<&'a str> {
+        self.host
+            .and_then(|host| host.icon.as_deref())
+            .or(self.config.mc.icon.as_deref())
+    }
+
+    pub fn max_players(&self) -> i32 {
+        self.host
+            .and_then(|host| host.max_players)
+            .unwrap_or(self.config.mc.max_players)
+    }
+
+    pub fn version(&self) -> Option<&'a str> {
+        self.host
+            .and_then(|host| host.version.as_deref())
+            .or(self.config.mc.version.as_deref())
+    }
+
+    pub fn disconnect_msg(&self) -> &'a str {
+        self.host
+            .and_then(|host| host.disconnect_msg.as_deref())
+            .unwrap_or(&self.config.mc.disconnect_msg)
+    }
+
+    pub fn starting_msg(&self) -> &'a str {
+        self.host
+            .and_then(|host| host.starting_msg.as_deref())
+            .unwrap_or(&self.config.mc.starting_msg)
+    }
+
+    pub fn reconnect_msg(&self) -> &'a str {
+        self.host
+            .and_then(|host| host.reconnect_msg.as_deref())
+            .unwrap_or(&self.config.limbo.reconnect_msg)
+    }
+
+    pub fn backend(&self) -> &'a BackendConfig {
+        self.host
+            .and_then(|host| host.backend.as_ref())
+            .unwrap_or(&self.config.backend)
+    }
+}
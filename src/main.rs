@@ -2,11 +2,11 @@
 
 mod quit;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use statik_core::prelude::*;
-use statik_server::{config::ServerConfig, server::Server};
+use statik_server::{capture, config::ServerConfig, server::Server};
 use tokio::{
     select,
     sync::{broadcast, mpsc},
@@ -18,12 +18,36 @@ struct Cli {
     /// Sets a custom config file
     #[arg(short, long, value_name = "FILE")]
     config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the packets in a capture file (see the `capture` config section)
+    Capture {
+        /// The capture file to read
+        file: PathBuf,
+
+        /// Instead of printing the capture, sends the client's packets to the
+        /// server at this address ("host:port") and prints what it sends back
+        #[arg(long, value_name = "ADDRESS")]
+        replay: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    if let Some(Command::Capture { file, replay }) = cli.command {
+        return match replay {
+            Some(address) => capture::replay(&file, &address).await,
+            None => capture::print(&file).await,
+        };
+    }
+
     let config_path = cli.config.unwrap_or("statik.toml".into());
 
     let config = match tokio::fs::read_to_string(&config_path).await {
@@ -38,7 +62,7 @@ async fn main() -> Result<()> {
             }
         },
         Err(e) => {
-            if config_path == PathBuf::from("statik.toml") {
+            if config_path == Path::new("statik.toml") {
                 //will error if we don't have write permissions.
                 if let Err(e) = tokio::fs::write(
                     PathBuf::from("statik.toml"),
@@ -85,7 +109,7 @@ async fn main() -> Result<()> {
     // purpose. The call below ignores the receiver of the broadcast pair, and when
     // a receiver is needed, the subscribe() method on the sender is used to create
     // one.
-    let (notify_shutdown, mut _shutdown_rx) = broadcast::channel::<String>(1);
+    let (notify_shutdown, mut _shutdown_rx) = broadcast::channel::<Option<String>>(1);
     let (shutdown_complete_tx, mut _shutdown_complete_rx) = mpsc::channel(1);
 
     let mut server = Server::new(config, notify_shutdown, shutdown_complete_tx).await?;
@@ -125,7 +149,7 @@ async fn main() -> Result<()> {
             reason = _shutdown_rx.recv() => {
 
                 debug!("internal shutdown signal recieved.");
-                server.shutdown(Some(reason?)).await?;
+                server.shutdown(reason?).await?;
                 break;
             }
         }